@@ -0,0 +1,54 @@
+// Builds an asset pack from a directory:
+//   cargo run --bin packer -- <asset dir> <output pack> [--store] [--list]
+// Entry names keep the directory name as prefix, so "assets/404.png" is found
+// the same way in the pack as on disk.
+
+use std::{env, path::Path, process};
+
+use motor_game::game_motor::pack::{AssetPack, Compression, PackBuilder};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let flags: Vec<&String> = args.iter().filter(|arg| arg.starts_with("--")).collect();
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+
+    if paths.len() != 2 {
+        println!("Usage: packer <asset dir> <output pack> [--store] [--list]");
+        process::exit(1);
+    }
+
+    let (dir, output) = (paths[0], paths[1]);
+    let compression = if flags.iter().any(|flag| *flag == "--store") { Compression::None } else { Compression::Lzss };
+    let prefix = Path::new(dir)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut builder = PackBuilder::new();
+    let count = match builder.add_dir(dir, &prefix, compression) {
+        Ok(count) => count,
+        Err(error) => {
+            println!("Can't read {}: {}", dir, error);
+            process::exit(1);
+        }
+    };
+
+    if let Err(error) = builder.write(output) {
+        println!("Can't write {}: {}", output, error);
+        process::exit(1);
+    }
+    println!("Packed {} files into {}", count, output);
+
+    if flags.iter().any(|flag| *flag == "--list") {
+        match AssetPack::open(output) {
+            Ok(pack) => {
+                for name in pack.names() {
+                    if let Some(entry) = pack.entry(name) {
+                        println!("  {} {:?} {} -> {} bytes", name, entry.compression, entry.original_size, entry.stored_size);
+                    }
+                }
+            }
+            Err(error) => println!("Can't open {}: {}", output, error),
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use sfml::{graphics::{Rect, Sprite, Texture}, system::Vector2};
+use std::fmt;
+
+use sfml::{graphics::{Color, IntRect, RcSprite, Rect, Sprite, Texture}, system::Vector2};
+
+use super::atlas::AtlasRegion;
 
 #[derive(Clone)]
 pub enum AnimationType {
@@ -16,22 +20,91 @@ pub struct Animation{
     pub frame_duration_ms: f32,
     pub current_total_duration: f32,
     pub current_frame: u32,
+    // Frames are cut from this part of the texture instead of the whole texture (atlas regions)
+    pub region: Option<IntRect>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimationError {
+    DuplicateAnimation { animation: String },
+    MissingTexture { animation: String, texture_id: String },
+    ZeroSizeRect { animation: String, rect_size: (i32, i32) },
+    RectIndexOutOfRange { animation: String, range: (u32, u32), frame_count: u32 },
+    MissingReturnState { animation: String, return_state: String },
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimationError::DuplicateAnimation { animation } => write!(f, "Animation already exists: {}", animation),
+            AnimationError::MissingTexture { animation, texture_id } => {
+                write!(f, "Animation {} uses missing texture: {}", animation, texture_id)
+            }
+            AnimationError::ZeroSizeRect { animation, rect_size } => {
+                write!(f, "Animation {} has an empty rect: {}x{}", animation, rect_size.0, rect_size.1)
+            }
+            AnimationError::RectIndexOutOfRange { animation, range, frame_count } => write!(
+                f,
+                "Animation {} rect range {}..={} doesn't fit in {} frames",
+                animation, range.0, range.1, frame_count
+            ),
+            AnimationError::MissingReturnState { animation, return_state } => {
+                write!(f, "Animation {} returns to missing animation: {}", animation, return_state)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
 
+// Outgoing frame kept alive while the next animation fades in
+#[derive(Clone)]
+pub struct Crossfade{
+    pub outgoing: RcSprite,
+    pub base_color: Color,
+    pub duration_ms: f32,
+    pub elapsed_ms: f32,
 }
 
+impl Crossfade{
+    pub fn new(outgoing: RcSprite, base_color: Color, duration_ms: f32) -> Self{
+        Crossfade{
+            outgoing,
+            base_color,
+            duration_ms,
+            elapsed_ms: 0.0,
+        }
+    }
+
+    pub fn increment_duration(&mut self, duration: f32){
+        self.elapsed_ms += duration;
+    }
+
+    // 0.0 -> only outgoing frame visible, 1.0 -> only incoming frame visible
+    pub fn progress(&self)->f32{
+        if self.duration_ms <= 0.0{
+            return 1.0;
+        }
+        (self.elapsed_ms / self.duration_ms).clamp(0.0, 1.0)
+    }
 
+    pub fn is_finished(&self)->bool{
+        self.progress() >= 1.0
+    }
 
-fn flip_rect(rect: Rect<i32>, flip_x: bool, flip_y: bool)->Rect<i32>{
-    let mut new_rect = rect;
-    if flip_x{
-        new_rect.left += new_rect.width;
-        new_rect.width = -new_rect.width;
+    pub fn incoming_color(&self)->Color{
+        with_alpha(self.base_color, self.progress())
     }
-    if flip_y{
-        new_rect.top += new_rect.height;
-        new_rect.height = -new_rect.height;
+
+    pub fn outgoing_color(&self)->Color{
+        with_alpha(self.base_color, 1.0 - self.progress())
     }
-    new_rect
+}
+
+fn with_alpha(color: Color, factor: f32)->Color{
+    let mut color = color;
+    color.a = (color.a as f32 * factor).round() as u8;
+    color
 }
 
 impl Animation{
@@ -43,6 +116,7 @@ impl Animation{
             frame_duration_ms,
             current_frame: 0,
             current_total_duration: 0.0,
+            region: None,
         }
     }
 
@@ -54,6 +128,7 @@ impl Animation{
             frame_duration_ms,
             current_frame: 0,
             current_total_duration: 0.0,
+            region: None,
         }
     }
 
@@ -65,6 +140,7 @@ impl Animation{
             frame_duration_ms,
             current_frame: 0,
             current_total_duration: 0.0,
+            region: None,
         }
     }
 
@@ -76,6 +152,7 @@ impl Animation{
             frame_duration_ms: 0.0,
             current_frame: 0,
             current_total_duration: 0.0,
+            region: None,
         }
     }
 
@@ -87,6 +164,43 @@ impl Animation{
             frame_duration_ms: 0.0,
             current_frame: 0,
             current_total_duration: 0.0,
+            region: None,
+        }
+    }
+
+    // Uses an atlas region, the texture id becomes the region's page
+    pub fn with_region(mut self, region: &AtlasRegion) -> Self{
+        self.textute_id = region.texture_id.clone();
+        self.region = Some(region.rect);
+        self
+    }
+
+    // Size frames are cut from, the region when there is one
+    fn frame_area(&self, texture_size: Vector2<u32>)->Vector2<u32>{
+        match self.region{
+            Some(region) => Vector2::new(region.width.max(0) as u32, region.height.max(0) as u32),
+            None => texture_size,
+        }
+    }
+
+    // Frames per row and per column, None when not even one frame fits
+    fn grid_size(&self, texture_size: Vector2<u32>)->Option<(i32, i32)>{
+        let (rect_width, rect_height) = self.rect_size?;
+        if rect_width <= 0 || rect_height <= 0{
+            return None;
+        }
+        let texture_size = self.frame_area(texture_size);
+        let (rects_per_row, rects_per_column) = (texture_size.x as i32 / rect_width, texture_size.y as i32 / rect_height);
+        if rects_per_row == 0 || rects_per_column == 0{
+            return None;
+        }
+        Some((rects_per_row, rects_per_column))
+    }
+
+    fn offset_rect(&self, rect: Rect<i32>)->Rect<i32>{
+        match self.region{
+            Some(region) => Rect::new(rect.left + region.left, rect.top + region.top, rect.width, rect.height),
+            None => rect,
         }
     }
 
@@ -156,11 +270,14 @@ impl Animation{
             }
         }
         else{
-            let rect = self.rect_size.unwrap();
-            let (rect_width, rect_height) = (rect.0, rect.1);
-            let (texture_width, texture_height) = (texture_size.x, texture_size.y);
-            let (rects_per_row, rects_per_column) = (texture_width as i32 / rect_width, texture_height as i32 / rect_height);
-            if self.current_frame >= (rects_per_row as i32* rects_per_column -1) as u32{
+            let (rects_per_row, rects_per_column) = match self.grid_size(texture_size){
+                Some(grid) => grid,
+                None => {
+                    self.current_frame = 0;
+                    return true;
+                }
+            };
+            if self.current_frame >= (rects_per_row * rects_per_column - 1) as u32{
                 self.current_frame = 0;
                 return true;
             }
@@ -179,30 +296,22 @@ impl Animation{
         self.get_rect(self.current_frame, texture_size)
     }
     
-    pub fn get_current_rect_flipped(&self, texture_size:Vector2<u32>, flip_x: bool, flip_y: bool)->Option<Rect<i32>>{
-        let rect = self.get_current_rect(texture_size);
-        if rect.is_none(){
-            return None;
-        }
-        Some(flip_rect(rect.unwrap(), flip_x, flip_y))
-    }
-
     fn get_rect(&self, frame_index:u32, texture_size:Vector2<u32>)->Option<Rect<i32>>{
 
        if self.rect_size.is_none(){
-           return None;
+           return self.region;
        }
 
         let rect_size = self.rect_size.unwrap();
         let (rect_width, rect_height) = (rect_size.0, rect_size.1);
-        let (texture_width, texture_height) = (texture_size.x as i32, texture_size.y as i32);
-        let (rects_per_row, rects_per_column) = (texture_width / rect_width, texture_height / rect_height);
+        // Empty rects or rects bigger than the texture have no frames
+        let (rects_per_row, rects_per_column) = self.grid_size(texture_size)?;
         
         if self.get_rect_index_range().is_none(){
             let frame_index = frame_index as i32;
             let left = (frame_index % rects_per_row as i32) * rect_width ;
             let top = (frame_index / rects_per_row as i32) * rect_height;
-            Some(Rect::new(left as i32, top as i32, rect_width as i32, rect_height as i32))
+            Some(self.offset_rect(Rect::new(left as i32, top as i32, rect_width as i32, rect_height as i32)))
         }
         else{
             let (start, end) = self.get_rect_index_range().unwrap();
@@ -215,13 +324,48 @@ impl Animation{
             let rect_index = self.frame_index_mod(frame_index).unwrap() as i32;
             let left = (rect_index % rects_per_row as i32) * rect_width;
             let top = (rect_index / rects_per_row as i32) * rect_height;
-            Some(Rect::new(left as i32, top as i32, rect_width as i32, rect_height as i32))
+            Some(self.offset_rect(Rect::new(left as i32, top as i32, rect_width as i32, rect_height as i32)))
             
         }
     
 
     }
 
+    // Texture size is None when the texture isn't loaded
+    pub fn validate(&self, name: &str, texture_size: Option<Vector2<u32>>)->Vec<AnimationError>{
+        let mut errors = Vec::new();
+
+        let texture_size = match texture_size{
+            Some(size) => self.frame_area(size),
+            None => {
+                errors.push(AnimationError::MissingTexture{animation: name.to_string(), texture_id: self.textute_id.clone()});
+                return errors;
+            }
+        };
+
+        let (rect_width, rect_height) = match self.rect_size{
+            Some(rect_size) => rect_size,
+            None => return errors,
+        };
+
+        if rect_width <= 0 || rect_height <= 0{
+            errors.push(AnimationError::ZeroSizeRect{animation: name.to_string(), rect_size: (rect_width, rect_height)});
+            return errors;
+        }
+
+        let frame_count = (texture_size.x as i32 / rect_width) * (texture_size.y as i32 / rect_height);
+        let frame_count = frame_count.max(0) as u32;
+        let range = self.get_rect_index_range().unwrap_or((0, frame_count.saturating_sub(1)));
+
+        // get_rect rejects single frame and reversed ranges as well
+        let has_range = self.get_rect_index_range().is_some();
+        if frame_count == 0 || range.1 >= frame_count || (has_range && range.0 >= range.1){
+            errors.push(AnimationError::RectIndexOutOfRange{animation: name.to_string(), range, frame_count});
+        }
+
+        errors
+    }
+
     pub fn increment_loop(&mut self){
         match &mut self.type_{
             AnimationType::FiniteLoop{current_loop, loops, ..} => {
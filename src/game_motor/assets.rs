@@ -0,0 +1,725 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    marker::PhantomData,
+    ops::Deref,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use sfml::{
+    audio::SoundBuffer,
+    graphics::{IntRect, RcFont, RcTexture, Texture},
+    SfBox,
+};
+
+use super::{
+    atlas::{Atlas, AtlasRegion},
+    pack::{AssetPack, PackError},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetError {
+    // File couldn't be read (missing, no permission, ...)
+    File { path: String, reason: String },
+    // File was read but SFML couldn't decode it
+    Format { path: String },
+    NotFound { id: String },
+    // Pack entry is corrupt or its checksum doesn't match
+    Pack { path: String, reason: String },
+    // Unloading was refused because handles to the asset are still alive
+    InUse { id: String, handles: usize },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::File { path, reason } => write!(f, "Can't read {}: {}", path, reason),
+            AssetError::Format { path } => write!(f, "Can't decode {}", path),
+            AssetError::NotFound { id } => write!(f, "Asset not found: {}", id),
+            AssetError::Pack { path, reason } => write!(f, "Can't read {} from pack: {}", path, reason),
+            AssetError::InUse { id, handles } => write!(f, "Asset {} still has {} live handles", id, handles),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+// Typed reference to a loaded asset, the asset counts how many handles are alive
+pub struct Handle<A> {
+    id: Rc<String>,
+    _asset: PhantomData<A>,
+}
+
+impl<A> Handle<A> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl<A> Clone for Handle<A> {
+    fn clone(&self) -> Self {
+        Handle {
+            id: self.id.clone(),
+            _asset: PhantomData,
+        }
+    }
+}
+
+impl<A> fmt::Debug for Handle<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+impl<A> PartialEq for Handle<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+struct AssetEntry<S> {
+    asset: S,
+    path: String,
+    // Only assets read from a directory can be watched
+    disk_path: Option<PathBuf>,
+    id: Rc<String>,
+    modified: Option<SystemTime>,
+}
+
+impl<S> AssetEntry<S> {
+    fn new(id: &str, path: &str, disk_path: Option<PathBuf>, asset: S) -> Self {
+        let modified = disk_path.as_deref().and_then(modified_time);
+        AssetEntry {
+            asset,
+            path: path.to_string(),
+            disk_path,
+            id: Rc::new(id.to_string()),
+            modified,
+        }
+    }
+
+    fn handle<A>(&self) -> Handle<A> {
+        Handle {
+            id: self.id.clone(),
+            _asset: PhantomData,
+        }
+    }
+
+    fn is_changed_on_disk(&self) -> bool {
+        let modified = self.disk_path.as_deref().and_then(modified_time);
+        modified.is_some() && modified != self.modified
+    }
+
+    fn disk_path_str(&self) -> String {
+        self.disk_path.as_ref().map(|path| path.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    // The entry itself holds one reference
+    fn ref_count(&self) -> usize {
+        Rc::strong_count(&self.id) - 1
+    }
+}
+
+// Asset types the server can hand out through typed handles
+pub trait Asset: Sized {
+    fn get<'a>(server: &'a AssetServer, id: &str) -> Option<&'a Self>;
+}
+
+impl Asset for RcTexture {
+    fn get<'a>(server: &'a AssetServer, id: &str) -> Option<&'a Self> {
+        server.texture(id)
+    }
+}
+
+impl Asset for RcFont {
+    fn get<'a>(server: &'a AssetServer, id: &str) -> Option<&'a Self> {
+        server.font(id)
+    }
+}
+
+impl Asset for SoundBuffer {
+    fn get<'a>(server: &'a AssetServer, id: &str) -> Option<&'a Self> {
+        server.sound(id)
+    }
+}
+
+// Fonts loaded from memory need their bytes for as long as the font lives
+pub struct FontAsset {
+    font: RcFont,
+    data: Option<Vec<u8>>,
+}
+
+impl FontAsset {
+    // Bytes kept for the font, fonts reloaded from a file read it themselves
+    pub fn memory_usage(&self) -> usize {
+        self.data.as_ref().map_or(0, |data| data.len())
+    }
+}
+
+impl Deref for FontAsset {
+    type Target = RcFont;
+
+    fn deref(&self) -> &RcFont {
+        &self.font
+    }
+}
+
+// Decoding shared by the asset server and ResourceHolder, the path only goes into errors
+pub fn decode_texture(path: &str, data: &[u8]) -> Result<RcTexture, AssetError> {
+    let format_error = || AssetError::Format { path: path.to_string() };
+    let mut texture = RcTexture::new().ok_or_else(format_error)?;
+    texture.load_from_memory(data, IntRect::default()).map_err(|_| format_error())?;
+    Ok(texture)
+}
+
+pub fn decode_font(path: &str, data: Vec<u8>) -> Result<FontAsset, AssetError> {
+    // The bytes are moved into the asset next to the font, so they outlive it
+    match unsafe { RcFont::from_memory(&data) } {
+        Some(font) => Ok(FontAsset { font, data: Some(data) }),
+        None => Err(AssetError::Format { path: path.to_string() }),
+    }
+}
+
+pub fn decode_sound(path: &str, data: &[u8]) -> Result<SfBox<SoundBuffer>, AssetError> {
+    SoundBuffer::from_memory(data).map_err(|_| AssetError::Format { path: path.to_string() })
+}
+
+pub type SharedAssets = Rc<RefCell<AssetServer>>;
+
+// Where asset paths are looked up, a directory root or a pack file
+#[derive(Debug, Clone)]
+pub enum AssetSource {
+    Directory(PathBuf),
+    Pack(Arc<AssetPack>),
+}
+
+// Sources are searched in order, the first one that has the path wins
+#[derive(Debug, Clone)]
+pub struct AssetSources {
+    sources: Vec<AssetSource>,
+}
+
+impl AssetSources {
+    pub fn new() -> Self {
+        AssetSources {
+            sources: vec![AssetSource::Directory(PathBuf::from("."))],
+        }
+    }
+
+    pub fn list(&self) -> &[AssetSource] {
+        &self.sources
+    }
+
+    // Replaces the directories, mounted packs stay
+    pub fn set_root<P: AsRef<Path>>(&mut self, root: P) {
+        self.sources.retain(|source| matches!(source, AssetSource::Pack(_)));
+        self.sources.push(AssetSource::Directory(root.as_ref().to_path_buf()));
+    }
+
+    pub fn add_directory<P: AsRef<Path>>(&mut self, root: P) {
+        self.sources.push(AssetSource::Directory(root.as_ref().to_path_buf()));
+    }
+
+    // Packs are searched before directories mounted earlier
+    pub fn mount_pack<P: AsRef<Path>>(&mut self, path: P) -> Result<(), AssetError> {
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        let pack = AssetPack::open(path.as_ref()).map_err(|error| pack_error(&path_str, error))?;
+        self.sources.insert(0, AssetSource::Pack(Arc::new(pack)));
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.sources.clear();
+    }
+
+    // File path of the asset when it comes from a directory
+    pub fn disk_path(&self, path: &str) -> Option<PathBuf> {
+        for source in self.sources.iter() {
+            match source {
+                AssetSource::Pack(pack) => {
+                    if pack.contains(path) {
+                        return None;
+                    }
+                }
+                AssetSource::Directory(root) => {
+                    let full = root.join(path);
+                    if full.is_file() {
+                        return Some(full);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.sources.iter().any(|source| match source {
+            AssetSource::Pack(pack) => pack.contains(path),
+            AssetSource::Directory(root) => root.join(path).is_file(),
+        })
+    }
+
+    // Uncompressed size, used for load progress
+    pub fn size(&self, path: &str) -> Option<u64> {
+        for source in self.sources.iter() {
+            match source {
+                AssetSource::Pack(pack) => {
+                    if let Some(entry) = pack.entry(path) {
+                        return Some(entry.original_size);
+                    }
+                }
+                AssetSource::Directory(root) => {
+                    if let Ok(meta) = fs::metadata(root.join(path)) {
+                        if meta.is_file() {
+                            return Some(meta.len());
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+        for source in self.sources.iter() {
+            match source {
+                AssetSource::Pack(pack) => {
+                    if pack.contains(path) {
+                        return pack.read(path).map_err(|error| pack_error(path, error));
+                    }
+                }
+                AssetSource::Directory(root) => {
+                    let full = root.join(path);
+                    if full.is_file() {
+                        return fs::read(&full).map_err(|error| AssetError::File { path: path.to_string(), reason: error.to_string() });
+                    }
+                }
+            }
+        }
+        Err(AssetError::File { path: path.to_string(), reason: "not found in any asset source".to_string() })
+    }
+}
+
+impl Default for AssetSources {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn pack_error(path: &str, error: PackError) -> AssetError {
+    match error {
+        PackError::Io(error) => AssetError::File { path: path.to_string(), reason: error.to_string() },
+        error => AssetError::Pack { path: path.to_string(), reason: error.to_string() },
+    }
+}
+
+pub struct AssetServer {
+    textures: HashMap<String, AssetEntry<RcTexture>>,
+    fonts: HashMap<String, AssetEntry<FontAsset>>,
+    sounds: HashMap<String, AssetEntry<SfBox<SoundBuffer>>>,
+    failed: HashMap<String, AssetError>,
+    // User (usually a scene name) -> ids it looks assets up by, unload_unused keeps those
+    users: HashMap<String, HashSet<String>>,
+    atlases: HashMap<String, Atlas>,
+    sources: AssetSources,
+    empty_texture_path: String,
+    watch_interval_ms: Option<f32>,
+    watch_elapsed_ms: f32,
+}
+
+fn remove_unused<S>(entries: &mut HashMap<String, AssetEntry<S>>, in_use: &HashSet<String>) -> Vec<String> {
+    let unused: Vec<String> =
+        entries.iter().filter(|(id, entry)| entry.ref_count() == 0 && !in_use.contains(*id)).map(|(id, _)| id.clone()).collect();
+    for id in unused.iter() {
+        entries.remove(id);
+    }
+    unused
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+impl AssetServer {
+    pub fn new() -> Self {
+        AssetServer {
+            textures: HashMap::new(),
+            fonts: HashMap::new(),
+            sounds: HashMap::new(),
+            failed: HashMap::new(),
+            users: HashMap::new(),
+            atlases: HashMap::new(),
+            sources: AssetSources::new(),
+            empty_texture_path: "assets/404.png".to_string(),
+            watch_interval_ms: None,
+            watch_elapsed_ms: 0.0,
+        }
+    }
+
+    pub fn shared() -> SharedAssets {
+        Rc::new(RefCell::new(AssetServer::new()))
+    }
+
+    pub fn sources(&self) -> &AssetSources {
+        &self.sources
+    }
+
+    pub fn sources_mut(&mut self) -> &mut AssetSources {
+        &mut self.sources
+    }
+
+    // Asset paths are relative to the root, "." by default
+    pub fn set_asset_root<P: AsRef<Path>>(&mut self, root: P) {
+        self.sources.set_root(root);
+    }
+
+    pub fn mount_pack<P: AsRef<Path>>(&mut self, path: P) -> Result<(), AssetError> {
+        self.sources.mount_pack(path)
+    }
+
+    // Texture shown in place of missing ones, relative to the asset root
+    pub fn empty_texture_path(&self) -> &str {
+        &self.empty_texture_path
+    }
+
+    pub fn set_empty_texture_path(&mut self, path: &str) {
+        self.empty_texture_path = path.to_string();
+    }
+
+    // Keeps track of the outcome so failed assets can be queried later
+    fn record<A>(&mut self, id: &str, result: Result<Handle<A>, AssetError>) -> Result<Handle<A>, AssetError> {
+        match &result {
+            Ok(_) => {
+                self.failed.remove(id);
+            }
+            Err(error) => {
+                self.failed.insert(id.to_string(), error.clone());
+            }
+        }
+        result
+    }
+
+    pub fn load_texture(&mut self, id: &str, path: &str) -> Result<Handle<RcTexture>, AssetError> {
+        self.load_texture_custom(id, path, |texture| texture)
+    }
+
+    pub fn load_texture_custom(&mut self, id: &str, path: &str, manup: fn(RcTexture) -> RcTexture) -> Result<Handle<RcTexture>, AssetError> {
+        let result = self.sources.read(path).and_then(|data| {
+            let texture = decode_texture(path, &data)?;
+            let entry = AssetEntry::new(id, path, self.sources.disk_path(path), manup(texture));
+            let handle = entry.handle();
+            self.textures.insert(id.to_string(), entry);
+            Ok(handle)
+        });
+        self.record(id, result)
+    }
+
+    pub fn load_font(&mut self, id: &str, path: &str) -> Result<Handle<RcFont>, AssetError> {
+        match self.sources.read(path) {
+            Ok(data) => self.insert_font_from_memory(id, path, data),
+            Err(error) => self.record(id, Err(error)),
+        }
+    }
+
+    pub fn load_sound(&mut self, id: &str, path: &str) -> Result<Handle<SoundBuffer>, AssetError> {
+        let result = self.sources.read(path).and_then(|data| {
+            let sound = decode_sound(path, &data)?;
+            let entry = AssetEntry::new(id, path, self.sources.disk_path(path), sound);
+            let handle = entry.handle();
+            self.sounds.insert(id.to_string(), entry);
+            Ok(handle)
+        });
+        self.record(id, result)
+    }
+
+    // Adds an asset created elsewhere (e.g. by the background loader)
+    pub fn insert_texture(&mut self, id: &str, path: &str, texture: RcTexture) -> Handle<RcTexture> {
+        let entry = AssetEntry::new(id, path, self.sources.disk_path(path), texture);
+        let handle = entry.handle();
+        self.textures.insert(id.to_string(), entry);
+        self.failed.remove(id);
+        handle
+    }
+
+    pub fn insert_font_from_memory(&mut self, id: &str, path: &str, data: Vec<u8>) -> Result<Handle<RcFont>, AssetError> {
+        let result = decode_font(path, data).map(|font| {
+            let entry = AssetEntry::new(id, path, self.sources.disk_path(path), font);
+            let handle = entry.handle();
+            self.fonts.insert(id.to_string(), entry);
+            handle
+        });
+        self.record(id, result)
+    }
+
+    pub fn insert_sound(&mut self, id: &str, path: &str, sound: SfBox<SoundBuffer>) -> Handle<SoundBuffer> {
+        let entry = AssetEntry::new(id, path, self.sources.disk_path(path), sound);
+        let handle = entry.handle();
+        self.sounds.insert(id.to_string(), entry);
+        self.failed.remove(id);
+        handle
+    }
+
+    // Pages have to be inserted as textures already, see AtlasBuilder::build
+    pub fn insert_atlas(&mut self, atlas: Atlas) {
+        self.atlases.insert(atlas.name.clone(), atlas);
+    }
+
+    pub fn atlas(&self, name: &str) -> Option<&Atlas> {
+        self.atlases.get(name)
+    }
+
+    // Looks the region up in every atlas
+    pub fn atlas_region(&self, name: &str) -> Option<AtlasRegion> {
+        self.atlases.values().find_map(|atlas| atlas.region(name).cloned())
+    }
+
+    // Unloads the atlas with its page textures
+    pub fn remove_atlas(&mut self, name: &str) {
+        if let Some(atlas) = self.atlases.remove(name) {
+            for page in atlas.pages.iter() {
+                if let Err(error) = self.unload(page) {
+                    println!("Atlas {}: {}", name, error);
+                }
+            }
+        }
+    }
+
+    pub fn record_failure(&mut self, id: &str, error: AssetError) {
+        self.failed.insert(id.to_string(), error);
+    }
+
+    pub fn get<A: Asset>(&self, handle: &Handle<A>) -> Option<&A> {
+        A::get(self, handle.id())
+    }
+
+    pub fn texture(&self, id: &str) -> Option<&RcTexture> {
+        self.textures.get(id).map(|entry| &entry.asset)
+    }
+
+    // Changes like set_repeated show up in every sprite using the texture
+    pub fn texture_mut(&mut self, id: &str) -> Option<&mut RcTexture> {
+        self.textures.get_mut(id).map(|entry| &mut entry.asset)
+    }
+
+    pub fn font(&self, id: &str) -> Option<&RcFont> {
+        self.fonts.get(id).map(|entry| &entry.asset.font)
+    }
+
+    pub fn sound(&self, id: &str) -> Option<&SoundBuffer> {
+        self.sounds.get(id).map(|entry| &*entry.asset)
+    }
+
+    pub fn texture_handle(&self, id: &str) -> Result<Handle<RcTexture>, AssetError> {
+        self.textures.get(id).map(|entry| entry.handle()).ok_or_else(|| AssetError::NotFound { id: id.to_string() })
+    }
+
+    pub fn font_handle(&self, id: &str) -> Result<Handle<RcFont>, AssetError> {
+        self.fonts.get(id).map(|entry| entry.handle()).ok_or_else(|| AssetError::NotFound { id: id.to_string() })
+    }
+
+    pub fn sound_handle(&self, id: &str) -> Result<Handle<SoundBuffer>, AssetError> {
+        self.sounds.get(id).map(|entry| entry.handle()).ok_or_else(|| AssetError::NotFound { id: id.to_string() })
+    }
+
+    pub fn path(&self, id: &str) -> Option<&str> {
+        self.textures
+            .get(id)
+            .map(|entry| entry.path.as_str())
+            .or_else(|| self.fonts.get(id).map(|entry| entry.path.as_str()))
+            .or_else(|| self.sounds.get(id).map(|entry| entry.path.as_str()))
+    }
+
+    // Number of live handles to the asset, assets looked up by id aren't counted
+    pub fn ref_count(&self, id: &str) -> usize {
+        self.textures
+            .get(id)
+            .map(|entry| entry.ref_count())
+            .or_else(|| self.fonts.get(id).map(|entry| entry.ref_count()))
+            .or_else(|| self.sounds.get(id).map(|entry| entry.ref_count()))
+            .unwrap_or(0)
+    }
+
+    // Refused while handles to the asset are alive, they would point at nothing.
+    // Every kind using the id is unloaded, so handles of each kind count.
+    pub fn unload(&mut self, id: &str) -> Result<(), AssetError> {
+        let handles = self.textures.get(id).map_or(0, |entry| entry.ref_count())
+            + self.fonts.get(id).map_or(0, |entry| entry.ref_count())
+            + self.sounds.get(id).map_or(0, |entry| entry.ref_count());
+        if handles > 0 {
+            return Err(AssetError::InUse { id: id.to_string(), handles });
+        }
+        let removed = self.textures.remove(id).is_some() | self.fonts.remove(id).is_some() | self.sounds.remove(id).is_some();
+        match removed {
+            true => Ok(()),
+            false => Err(AssetError::NotFound { id: id.to_string() }),
+        }
+    }
+
+    // Replaces the ids the user looks assets up by. Scenes register what they draw, see Scene::register_asset_use,
+    // fonts and sounds used by id have to be registered by whoever uses them.
+    pub fn set_user(&mut self, user: &str, ids: HashSet<String>) {
+        self.users.insert(user.to_string(), ids);
+    }
+
+    pub fn remove_user(&mut self, user: &str) {
+        self.users.remove(user);
+    }
+
+    pub fn is_used_by_id(&self, id: &str) -> bool {
+        self.users.values().any(|ids| ids.contains(id))
+    }
+
+    // Drops every asset with no live handles that no registered user looks up by id, returns the dropped ids.
+    // Each kind is checked on its own, a texture and a sound can share an id.
+    pub fn unload_unused(&mut self) -> Vec<String> {
+        let in_use: HashSet<String> = self.users.values().flatten().cloned().collect();
+        let mut dropped = remove_unused(&mut self.textures, &in_use);
+        dropped.extend(remove_unused(&mut self.fonts, &in_use));
+        dropped.extend(remove_unused(&mut self.sounds, &in_use));
+        dropped
+    }
+
+    // Checks the files on disk every interval_ms and reloads the ones that changed
+    pub fn watch(&mut self, interval_ms: f32) {
+        self.watch_interval_ms = Some(interval_ms.max(0.0));
+        self.watch_elapsed_ms = 0.0;
+    }
+
+    pub fn unwatch(&mut self) {
+        self.watch_interval_ms = None;
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watch_interval_ms.is_some()
+    }
+
+    // Called every frame with the frame time, returns the ids reloaded this time
+    pub fn poll_changes(&mut self, elapsed_ms: f32) -> Vec<String> {
+        let interval_ms = match self.watch_interval_ms {
+            Some(interval_ms) => interval_ms,
+            None => return Vec::new(),
+        };
+
+        self.watch_elapsed_ms += elapsed_ms;
+        if self.watch_elapsed_ms < interval_ms {
+            return Vec::new();
+        }
+        self.watch_elapsed_ms = 0.0;
+        self.reload_changed()
+    }
+
+    // Reloads in place, so sprites keep pointing at the same texture.
+    // A file that fails to reload (e.g. still being written) keeps the old asset and is retried
+    pub fn reload_changed(&mut self) -> Vec<String> {
+        let mut reloaded: Vec<String> = Vec::new();
+        let mut failures: Vec<(String, AssetError)> = Vec::new();
+
+        for (id, entry) in self.textures.iter_mut().filter(|(_, e)| e.is_changed_on_disk()) {
+            let result = Texture::from_file(&entry.disk_path_str()).map(|mut texture| {
+                texture.set_smooth(entry.asset.is_smooth());
+                texture.set_repeated(entry.asset.is_repeated());
+                entry.asset.swap(&mut texture);
+            });
+            match result {
+                Ok(()) => {
+                    entry.modified = entry.disk_path.as_deref().and_then(modified_time);
+                    reloaded.push(id.clone());
+                }
+                Err(_) => failures.push((id.clone(), AssetError::Format { path: entry.path.clone() })),
+            }
+        }
+
+        // Fonts and sound buffers are replaced, texts and sounds have to pick them up again
+        for (id, entry) in self.fonts.iter_mut().filter(|(_, e)| e.is_changed_on_disk()) {
+            match RcFont::from_file(&entry.disk_path_str()) {
+                Some(font) => {
+                    entry.asset = FontAsset { font, data: None };
+                    entry.modified = entry.disk_path.as_deref().and_then(modified_time);
+                    reloaded.push(id.clone());
+                }
+                None => failures.push((id.clone(), AssetError::Format { path: entry.path.clone() })),
+            }
+        }
+
+        for (id, entry) in self.sounds.iter_mut().filter(|(_, e)| e.is_changed_on_disk()) {
+            match SoundBuffer::from_file(&entry.disk_path_str()) {
+                Ok(sound) => {
+                    entry.asset = sound;
+                    entry.modified = entry.disk_path.as_deref().and_then(modified_time);
+                    reloaded.push(id.clone());
+                }
+                Err(_) => failures.push((id.clone(), AssetError::Format { path: entry.path.clone() })),
+            }
+        }
+
+        for id in reloaded.iter() {
+            self.failed.remove(id);
+        }
+        for (id, error) in failures {
+            if !self.failed.contains_key(&id) {
+                println!("Failed to reload {}: {}", id, error);
+            }
+            self.failed.insert(id, error);
+        }
+        reloaded
+    }
+
+    pub fn failed_assets(&self) -> &HashMap<String, AssetError> {
+        &self.failed
+    }
+
+    pub fn has_failed(&self, id: &str) -> bool {
+        self.failed.contains_key(id)
+    }
+}
+
+impl Default for AssetServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(ids: &[&str]) -> HashMap<String, AssetEntry<u32>> {
+        ids.iter().map(|id| (id.to_string(), AssetEntry::new(id, id, None, 0))).collect()
+    }
+
+    #[test]
+    fn remove_unused_keeps_handles_and_ids_in_use() {
+        let mut textures = entries(&["shared", "hud", "old"]);
+        let mut sounds = entries(&["shared", "jump"]);
+        let in_use: HashSet<String> = ["hud".to_string()].into_iter().collect();
+        let sound_handle: Handle<SoundBuffer> = sounds["shared"].handle();
+
+        let mut dropped = remove_unused(&mut textures, &in_use);
+        dropped.sort();
+        assert_eq!(dropped, vec!["old".to_string(), "shared".to_string()]);
+        assert_eq!(textures.keys().collect::<Vec<_>>(), vec!["hud"]);
+
+        // The texture of the same id going away doesn't touch the sound
+        assert_eq!(remove_unused(&mut sounds, &in_use), vec!["jump".to_string()]);
+        assert!(sounds.contains_key("shared"));
+        drop(sound_handle);
+        assert_eq!(remove_unused(&mut sounds, &in_use), vec!["shared".to_string()]);
+    }
+
+    #[test]
+    fn ids_stay_in_use_while_any_user_registers_them() {
+        let mut server = AssetServer::new();
+        server.set_user("level", ["tiles".to_string(), "hero".to_string()].into_iter().collect());
+        server.set_user("menu", ["hero".to_string()].into_iter().collect());
+        assert!(server.is_used_by_id("hero") && server.is_used_by_id("tiles"));
+        server.set_user("level", HashSet::new());
+        assert!(!server.is_used_by_id("tiles"));
+        assert!(server.is_used_by_id("hero"));
+        server.remove_user("menu");
+        assert!(!server.is_used_by_id("hero"));
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::HashMap, fmt};
+
+use sfml::graphics::{Image, IntRect, RcTexture};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AtlasError {
+    // Image is bigger than a page, even alone
+    TooLarge { name: String, size: (u32, u32), page_size: (u32, u32) },
+    DuplicateName { name: String },
+    Asset(AssetError),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::TooLarge { name, size, page_size } => write!(
+                f,
+                "{} ({}x{}) doesn't fit in a {}x{} atlas page",
+                name, size.0, size.1, page_size.0, page_size.1
+            ),
+            AtlasError::DuplicateName { name } => write!(f, "Atlas image already added: {}", name),
+            AtlasError::Asset(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+// Where a packed image ended up
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasPlacement {
+    pub name: String,
+    pub page: usize,
+    pub rect: IntRect,
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    used_width: u32,
+}
+
+// Shelf packer, only deals with sizes so it works without a GPU.
+// Images are packed tallest first, each row (shelf) is as tall as its first image.
+#[derive(Debug, Clone)]
+pub struct AtlasPacker {
+    pub page_width: u32,
+    pub page_height: u32,
+    // Empty pixels around every image, avoids bleeding with smooth textures
+    pub padding: u32,
+}
+
+impl AtlasPacker {
+    pub fn new(page_width: u32, page_height: u32, padding: u32) -> Self {
+        AtlasPacker {
+            page_width,
+            page_height,
+            padding,
+        }
+    }
+
+    // Same input gives the same placements, a new page is started when one is full
+    pub fn pack(&self, sizes: &[(String, u32, u32)]) -> Result<Vec<AtlasPlacement>, AtlasError> {
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by(|a, b| {
+            let (_, aw, ah) = &sizes[*a];
+            let (_, bw, bh) = &sizes[*b];
+            bh.cmp(ah).then(bw.cmp(aw)).then(sizes[*a].0.cmp(&sizes[*b].0))
+        });
+
+        let mut pages: Vec<Vec<Shelf>> = Vec::new();
+        let mut placements: Vec<AtlasPlacement> = Vec::with_capacity(sizes.len());
+
+        for index in order {
+            let (name, width, height) = &sizes[index];
+            let padded_width = width + self.padding * 2;
+            let padded_height = height + self.padding * 2;
+            if padded_width > self.page_width || padded_height > self.page_height {
+                return Err(AtlasError::TooLarge {
+                    name: name.clone(),
+                    size: (*width, *height),
+                    page_size: (self.page_width, self.page_height),
+                });
+            }
+
+            let spot = pages.iter_mut().enumerate().find_map(|(page, shelves)| {
+                self.place(shelves, padded_width, padded_height).map(|(x, y)| (page, x, y))
+            });
+            let (page, x, y) = match spot {
+                Some(spot) => spot,
+                None => {
+                    pages.push(Vec::new());
+                    let page = pages.len() - 1;
+                    // Always fits, the size was checked above
+                    let (x, y) = self.place(&mut pages[page], padded_width, padded_height).unwrap_or((0, 0));
+                    (page, x, y)
+                }
+            };
+
+            placements.push(AtlasPlacement {
+                name: name.clone(),
+                page,
+                rect: IntRect::new(
+                    (x + self.padding) as i32,
+                    (y + self.padding) as i32,
+                    *width as i32,
+                    *height as i32,
+                ),
+            });
+        }
+
+        Ok(placements)
+    }
+
+    fn place(&self, shelves: &mut Vec<Shelf>, width: u32, height: u32) -> Option<(u32, u32)> {
+        for shelf in shelves.iter_mut() {
+            if height <= shelf.height && shelf.used_width + width <= self.page_width {
+                let x = shelf.used_width;
+                shelf.used_width += width;
+                return Some((x, shelf.y));
+            }
+        }
+
+        let y = shelves.last().map(|shelf| shelf.y + shelf.height).unwrap_or(0);
+        if y + height > self.page_height {
+            return None;
+        }
+        shelves.push(Shelf { y, height, used_width: width });
+        Some((0, y))
+    }
+}
+
+// Named sub-rect of an atlas page texture
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasRegion {
+    pub texture_id: String,
+    pub rect: IntRect,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Atlas {
+    pub name: String,
+    // Texture ids of the pages, "<atlas name>#<page>"
+    pub pages: Vec<String>,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+}
+
+// Collects images and uploads them as atlas pages into the asset server
+pub struct AtlasBuilder {
+    name: String,
+    packer: AtlasPacker,
+    smooth: bool,
+    images: Vec<(String, Image)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(name: &str, page_width: u32, page_height: u32) -> Self {
+        AtlasBuilder {
+            name: name.to_string(),
+            packer: AtlasPacker::new(page_width, page_height, 1),
+            smooth: false,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn padding(&mut self, padding: u32) -> &mut Self {
+        self.packer.padding = padding;
+        self
+    }
+
+    pub fn smooth(&mut self, smooth: bool) -> &mut Self {
+        self.smooth = smooth;
+        self
+    }
+
+    // Path is looked up in the asset server sources, like load_texture
+    pub fn add(&mut self, name: &str, path: &str, assets: &AssetServer) -> Result<(), AtlasError> {
+        let data = assets.sources().read(path).map_err(AtlasError::Asset)?;
+        let image = Image::from_memory(&data).ok_or_else(|| AtlasError::Asset(AssetError::Format { path: path.to_string() }))?;
+        self.add_image(name, image)
+    }
+
+    pub fn add_image(&mut self, name: &str, image: Image) -> Result<(), AtlasError> {
+        if self.images.iter().any(|(existing, _)| existing == name) {
+            return Err(AtlasError::DuplicateName { name: name.to_string() });
+        }
+        self.images.push((name.to_string(), image));
+        Ok(())
+    }
+
+    // Pages are added to the server as textures, the atlas is registered so regions can be looked up by name
+    pub fn build(self, assets: &mut AssetServer) -> Result<Atlas, AtlasError> {
+        let sizes: Vec<(String, u32, u32)> = self
+            .images
+            .iter()
+            .map(|(name, image)| (name.clone(), image.size().x, image.size().y))
+            .collect();
+        let placements = self.packer.pack(&sizes)?;
+
+        let page_count = placements.iter().map(|placement| placement.page + 1).max().unwrap_or(0);
+        let mut pages: Vec<Image> = (0..page_count)
+            .map(|_| Image::new(self.packer.page_width, self.packer.page_height))
+            .collect();
+
+        let images: HashMap<&String, &Image> = self.images.iter().map(|(name, image)| (name, image)).collect();
+        for placement in placements.iter() {
+            if let Some(image) = images.get(&placement.name) {
+                let rect = placement.rect;
+                pages[placement.page].copy_image(image, rect.left as u32, rect.top as u32, IntRect::default(), false);
+            }
+        }
+
+        let mut atlas = Atlas {
+            name: self.name.clone(),
+            ..Default::default()
+        };
+        for (index, page) in pages.iter().enumerate() {
+            let id = format!("{}#{}", self.name, index);
+            let format_error = || AtlasError::Asset(AssetError::Format { path: id.clone() });
+            let mut texture = RcTexture::new().ok_or_else(format_error)?;
+            texture.load_from_image(page, IntRect::default()).map_err(|_| format_error())?;
+            texture.set_smooth(self.smooth);
+            assets.insert_texture(&id, &id, texture);
+            atlas.pages.push(id);
+        }
+
+        for placement in placements {
+            let region = AtlasRegion {
+                texture_id: atlas.pages[placement.page].clone(),
+                rect: placement.rect,
+            };
+            atlas.regions.insert(placement.name, region);
+        }
+
+        assets.insert_atlas(atlas.clone());
+        Ok(atlas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(list: &[(&str, u32, u32)]) -> Vec<(String, u32, u32)> {
+        list.iter().map(|(name, width, height)| (name.to_string(), *width, *height)).collect()
+    }
+
+    fn padded(rect: IntRect, padding: i32) -> IntRect {
+        IntRect::new(rect.left - padding, rect.top - padding, rect.width + padding * 2, rect.height + padding * 2)
+    }
+
+    fn overlaps(a: IntRect, b: IntRect) -> bool {
+        a.left < b.left + b.width && b.left < a.left + a.width && a.top < b.top + b.height && b.top < a.top + a.height
+    }
+
+    #[test]
+    fn placements_do_not_overlap_and_keep_padding() {
+        let padding = 2;
+        let packer = AtlasPacker::new(256, 256, padding);
+        let input = sizes(&[("a", 64, 64), ("b", 30, 50), ("c", 100, 20), ("d", 64, 64), ("e", 10, 10), ("f", 120, 40)]);
+        let placements = packer.pack(&input).unwrap();
+        assert_eq!(placements.len(), input.len());
+
+        for (index, placement) in placements.iter().enumerate() {
+            let (_, width, height) = input.iter().find(|(name, ..)| *name == placement.name).unwrap();
+            assert_eq!((placement.rect.width, placement.rect.height), (*width as i32, *height as i32));
+
+            // Padding stays inside the page
+            let outer = padded(placement.rect, padding as i32);
+            assert!(outer.left >= 0 && outer.top >= 0);
+            assert!(outer.left + outer.width <= 256 && outer.top + outer.height <= 256);
+
+            // Padded rects don't overlap, so images are at least 2 * padding apart
+            for other in placements[index + 1..].iter().filter(|other| other.page == placement.page) {
+                assert!(!overlaps(outer, padded(other.rect, padding as i32)), "{} overlaps {}", placement.name, other.name);
+            }
+        }
+    }
+
+    #[test]
+    fn full_page_overflows_to_a_new_page() {
+        let packer = AtlasPacker::new(128, 128, 0);
+        let input = sizes(&[("a", 128, 64), ("b", 128, 64), ("c", 128, 64)]);
+        let placements = packer.pack(&input).unwrap();
+        let pages: Vec<usize> = placements.iter().map(|placement| placement.page).collect();
+        assert_eq!(pages, vec![0, 0, 1]);
+        assert_eq!(placements[2].rect, IntRect::new(0, 0, 128, 64));
+    }
+
+    #[test]
+    fn image_larger_than_a_page_is_an_error() {
+        let packer = AtlasPacker::new(128, 128, 1);
+        // Fits without the padding, not with it
+        let result = packer.pack(&sizes(&[("small", 16, 16), ("big", 127, 10)]));
+        assert_eq!(
+            result,
+            Err(AtlasError::TooLarge { name: "big".to_string(), size: (127, 10), page_size: (128, 128) })
+        );
+    }
+
+    #[test]
+    fn packing_is_deterministic() {
+        let packer = AtlasPacker::new(256, 256, 1);
+        let input = sizes(&[("a", 40, 40), ("b", 40, 40), ("c", 20, 60)]);
+        let mut reversed = input.clone();
+        reversed.reverse();
+        let mut first = packer.pack(&input).unwrap();
+        let mut second = packer.pack(&reversed).unwrap();
+        first.sort_by(|a, b| a.name.cmp(&b.name));
+        second.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use super::prelude::*;
+
+// Neighbour bits of a mask. Four neighbour rules only use the edges (N, E, S, W).
+pub const NORTH: u8 = 1;
+pub const NORTH_EAST: u8 = 2;
+pub const EAST: u8 = 4;
+pub const SOUTH_EAST: u8 = 8;
+pub const SOUTH: u8 = 16;
+pub const SOUTH_WEST: u8 = 32;
+pub const WEST: u8 = 64;
+pub const NORTH_WEST: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutotileMode {
+    // 16 tiles, picked by the 4 edge neighbours
+    FourNeighbour,
+    // 47 tiles, corners only count when both edges next to them are set
+    Blob,
+}
+
+// Corner bits without both of their edges make no visual difference, they are cleared
+pub fn reduce_blob_mask(mask: u8) -> u8 {
+    let mut reduced = mask & (NORTH | EAST | SOUTH | WEST);
+    for (corner, first, second) in [
+        (NORTH_EAST, NORTH, EAST),
+        (SOUTH_EAST, SOUTH, EAST),
+        (SOUTH_WEST, SOUTH, WEST),
+        (NORTH_WEST, NORTH, WEST),
+    ] {
+        if mask & corner != 0 && mask & first != 0 && mask & second != 0 {
+            reduced |= corner;
+        }
+    }
+    reduced
+}
+
+// The 47 distinct blob masks, ascending. The default blob layout puts them in this order.
+pub fn blob_masks() -> Vec<u8> {
+    let masks: HashSet<u8> = (0..=255u8).map(reduce_blob_mask).collect();
+    let mut masks: Vec<u8> = masks.into_iter().collect();
+    masks.sort();
+    masks
+}
+
+// Edge bits of a four neighbour mask packed into 0..16 (N=1, E=2, S=4, W=8)
+pub fn four_neighbour_index(mask: u8) -> u8 {
+    [NORTH, EAST, SOUTH, WEST]
+        .iter()
+        .enumerate()
+        .filter(|(_, bit)| mask & **bit != 0)
+        .fold(0, |index, (shift, _)| index | (1 << shift))
+}
+
+// Picks the tile of a terrain from its neighbours
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutotileRule {
+    pub name: String,
+    pub mode: AutotileMode,
+    // Reduced mask -> gid
+    pub tiles: HashMap<u8, u32>,
+    // Gids that count as this terrain without being retiled, e.g. hand placed variations
+    pub extra_members: HashSet<u32>,
+    // Outside the map counts as the same terrain, so borders don't get edges
+    pub connect_to_edges: bool,
+}
+
+impl AutotileRule {
+    pub fn new(name: &str, mode: AutotileMode) -> Self {
+        AutotileRule {
+            name: name.to_string(),
+            mode,
+            tiles: HashMap::new(),
+            extra_members: HashSet::new(),
+            connect_to_edges: true,
+        }
+    }
+
+    // Tiles are laid out by four_neighbour_index, first_gid + index
+    pub fn four_neighbour(name: &str, first_gid: u32) -> Self {
+        let mut rule = AutotileRule::new(name, AutotileMode::FourNeighbour);
+        for index in 0..16u8 {
+            let mask = [NORTH, EAST, SOUTH, WEST]
+                .iter()
+                .enumerate()
+                .filter(|(shift, _)| index & (1 << shift) != 0)
+                .fold(0, |mask, (_, bit)| mask | bit);
+            rule.tiles.insert(mask, first_gid + index as u32);
+        }
+        rule
+    }
+
+    // Tiles are laid out in blob_masks order, first_gid + index
+    pub fn blob(name: &str, first_gid: u32) -> Self {
+        let mut rule = AutotileRule::new(name, AutotileMode::Blob);
+        for (index, mask) in blob_masks().into_iter().enumerate() {
+            rule.tiles.insert(mask, first_gid + index as u32);
+        }
+        rule
+    }
+
+    // For tilesets laid out differently, the mask is reduced first
+    pub fn with_tile(mut self, mask: u8, gid: u32) -> Self {
+        let mask = self.reduce(mask);
+        self.tiles.insert(mask, gid);
+        self
+    }
+
+    pub fn with_member(mut self, gid: u32) -> Self {
+        self.extra_members.insert(gid);
+        self
+    }
+
+    pub fn reduce(&self, mask: u8) -> u8 {
+        match self.mode {
+            AutotileMode::FourNeighbour => mask & (NORTH | EAST | SOUTH | WEST),
+            AutotileMode::Blob => reduce_blob_mask(mask),
+        }
+    }
+
+    pub fn is_member(&self, gid: u32) -> bool {
+        let gid = gid & GID_MASK;
+        gid != 0 && (self.extra_members.contains(&gid) || self.tiles.values().any(|tile| *tile == gid))
+    }
+
+    // Tile used when painting, the one surrounded on every side
+    pub fn paint_gid(&self) -> Option<u32> {
+        self.tile_for(0xFF)
+    }
+
+    // Falls back to the edges only, then to the full tile when the tileset lacks the exact one
+    pub fn tile_for(&self, mask: u8) -> Option<u32> {
+        let mask = self.reduce(mask);
+        self.tiles
+            .get(&mask)
+            .or_else(|| self.tiles.get(&(mask & (NORTH | EAST | SOUTH | WEST))))
+            .or_else(|| self.tiles.get(&self.reduce(0xFF)))
+            .copied()
+    }
+
+    pub fn mask_at(&self, layer: &TileLayer, x: i32, y: i32) -> u8 {
+        let same = |dx: i32, dy: i32| {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= layer.width as i32 || ny >= layer.height as i32 {
+                return self.connect_to_edges;
+            }
+            self.is_member(layer.tile(nx, ny))
+        };
+        let mut mask = 0;
+        for (bit, dx, dy) in [
+            (NORTH, 0, -1),
+            (NORTH_EAST, 1, -1),
+            (EAST, 1, 0),
+            (SOUTH_EAST, 1, 1),
+            (SOUTH, 0, 1),
+            (SOUTH_WEST, -1, 1),
+            (WEST, -1, 0),
+            (NORTH_WEST, -1, -1),
+        ] {
+            if same(dx, dy) {
+                mask |= bit;
+            }
+        }
+        self.reduce(mask)
+    }
+
+    // Gid the cell should have, None when it isn't this terrain or is a hand placed member.
+    // Flip bits of the cell are kept.
+    pub fn resolve(&self, layer: &TileLayer, x: i32, y: i32) -> Option<u32> {
+        let raw = layer.tile(x, y);
+        let gid = raw & GID_MASK;
+        if !self.is_member(gid) || self.extra_members.contains(&gid) {
+            return None;
+        }
+        self.tile_for(self.mask_at(layer, x, y)).map(|tile| tile | (raw & !GID_MASK))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDGES: u8 = NORTH | EAST | SOUTH | WEST;
+
+    #[test]
+    fn blob_has_47_distinct_masks() {
+        let masks = blob_masks();
+        assert_eq!(masks.len(), 47);
+        assert!(masks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!((masks[0], masks[46]), (0, 0xFF));
+        assert!(masks.iter().all(|mask| reduce_blob_mask(*mask) == *mask));
+    }
+
+    #[test]
+    fn reduce_blob_mask_clears_corners_without_both_edges() {
+        assert_eq!(reduce_blob_mask(NORTH_EAST | SOUTH_EAST | SOUTH_WEST | NORTH_WEST), 0);
+        assert_eq!(reduce_blob_mask(NORTH | NORTH_EAST), NORTH);
+        assert_eq!(reduce_blob_mask(EAST | NORTH_EAST), EAST);
+        assert_eq!(reduce_blob_mask(NORTH | EAST | NORTH_EAST), NORTH | EAST | NORTH_EAST);
+        assert_eq!(reduce_blob_mask(SOUTH | WEST | SOUTH_WEST | NORTH_WEST), SOUTH | WEST | SOUTH_WEST);
+        assert_eq!(reduce_blob_mask(EDGES | SOUTH_EAST), EDGES | SOUTH_EAST);
+        assert_eq!(reduce_blob_mask(0xFF), 0xFF);
+    }
+
+    #[test]
+    fn four_neighbour_layout_covers_every_edge_mask() {
+        let rule = AutotileRule::four_neighbour("water", 10);
+        assert_eq!(rule.tiles.len(), 16);
+        for mask in 0..=255u8 {
+            assert_eq!(rule.tile_for(mask), Some(10 + four_neighbour_index(mask) as u32));
+        }
+    }
+
+    #[test]
+    fn resolve_keeps_flip_bits() {
+        let rule = AutotileRule::four_neighbour("water", 1);
+        let layer = TileLayer::with_tiles("water", 3, 1, vec![0, 16 | 0x8000_0000, 0]);
+        let mut closed = rule.clone();
+        closed.connect_to_edges = false;
+        // Alone in the row, only the map edges above and below connect
+        assert_eq!(rule.resolve(&layer, 1, 0), Some((1 + four_neighbour_index(NORTH | SOUTH) as u32) | 0x8000_0000));
+        assert_eq!(closed.resolve(&layer, 1, 0), Some(1 | 0x8000_0000));
+        assert_eq!(rule.resolve(&layer, 0, 0), None);
+    }
+}
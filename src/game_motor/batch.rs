@@ -0,0 +1,95 @@
+use sfml::{
+    graphics::{BlendMode, PrimitiveType, RcSprite, RenderStates, RenderTarget, Texture, Transform, Transformable, Vertex},
+    system::Vector2f,
+};
+
+// Counted every frame by Scene::render, reset when a new frame starts
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    // Every draw sent to the window, batches included
+    pub draw_calls: u32,
+    pub batches: u32,
+    // Sprites that went through a batch instead of their own draw
+    pub batched_sprites: u32,
+}
+
+// Collects sprites as textured triangles, consecutive sprites with the same texture
+// end up in a single draw. A different texture flushes, so the draw order is kept.
+pub struct SpriteBatch<'a> {
+    texture: Option<&'a Texture>,
+    vertices: Vec<Vertex>,
+}
+
+impl<'a> SpriteBatch<'a> {
+    pub fn new() -> Self {
+        SpriteBatch {
+            texture: None,
+            vertices: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, target: &mut dyn RenderTarget, sprite: &'a RcSprite, stats: &mut RenderStats) {
+        self.push_transformed(target, sprite, &Transform::IDENTITY, stats);
+    }
+
+    // Sprite drawn under a parent transform, like the parts of a skeleton
+    pub fn push_transformed(
+        &mut self,
+        target: &mut dyn RenderTarget,
+        sprite: &'a RcSprite,
+        parent: &Transform,
+        stats: &mut RenderStats,
+    ) {
+        // Sprites without a texture draw nothing
+        let texture = match sprite.texture() {
+            Some(texture) => texture,
+            None => return,
+        };
+        if !self.texture.map(|current| std::ptr::eq(current, texture)).unwrap_or(false) {
+            self.flush(target, stats);
+            self.texture = Some(texture);
+        }
+
+        let mut transform = *parent;
+        transform.combine(sprite.transform());
+
+        // Negative rect sizes flip the texture, the quad itself stays positive
+        let rect = sprite.texture_rect();
+        let width = rect.width.abs() as f32;
+        let height = rect.height.abs() as f32;
+        let left = rect.left as f32;
+        let top = rect.top as f32;
+        let right = (rect.left + rect.width) as f32;
+        let bottom = (rect.top + rect.height) as f32;
+        let color = sprite.color();
+
+        let corner = |x: f32, y: f32, u: f32, v: f32| {
+            Vertex::new(transform.transform_point(Vector2f::new(x, y)), color, Vector2f::new(u, v))
+        };
+        let top_left = corner(0.0, 0.0, left, top);
+        let top_right = corner(width, 0.0, right, top);
+        let bottom_right = corner(width, height, right, bottom);
+        let bottom_left = corner(0.0, height, left, bottom);
+        self.vertices
+            .extend_from_slice(&[top_left, top_right, bottom_right, top_left, bottom_right, bottom_left]);
+        stats.batched_sprites += 1;
+    }
+
+    // Draws what was collected, call it before drawing anything outside the batch
+    pub fn flush(&mut self, target: &mut dyn RenderTarget, stats: &mut RenderStats) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let states = RenderStates::new(BlendMode::ALPHA, Transform::IDENTITY, self.texture, None);
+        target.draw_primitives(&self.vertices, PrimitiveType::TRIANGLES, &states);
+        stats.draw_calls += 1;
+        stats.batches += 1;
+        self.vertices.clear();
+    }
+}
+
+impl<'a> Default for SpriteBatch<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
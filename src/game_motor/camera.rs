@@ -0,0 +1,403 @@
+use sfml::{
+    graphics::{Color, FloatRect, Transform, View},
+    system::{Vector2f, Vector2i, Vector2u},
+    SfBox,
+};
+
+// Full screen color going from one alpha to another, flashes and fades
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenFade {
+    pub color: Color,
+    pub from_alpha: f32,
+    pub to_alpha: f32,
+    pub duration_ms: f32,
+    pub elapsed_ms: f32,
+    // Keep showing the last alpha when finished (fade out), otherwise the effect ends
+    pub hold: bool,
+}
+
+impl ScreenFade {
+    pub fn new(color: Color, from_alpha: f32, to_alpha: f32, duration_ms: f32, hold: bool) -> Self {
+        ScreenFade {
+            color,
+            from_alpha,
+            to_alpha,
+            duration_ms,
+            elapsed_ms: 0.0,
+            hold,
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.duration_ms <= 0.0 {
+            return 1.0;
+        }
+        (self.elapsed_ms / self.duration_ms).clamp(0.0, 1.0)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    // Color with the current alpha, the color's own alpha is the maximum
+    pub fn current_color(&self) -> Color {
+        let alpha = self.from_alpha + (self.to_alpha - self.from_alpha) * self.progress();
+        let mut color = self.color;
+        color.a = (self.color.a as f32 * alpha.clamp(0.0, 1.0)).round() as u8;
+        color
+    }
+}
+
+// Smooth value in -1.0 - 1.0, the same time always gives the same value so replays shake the same
+fn shake_noise(time_ms: f32, seed: f32) -> f32 {
+    let t = time_ms / 1000.0;
+    ((t * 37.0 + seed).sin() * 0.5 + (t * 61.0 + seed * 2.3).sin() * 0.3 + (t * 89.0 + seed * 4.1).sin() * 0.2).clamp(-1.0, 1.0)
+}
+
+// What the scene is looked at through, turned into an SFML View every frame
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub center: Vector2f,
+    // 1.0 shows the world 1:1, 2.0 shows everything twice as big
+    pub zoom: f32,
+    // Degrees
+    pub rotation: f32,
+    target: Option<String>,
+    // Size of the box around the center the target can move in without moving the camera
+    pub dead_zone: Vector2f,
+    // Time (ms) it takes to cover most of the distance to the target, 0.0 snaps
+    pub smoothing_ms: f32,
+    pub bounds: Option<FloatRect>,
+    // Part of the window drawn into, as fractions (0.0 - 1.0) of the window size
+    pub viewport: FloatRect,
+    // Layers this camera draws, None draws all of them
+    pub layers: Option<Vec<i32>>,
+    pub enabled: bool,
+    window_size: Vector2f,
+    viewport_size: Vector2f,
+    // 0.0 - 1.0, shake strength is trauma squared
+    trauma: f32,
+    pub trauma_decay_per_s: f32,
+    pub max_shake_offset: f32,
+    pub max_shake_rotation: f32,
+    shake_time_ms: f32,
+    fade: Option<ScreenFade>,
+    flash: Option<ScreenFade>,
+    // Centered on the window until it is moved or follows something
+    placed: bool,
+    snap: bool,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            center: Vector2f::new(0.0, 0.0),
+            zoom: 1.0,
+            rotation: 0.0,
+            target: None,
+            dead_zone: Vector2f::new(0.0, 0.0),
+            smoothing_ms: 0.0,
+            bounds: None,
+            viewport: FloatRect::new(0.0, 0.0, 1.0, 1.0),
+            layers: None,
+            enabled: true,
+            window_size: Vector2f::new(0.0, 0.0),
+            viewport_size: Vector2f::new(0.0, 0.0),
+            trauma: 0.0,
+            trauma_decay_per_s: 1.0,
+            max_shake_offset: 20.0,
+            max_shake_rotation: 3.0,
+            shake_time_ms: 0.0,
+            fade: None,
+            flash: None,
+            placed: false,
+            snap: false,
+        }
+    }
+
+    pub fn follow(&mut self, entity_id: &str) {
+        self.target = Some(entity_id.to_string());
+        self.snap = true;
+    }
+
+    pub fn stop_following(&mut self) {
+        self.target = None;
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    pub fn set_dead_zone(&mut self, width: f32, height: f32) {
+        self.dead_zone = Vector2f::new(width.max(0.0), height.max(0.0));
+    }
+
+    pub fn set_smoothing(&mut self, smoothing_ms: f32) {
+        self.smoothing_ms = smoothing_ms.max(0.0);
+    }
+
+    // The camera never shows anything outside of bounds (unless bounds are smaller than the view)
+    pub fn set_bounds(&mut self, bounds: FloatRect) {
+        self.bounds = Some(bounds);
+    }
+
+    pub fn clear_bounds(&mut self) {
+        self.bounds = None;
+    }
+
+    pub fn set_center(&mut self, center: Vector2f) {
+        self.center = center;
+        self.placed = true;
+    }
+
+    pub fn move_(&mut self, offset: Vector2f) {
+        self.set_center(self.center + offset);
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(0.01);
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.set_zoom(self.zoom * factor);
+    }
+
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation % 360.0;
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.set_rotation(self.rotation + angle);
+    }
+
+    // Draws into part of the window, e.g. FloatRect::new(0.0, 0.0, 0.5, 1.0) for the left half
+    pub fn with_viewport(viewport: FloatRect) -> Self {
+        Camera {
+            viewport,
+            ..Camera::new()
+        }
+    }
+
+    pub fn set_viewport(&mut self, viewport: FloatRect) {
+        self.viewport = viewport;
+        self.update_viewport_size();
+    }
+
+    pub fn set_layers(&mut self, layers: Option<Vec<i32>>) {
+        self.layers = layers;
+    }
+
+    pub fn shows_layer(&self, layer: i32) -> bool {
+        self.layers.as_ref().map(|layers| layers.contains(&layer)).unwrap_or(true)
+    }
+
+    // Window size in pixels, the game loop keeps it up to date
+    pub fn set_window_size(&mut self, size: Vector2u) {
+        self.window_size = Vector2f::new(size.x as f32, size.y as f32);
+        self.update_viewport_size();
+    }
+
+    fn update_viewport_size(&mut self) {
+        self.viewport_size = Vector2f::new(
+            self.window_size.x * self.viewport.width,
+            self.window_size.y * self.viewport.height,
+        );
+        if !self.placed && self.target.is_none() {
+            self.center = self.viewport_size / 2.0;
+        }
+    }
+
+    // Size of the viewport in pixels
+    pub fn viewport_size(&self) -> Vector2f {
+        self.viewport_size
+    }
+
+    // Viewport in window pixels
+    pub fn viewport_pixels(&self) -> FloatRect {
+        FloatRect::new(
+            self.window_size.x * self.viewport.left,
+            self.window_size.y * self.viewport.top,
+            self.viewport_size.x,
+            self.viewport_size.y,
+        )
+    }
+
+    pub fn contains_screen_point(&self, point: Vector2i) -> bool {
+        self.viewport_pixels().contains(Vector2f::new(point.x as f32, point.y as f32))
+    }
+    // World size that fits in the window
+    pub fn visible_size(&self) -> Vector2f {
+        self.viewport_size / self.zoom
+    }
+
+    pub fn visible_area(&self) -> FloatRect {
+        let size = self.visible_size();
+        FloatRect::new(self.center.x - size.x / 2.0, self.center.y - size.y / 2.0, size.x, size.y)
+    }
+
+    // Hits add trauma, it wears off by itself
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    pub fn stop_shake(&mut self) {
+        self.trauma = 0.0;
+    }
+
+    // Offset and rotation the shake adds to the view this frame
+    pub fn shake(&self) -> (Vector2f, f32) {
+        let strength = self.trauma * self.trauma;
+        if strength <= 0.0 {
+            return (Vector2f::new(0.0, 0.0), 0.0);
+        }
+        let offset = Vector2f::new(
+            self.max_shake_offset * strength * shake_noise(self.shake_time_ms, 1.0),
+            self.max_shake_offset * strength * shake_noise(self.shake_time_ms, 2.0),
+        );
+        (offset, self.max_shake_rotation * strength * shake_noise(self.shake_time_ms, 3.0))
+    }
+
+    // Screen turns color at once and goes back to normal over duration
+    pub fn flash(&mut self, color: Color, duration_ms: f32) {
+        self.flash = Some(ScreenFade::new(color, 1.0, 0.0, duration_ms, false));
+    }
+
+    // Screen goes to color and stays there, e.g. before changing level
+    pub fn fade_out(&mut self, color: Color, duration_ms: f32) {
+        self.fade = Some(ScreenFade::new(color, 0.0, 1.0, duration_ms, true));
+    }
+
+    // Screen starts as color and clears up
+    pub fn fade_in(&mut self, color: Color, duration_ms: f32) {
+        self.fade = Some(ScreenFade::new(color, 1.0, 0.0, duration_ms, false));
+    }
+
+    pub fn clear_fade(&mut self) {
+        self.fade = None;
+        self.flash = None;
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.map(|fade| !fade.is_finished()).unwrap_or(false)
+    }
+
+    // True once a fade out fully covers the screen
+    pub fn is_faded_out(&self) -> bool {
+        self.fade.map(|fade| fade.hold && fade.is_finished()).unwrap_or(false)
+    }
+
+    // Colors to draw over the whole screen, fade first
+    pub fn overlays(&self) -> Vec<Color> {
+        self.fade.iter().chain(self.flash.iter()).map(|fade| fade.current_color()).filter(|color| color.a > 0).collect()
+    }
+
+    fn update_effects(&mut self, delta_ms: f32) {
+        self.trauma = (self.trauma - self.trauma_decay_per_s * delta_ms / 1000.0).max(0.0);
+        self.shake_time_ms += delta_ms;
+
+        for effect in [&mut self.fade, &mut self.flash] {
+            if let Some(fade) = effect.as_mut() {
+                fade.elapsed_ms += delta_ms;
+                if fade.is_finished() && !fade.hold {
+                    *effect = None;
+                }
+            }
+        }
+    }
+
+    // Moves towards the followed entity's position, then keeps the view inside the bounds
+    pub fn update(&mut self, delta_ms: f32, target_position: Option<Vector2f>) {
+        self.update_effects(delta_ms);
+
+        if let Some(target) = target_position {
+            let half_zone = self.dead_zone / 2.0;
+            let mut desired = self.center;
+            if target.x > self.center.x + half_zone.x {
+                desired.x = target.x - half_zone.x;
+            } else if target.x < self.center.x - half_zone.x {
+                desired.x = target.x + half_zone.x;
+            }
+            if target.y > self.center.y + half_zone.y {
+                desired.y = target.y - half_zone.y;
+            } else if target.y < self.center.y - half_zone.y {
+                desired.y = target.y + half_zone.y;
+            }
+
+            if self.snap || self.smoothing_ms <= 0.0 {
+                self.center = if self.snap { target } else { desired };
+                self.snap = false;
+            } else {
+                let t = 1.0 - (-delta_ms / self.smoothing_ms).exp();
+                self.center += (desired - self.center) * t;
+            }
+            self.placed = true;
+        }
+
+        self.clamp_to_bounds();
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let half = self.visible_size() / 2.0;
+
+        self.center.x = if bounds.width <= half.x * 2.0 {
+            bounds.left + bounds.width / 2.0
+        } else {
+            self.center.x.clamp(bounds.left + half.x, bounds.left + bounds.width - half.x)
+        };
+        self.center.y = if bounds.height <= half.y * 2.0 {
+            bounds.top + bounds.height / 2.0
+        } else {
+            self.center.y.clamp(bounds.top + half.y, bounds.top + bounds.height - half.y)
+        };
+    }
+
+    // Includes the shake, screen/world conversion doesn't
+    pub fn view(&self) -> SfBox<View> {
+        let (offset, rotation) = self.shake();
+        let mut view = View::new(self.center + offset, self.visible_size());
+        view.set_rotation(self.rotation + rotation);
+        view.set_viewport(self.viewport);
+        view
+    }
+
+    // Pixel space view over the viewport, for drawing the fade and flash overlays
+    pub fn screen_view(&self) -> SfBox<View> {
+        let mut view = View::new(self.viewport_size / 2.0, self.viewport_size);
+        view.set_viewport(self.viewport);
+        view
+    }
+
+    // World to window pixels
+    pub fn transform(&self) -> Transform {
+        let viewport = self.viewport_pixels();
+        let mut transform = Transform::IDENTITY;
+        transform.translate(viewport.left + viewport.width / 2.0, viewport.top + viewport.height / 2.0);
+        transform.scale(self.zoom, self.zoom);
+        transform.rotate(-self.rotation);
+        transform.translate(-self.center.x, -self.center.y);
+        transform
+    }
+
+    pub fn screen_to_world(&self, point: Vector2i) -> Vector2f {
+        self.transform().inverse().transform_point(Vector2f::new(point.x as f32, point.y as f32))
+    }
+
+    pub fn world_to_screen(&self, point: Vector2f) -> Vector2i {
+        let screen = self.transform().transform_point(point);
+        Vector2i::new(screen.x.round() as i32, screen.y.round() as i32)
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -26,6 +26,8 @@ pub trait EntityTrait {
     fn set_current_animation(&mut self, name: &str);
     fn set_current_animation_flipped(&mut self, name: &str, flip_x: bool, flip_y: bool);
     fn add_animation(&mut self, name: &str, animation: Animation);
+    fn try_add_animation(&mut self, name: &str, animation: Animation) -> Result<(), AnimationError>;
+    fn remove_animation(&mut self, name: &str) -> Option<Animation>;
     fn current_animation(&self) -> &str;
 
     fn move_up(&mut self);
@@ -43,6 +45,27 @@ pub trait EntityTrait {
 
     fn get_animations(&self) -> &HashMap<String, Animation>;
     fn get_flip(&self) -> (bool, bool);
+    fn set_flip(&mut self, flip_x: bool, flip_y: bool);
+
+    fn set_skeleton(&mut self, skeleton: Skeleton);
+    fn get_skeleton(&self) -> Option<&Skeleton>;
+    fn get_skeleton_mut(&mut self) -> Option<&mut Skeleton>;
+
+    fn add_attachment_point(&mut self, name: &str, local_position: Vector2f);
+    fn get_attachment_point(&self, name: &str) -> Option<Vector2f>;
+
+    fn set_crossfade_duration(&mut self, duration_ms: f32);
+    fn get_crossfade_duration(&self) -> f32;
+    fn get_crossfade(&self) -> Option<&Crossfade>;
+    fn get_crossfade_mut(&mut self) -> Option<&mut Crossfade>;
+    fn stop_crossfade(&mut self);
+
+    fn set_layer(&mut self, layer: i32);
+    fn get_layer(&self) -> i32;
+    fn set_z_index(&mut self, z_index: i32);
+    fn get_z_index(&self) -> i32;
+    fn set_shader(&mut self, shader_id: Option<&str>);
+    fn get_shader(&self) -> Option<&str>;
 
     fn get_physics(&self) -> &PhysicalProperties;
     fn get_physics_mut(&mut self) -> &mut PhysicalProperties;
@@ -71,9 +94,18 @@ pub struct Entity {
     animation_changed: bool,
     flip_x: bool,
     flip_y: bool,
+    scale: Vector2f,
+    origin: Vector2f,
+    attachment_points: HashMap<String, Vector2f>,
     physics: PhysicalProperties,
     deletion_flag: bool,
     previous_position: Vector2f,
+    crossfade_duration_ms: f32,
+    crossfade: Option<Crossfade>,
+    skeleton: Option<Skeleton>,
+    layer: i32,
+    z_index: i32,
+    shader: Option<String>,
 }
 
 impl Entity {
@@ -87,10 +119,36 @@ impl Entity {
             animation_changed: true,
             flip_x: false,
             flip_y: false,
+            scale: Vector2f::new(1.0, 1.0),
+            origin: Vector2f::new(0.0, 0.0),
+            attachment_points: HashMap::new(),
             physics: PhysicalProperties::default(),
             deletion_flag: false,
+            crossfade_duration_ms: 0.0,
+            crossfade: None,
+            skeleton: None,
+            layer: 0,
+            z_index: 0,
+            shader: None,
+        }
+    }
 
+    // Orientation is applied through the sprite scale, origin is mirrored so the
+    // flipped sprite covers the same bounds (and colliders) as the unflipped one
+    fn apply_orientation(&mut self) {
+        let size = self.sprite.local_bounds().size();
+        let mut scale = self.scale;
+        let mut origin = self.origin;
+        if self.flip_x {
+            scale.x = -scale.x;
+            origin.x = size.x - origin.x;
         }
+        if self.flip_y {
+            scale.y = -scale.y;
+            origin.y = size.y - origin.y;
+        }
+        self.sprite.set_scale(scale);
+        self.sprite.set_origin(origin);
     }
 }
 
@@ -179,10 +237,87 @@ impl EntityTrait for Entity {
         (self.flip_x, self.flip_y)
     }
 
+    fn set_flip(&mut self, flip_x: bool, flip_y: bool) {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self.apply_orientation();
+    }
+
+    fn set_skeleton(&mut self, skeleton: Skeleton) {
+        self.skeleton = Some(skeleton);
+    }
+
+    fn get_skeleton(&self) -> Option<&Skeleton> {
+        self.skeleton.as_ref()
+    }
+
+    fn get_skeleton_mut(&mut self) -> Option<&mut Skeleton> {
+        self.skeleton.as_mut()
+    }
+
+    // Local position is in unflipped frame pixels
+    fn add_attachment_point(&mut self, name: &str, local_position: Vector2f) {
+        self.attachment_points.insert(name.to_string(), local_position);
+    }
+
+    // World position of the attachment point, the sprite transform already mirrors it
+    fn get_attachment_point(&self, name: &str) -> Option<Vector2f> {
+        let local = self.attachment_points.get(name)?;
+        Some(self.sprite.transform().transform_point(*local))
+    }
+
     fn get_animations(&self) -> &HashMap<String, Animation> {
         &self.animations
     }
 
+    // Lower layers are drawn first, z index orders entities inside a layer
+    fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    fn get_layer(&self) -> i32 {
+        self.layer
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+    }
+
+    fn get_z_index(&self) -> i32 {
+        self.z_index
+    }
+
+    // Id of a shader in the scene shaders, the entity is drawn on its own with it
+    fn set_shader(&mut self, shader_id: Option<&str>) {
+        self.shader = shader_id.map(|id| id.to_string());
+    }
+
+    fn get_shader(&self) -> Option<&str> {
+        self.shader.as_deref()
+    }
+
+    fn set_crossfade_duration(&mut self, duration_ms: f32) {
+        self.crossfade_duration_ms = duration_ms.max(0.0);
+    }
+
+    fn get_crossfade_duration(&self) -> f32 {
+        self.crossfade_duration_ms
+    }
+
+    fn get_crossfade(&self) -> Option<&Crossfade> {
+        self.crossfade.as_ref()
+    }
+
+    fn get_crossfade_mut(&mut self) -> Option<&mut Crossfade> {
+        self.crossfade.as_mut()
+    }
+
+    fn stop_crossfade(&mut self) {
+        if let Some(fade) = self.crossfade.take() {
+            self.sprite.set_color(fade.base_color);
+        }
+    }
+
     fn get_sprite_mut(&mut self) -> &mut RcSprite {
         &mut self.sprite
     }
@@ -228,14 +363,23 @@ impl EntityTrait for Entity {
     }
 
     fn set_color(&mut self, color: Color) {
+        if let Some(fade) = self.crossfade.as_mut() {
+            fade.base_color = color;
+            self.sprite.set_color(fade.incoming_color());
+            return;
+        }
         self.sprite.set_color(color);
     }
 
     fn set_texture(&mut self, texture: &RcTexture, reset_rect: bool) {
         self.sprite.set_texture(texture, reset_rect);
+        self.apply_orientation();
     }
 
     fn color(&self) -> Color {
+        if let Some(fade) = self.crossfade.as_ref() {
+            return fade.base_color;
+        }
         self.sprite.color()
     }
 
@@ -245,6 +389,7 @@ impl EntityTrait for Entity {
 
     fn set_texture_rect(&mut self, rect: IntRect) {
         self.sprite.set_texture_rect(rect);
+        self.apply_orientation();
     }
 
     fn get_animation(&self, name: &str) -> Option<&Animation> {
@@ -265,8 +410,7 @@ impl EntityTrait for Entity {
 
     fn set_current_animation_flipped(&mut self, name: &str, flip_x: bool, flip_y: bool) {
         self.set_current_animation(name);
-        self.flip_x = flip_x;
-        self.flip_y = flip_y;
+        self.set_flip(flip_x, flip_y);
     }
 
     fn set_current_animation(&mut self, name: &str) {
@@ -285,15 +429,28 @@ impl EntityTrait for Entity {
             return;
         }
 
-        self.flip_x = false;
-        self.flip_y = false;
+        // Keep the outgoing frame around so it can fade out
+        if self.crossfade_duration_ms > 0.0 && self.sprite.texture().is_some() {
+            self.stop_crossfade();
+            let base_color = self.sprite.color();
+            self.crossfade = Some(Crossfade::new(self.sprite.clone(), base_color, self.crossfade_duration_ms));
+            self.sprite.set_color(self.crossfade.as_ref().unwrap().incoming_color());
+        }
+
         self.animation_changed = true;
         self.current_animation = name.to_string();
     }
 
+    // Duplicates are reported and ignored, the first animation with the name is kept
     fn add_animation(&mut self, name: &str, animation: Animation) {
+        if let Err(error) = self.try_add_animation(name, animation) {
+            println!("Entity {}: {}", self.id, error);
+        }
+    }
+
+    fn try_add_animation(&mut self, name: &str, animation: Animation) -> Result<(), AnimationError> {
         if self.animations.contains_key(name) {
-            panic!("Animation already exists: {}", name);
+            return Err(AnimationError::DuplicateAnimation { animation: name.to_string() });
         }
 
         if self.animations.is_empty() {
@@ -301,6 +458,11 @@ impl EntityTrait for Entity {
         }
 
         self.animations.insert(name.to_string(), animation);
+        Ok(())
+    }
+
+    fn remove_animation(&mut self, name: &str) -> Option<Animation> {
+        self.animations.remove(name)
     }
 }
 
@@ -316,7 +478,7 @@ impl Drawable for Entity {
 
 impl Transformable for Entity {
     fn get_scale(&self) -> sfml::system::Vector2f {
-        self.sprite.get_scale()
+        self.scale
     }
 
     fn inverse_transform(&self) -> &sfml::graphics::Transform {
@@ -324,7 +486,9 @@ impl Transformable for Entity {
     }
 
     fn scale<F: Into<sfml::system::Vector2f>>(&mut self, factors: F) {
-        self.sprite.scale(factors);
+        let factors = factors.into();
+        self.scale = Vector2f::new(self.scale.x * factors.x, self.scale.y * factors.y);
+        self.apply_orientation();
     }
 
     fn transform(&self) -> &sfml::graphics::Transform {
@@ -340,7 +504,7 @@ impl Transformable for Entity {
     }
 
     fn origin(&self) -> sfml::system::Vector2f {
-        self.sprite.origin()
+        self.origin
     }
 
     fn move_<O: Into<sfml::system::Vector2f>>(&mut self, offset: O) {
@@ -356,7 +520,8 @@ impl Transformable for Entity {
     }
 
     fn set_origin<O: Into<sfml::system::Vector2f>>(&mut self, origin: O) {
-        self.sprite.set_origin(origin);
+        self.origin = origin.into();
+        self.apply_orientation();
     }
 
     fn set_rotation(&mut self, angle: f32) {
@@ -364,6 +529,7 @@ impl Transformable for Entity {
     }
 
     fn set_scale<S: Into<sfml::system::Vector2f>>(&mut self, scale: S) {
-        self.sprite.set_scale(scale);
+        self.scale = scale.into();
+        self.apply_orientation();
     }
 }
@@ -1,28 +1,31 @@
-use std::{io::SeekFrom, time::Instant};
+use std::{cell::Cell, io::SeekFrom, time::Instant};
 
 use sfml::{
     graphics::{Color, Drawable, RenderTarget, RenderWindow, Transformable},
+    system::Vector2i,
     window::{Event, Key, Style, VideoMode},
 };
 
-use crate::{EntityTrait, Scene};
+use super::{entity::EntityTrait, scene::Scene};
+
+use super::replay::{InputRecording, InputReplay, RecordedInput};
 
 use super::scene;
 
 
-pub static mut RENDER_MS: f32 = 16.66;
+// Frame time of the game ticking on this thread, set by Game at the start of every tick.
+// Per thread, so games stepped side by side (tests) don't see each other's frame time.
+thread_local! {
+    pub static RENDER_MS: Cell<f32> = const { Cell::new(16.66) };
+}
 
 
 pub fn get_render_ms() -> f32{
-    unsafe{
-        RENDER_MS
-    }
+    RENDER_MS.with(Cell::get)
 }
 
 fn set_render_ms(ms: f32){
-    unsafe{
-        RENDER_MS = ms;
-    }
+    RENDER_MS.with(|render_ms| render_ms.set(ms));
 }
 
 
@@ -40,6 +43,14 @@ where
     title: String,
     style: Style,
     scene: Scene<T>,
+    tick: u64,
+    fixed_delta_ms: Option<f32>,
+    // Measured time of the last frame, used when there is no fixed delta
+    frame_ms: f32,
+    recording: Option<(String, InputRecording)>,
+    replay: Option<InputReplay>,
+    // Fed on the next tick like window events, for driving the game without a window
+    queued_input: Vec<RecordedInput>,
 }
 
 impl<T> Game<T>
@@ -52,6 +63,12 @@ where
             title: title.to_string(),
             style,
             scene,
+            tick: 0,
+            fixed_delta_ms: None,
+            frame_ms: 16.66,
+            recording: None,
+            replay: None,
+            queued_input: Vec::new(),
         }
     }
 
@@ -83,6 +100,168 @@ where
     }
 
 
+    pub fn scene(&self) -> &Scene<T> {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene<T> {
+        &mut self.scene
+    }
+
+    // Every frame advances the world by this much instead of the measured frame time
+    pub fn set_fixed_delta(&mut self, delta_ms: Option<f32>) {
+        self.fixed_delta_ms = delta_ms;
+    }
+
+    // Game time the next tick advances by
+    pub fn delta_ms(&self) -> f32 {
+        self.fixed_delta_ms.unwrap_or(self.frame_ms)
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    // Inputs fed to the scene are recorded with their tick and written to path when the window closes
+    pub fn record_input(&mut self, path: &str) {
+        self.recording = Some((path.to_string(), InputRecording::new(self.fixed_delta_ms)));
+    }
+
+    // Returns the inputs recorded so far without saving them
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take().map(|(_, recording)| recording)
+    }
+
+    // Live input is ignored while replaying, the recording's fixed delta is used if it has one
+    pub fn replay_input(&mut self, recording: &InputRecording) {
+        if recording.fixed_delta_ms.is_some() {
+            self.fixed_delta_ms = recording.fixed_delta_ms;
+        }
+        self.replay = Some(InputReplay::new(recording));
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    fn apply_input(&mut self, input: RecordedInput) {
+        if let Some((_, recording)) = self.recording.as_mut() {
+            recording.push(self.tick, input);
+        }
+
+        match input {
+            RecordedInput::KeyPressed(code) => self.set_key(KeyState::Pressed(code)),
+            RecordedInput::KeyReleased(code) => self.set_key(KeyState::Released(code)),
+            RecordedInput::MouseMoved { x, y } => {
+                self.scene.set_mouse_position(Vector2i::new(x, y));
+            }
+            RecordedInput::MouseButton { button, pressed, x, y } => {
+                self.scene.set_mouse_position(Vector2i::new(x, y));
+                self.scene.set_mouse_button(button, pressed);
+            }
+            RecordedInput::MouseWheel { wheel, delta } => {
+                self.scene.add_mouse_wheel(wheel, delta);
+            }
+            RecordedInput::JoystickButton { joystick, button, pressed } => {
+                self.scene.set_joystick_button(joystick, button, pressed);
+            }
+            RecordedInput::JoystickMoved { joystick, axis, position } => {
+                self.scene.set_joystick_axis(joystick, axis, position);
+            }
+            RecordedInput::JoystickDisconnected { joystick } => {
+                self.scene.clear_joystick(joystick);
+            }
+        }
+    }
+
+    // Handled on the next tick together with the window events, recorded like them
+    pub fn queue_input(&mut self, input: RecordedInput) {
+        self.queued_input.push(input);
+    }
+
+    // Advances the game one frame without a window, e.g. to replay a recording in a test
+    pub fn step(&mut self) {
+        self.tick(None);
+    }
+
+    fn tick(&mut self, mut wnd: Option<&mut RenderWindow>) {
+        set_render_ms(self.delta_ms());
+
+        // UPDATE ENTITIES
+        let (collisions, solid_collisions) = self.scene.update_entities();
+
+        // FIRE FIRST RENDER EVENT
+        if self.scene.first_render {
+            (self.scene.on_start)(&mut self.scene);
+            self.scene.first_render = false;
+        }
+
+        // FIRE ON_EVENT
+        self.scene.begin_input_frame(get_render_ms());
+        if let Some(wnd) = wnd.as_deref_mut() {
+            while let Some(event) = wnd.poll_event() {
+                if let Event::Closed = event {
+                    wnd.close();
+                }
+                if self.replay.is_none() {
+                    if let Some(input) = RecordedInput::from_event(&event) {
+                        self.apply_input(input);
+                    }
+                }
+                (self.scene.on_event)(&mut self.scene, event);
+            }
+        }
+        for input in std::mem::take(&mut self.queued_input) {
+            if self.replay.is_none() {
+                self.apply_input(input);
+            }
+        }
+
+        // REPLAY RECORDED INPUT
+        if let Some(replay) = self.replay.as_mut() {
+            let inputs = replay.inputs_until(self.tick);
+            if replay.is_finished() {
+                self.replay = None;
+            }
+            for input in inputs {
+                self.apply_input(input);
+            }
+        }
+        self.scene.update_actions();
+
+        // The camera may have moved even if the mouse didn't
+        if let Some(wnd) = wnd.as_deref() {
+            self.scene.set_window_size(wnd.size());
+        }
+        let mouse_world = self.scene.screen_to_world(self.scene.mouse_position());
+        self.scene.set_mouse_world_position(mouse_world);
+
+        // HOT RELOAD CHANGED ASSETS
+        self.scene.poll_asset_changes();
+
+        // FIRE ON_ASSETS_LOADED
+        if let Some(progress) = self.scene.poll_loading() {
+            (self.scene.on_assets_loaded)(&mut self.scene, progress);
+        }
+
+        // FIRE ON_UPDATE
+        (self.scene.on_update)(&mut self.scene);
+
+        // FIRE ON_COLLISION
+        for (e1,e2,rect) in collisions{
+            (self.scene.on_collision)(&mut self.scene, e1, e2, rect);
+        }
+
+        self.scene.push_back_solid_colisions(solid_collisions);
+        self.scene.update_tilemaps();
+
+        // CAMERA FOLLOW
+        self.scene.update_camera();
+        self.scene.update_parallax();
+        self.scene.update_particles();
+        self.tick += 1;
+    }
+
     pub fn run<W>(&mut self, v_mode_or_size: W) where W: Into<VideoMode> {
 
 
@@ -94,7 +273,12 @@ where
             &Default::default(),
         );
 
-        wnd.set_framerate_limit(240);
+        // A fixed delta is game time per frame, the frame rate has to match it or replays run fast
+        let framerate_limit = match self.fixed_delta_ms {
+            Some(delta) if delta > 0.0 => (1000.0 / delta).round() as u32,
+            _ => 240,
+        };
+        wnd.set_framerate_limit(framerate_limit);
 
         
 
@@ -102,46 +286,90 @@ where
             let now = Instant::now();
 
             wnd.clear(Color::BLACK);
-          
-
-            // UPDATE ENTITIES
-            let (collisions, solid_collisions) = self.scene.update_entities();
-              
-             // FIRE FIRST RENDER EVENT
-             if self.scene.first_render{
-                (self.scene.on_start)(&mut self.scene);
-                self.scene.first_render = false;
-            }
-           
-            // FIRE ON_EVENT
-            while let Some(event) = wnd.poll_event() {
-                match event {
-                    Event::Closed => wnd.close(),
-                    Event::KeyPressed { code,.. } => {
-                       self.set_key(KeyState::Pressed(code));
-                    },
-                    Event::KeyReleased { code,.. } => {
-                        self.set_key(KeyState::Released(code));
-                    },
-                    _ => {}
-                }
-                (self.scene.on_event)(&mut self.scene, event);
+
+            self.tick(Some(&mut wnd));
+            self.scene.render(&mut wnd);
+
+            wnd.display();
+            self.frame_ms = now.elapsed().as_millis() as f32; // GET RENDER TIME
+        }
+
+        if let Some((path, recording)) = self.recording.take() {
+            match recording.save(&path) {
+                Ok(()) => println!("Input recorded to {}", path),
+                Err(error) => println!("Failed to save input recording {}: {}", path, error),
             }
+        }
+    }
+}
 
-            // FIRE ON_UPDATE
-            (self.scene.on_update)(&mut self.scene);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_motor::{assets::AssetServer, entity::Entity};
 
-            // FIRE ON_COLLISION
-            for (e1,e2,rect) in collisions{
-                (self.scene.on_collision)(&mut self.scene, e1, e2, rect);
+    // D and A walk right and left, W moves up, so the position depends on every input
+    fn replay_scene() -> Scene<Entity> {
+        let mut scene = Scene::headless("replay", AssetServer::shared());
+        scene.add_entity(Entity::new("walker"));
+        scene.on_update = |scene| {
+            let mut walker = scene.entity("walker").borrow_mut();
+            if scene.check_key(Key::D) {
+                walker.move_right();
             }
+            if scene.check_key(Key::A) {
+                walker.move_left();
+            }
+            if scene.check_key(Key::W) {
+                walker.move_up();
+            }
+        };
+        scene
+    }
 
-            self.scene.push_back_solid_colisions(solid_collisions);
-            self.scene.render(&mut wnd);
+    fn headless_game() -> Game<Entity> {
+        let mut game = Game::new("replay", Style::NONE, replay_scene());
+        game.set_fixed_delta(Some(16.0));
+        game
+    }
 
-            wnd.display();
-            let elapsed = now.elapsed().as_millis() as f32; // GET RENDER TIME
-            set_render_ms(elapsed); // SET RENDER TIME
+    #[test]
+    fn replay_reproduces_scene_state_tick_for_tick() {
+        let script = [
+            (5, RecordedInput::KeyPressed(Key::D)),
+            (30, RecordedInput::KeyPressed(Key::W)),
+            (42, RecordedInput::KeyReleased(Key::D)),
+            (43, RecordedInput::KeyPressed(Key::A)),
+            (70, RecordedInput::KeyReleased(Key::W)),
+            (90, RecordedInput::KeyReleased(Key::A)),
+        ];
+
+        let mut recorder = headless_game();
+        recorder.record_input("unused");
+        let mut recorded_hashes = Vec::new();
+        for tick in 0..120 {
+            for (_, input) in script.iter().filter(|(at, _)| *at == tick) {
+                recorder.queue_input(*input);
+            }
+            recorder.step();
+            recorded_hashes.push(recorder.scene().state_hash());
         }
+        let recording = recorder.stop_recording().unwrap();
+        assert_eq!(recording.inputs.len(), script.len());
+
+        // Through the file format, like --record / --replay
+        let recording = InputRecording::from_file_str(&recording.to_file_string()).unwrap();
+        let mut player = headless_game();
+        player.set_fixed_delta(None);
+        player.replay_input(&recording);
+        let replayed_hashes: Vec<u64> = (0..120)
+            .map(|_| {
+                player.step();
+                player.scene().state_hash()
+            })
+            .collect();
+
+        assert_eq!(recorded_hashes, replayed_hashes);
+        assert_ne!(recorded_hashes[0], recorded_hashes[119]);
     }
 }
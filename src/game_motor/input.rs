@@ -0,0 +1,635 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, fs,
+    hash::Hash,
+    io,
+    path::Path,
+};
+
+use sfml::window::{joystick, mouse, Key};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputBinding {
+    Key(Key),
+    Mouse(mouse::Button),
+    JoystickButton { joystick: u32, button: u32 },
+    // Counts as pressed when the axis is past the threshold in direction (1.0 or -1.0)
+    JoystickAxis { joystick: u32, axis: joystick::Axis, direction: f32 },
+}
+
+// Anything with a pressed/released state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputButton {
+    Key(Key),
+    Mouse(mouse::Button),
+    Joystick { joystick: u32, button: u32 },
+}
+
+impl From<Key> for InputButton {
+    fn from(key: Key) -> Self {
+        InputButton::Key(key)
+    }
+}
+
+impl From<mouse::Button> for InputButton {
+    fn from(button: mouse::Button) -> Self {
+        InputButton::Mouse(button)
+    }
+}
+
+// Press/release transitions of buttons or actions, just_* sets only live for one frame
+#[derive(Debug, Clone)]
+pub struct PressTracker<K: Hash + Eq + Clone> {
+    held_ms: HashMap<K, f32>,
+    just_pressed: HashSet<K>,
+    just_released: HashSet<K>,
+    last_press_ms: HashMap<K, f32>,
+    double_tapped: HashSet<K>,
+    clock_ms: f32,
+    // Two presses closer than this are a double tap
+    pub double_tap_ms: f32,
+}
+
+impl<K: Hash + Eq + Clone> PressTracker<K> {
+    pub fn new() -> Self {
+        PressTracker {
+            held_ms: HashMap::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+            last_press_ms: HashMap::new(),
+            double_tapped: HashSet::new(),
+            clock_ms: 0.0,
+            double_tap_ms: 250.0,
+        }
+    }
+
+    // Clears last frame's transitions and adds the frame time to held buttons
+    pub fn begin_frame(&mut self, delta_ms: f32) {
+        self.clock_ms += delta_ms;
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.double_tapped.clear();
+        for held in self.held_ms.values_mut() {
+            *held += delta_ms;
+        }
+    }
+
+    // Repeated presses without a release are ignored
+    pub fn press(&mut self, key: K) {
+        if self.held_ms.contains_key(&key) {
+            return;
+        }
+        if let Some(last) = self.last_press_ms.get(&key) {
+            if self.clock_ms - last <= self.double_tap_ms {
+                self.double_tapped.insert(key.clone());
+            }
+        }
+        // A double tap doesn't start the next one
+        if self.double_tapped.contains(&key) {
+            self.last_press_ms.remove(&key);
+        } else {
+            self.last_press_ms.insert(key.clone(), self.clock_ms);
+        }
+        self.held_ms.insert(key.clone(), 0.0);
+        self.just_pressed.insert(key);
+    }
+
+    pub fn release(&mut self, key: K) {
+        if self.held_ms.remove(&key).is_some() {
+            self.just_released.insert(key);
+        }
+    }
+
+    pub fn is_held(&self, key: &K) -> bool {
+        self.held_ms.contains_key(key)
+    }
+
+    pub fn is_just_pressed(&self, key: &K) -> bool {
+        self.just_pressed.contains(key)
+    }
+
+    pub fn is_just_released(&self, key: &K) -> bool {
+        self.just_released.contains(key)
+    }
+
+    // True on the frame of the second press
+    pub fn is_double_tap(&self, key: &K) -> bool {
+        self.double_tapped.contains(key)
+    }
+
+    // How long it has been held, 0.0 if it isn't
+    pub fn held_duration(&self, key: &K) -> f32 {
+        self.held_ms.get(key).copied().unwrap_or(0.0)
+    }
+
+    pub fn clear(&mut self) {
+        self.held_ms.clear();
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.last_press_ms.clear();
+        self.double_tapped.clear();
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for PressTracker<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Recent action presses, oldest first, for combos like ["down", "forward", "attack"]
+#[derive(Debug, Clone)]
+pub struct InputBuffer {
+    entries: VecDeque<(String, f32)>,
+    clock_ms: f32,
+    // Presses older than this are dropped, a combo has to fit in it
+    pub window_ms: f32,
+    pub capacity: usize,
+}
+
+impl InputBuffer {
+    pub fn new(window_ms: f32, capacity: usize) -> Self {
+        InputBuffer {
+            entries: VecDeque::new(),
+            clock_ms: 0.0,
+            window_ms,
+            capacity,
+        }
+    }
+
+    pub fn advance(&mut self, delta_ms: f32) {
+        self.clock_ms += delta_ms;
+        let oldest = self.clock_ms - self.window_ms;
+        while self.entries.front().map(|(_, time)| *time < oldest).unwrap_or(false) {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, action: &str) {
+        self.entries.push_back((action.to_string(), self.clock_ms));
+        while self.entries.len() > self.capacity.max(1) {
+            self.entries.pop_front();
+        }
+    }
+
+    // The sequence has to be the latest presses, in order, with nothing in between
+    pub fn matches(&self, sequence: &[&str]) -> bool {
+        if sequence.is_empty() || sequence.len() > self.entries.len() {
+            return false;
+        }
+        let start = self.entries.len() - sequence.len();
+        self.entries.iter().skip(start).zip(sequence.iter()).all(|((action, _), step)| action == step)
+    }
+
+    // Like matches, but clears the buffer so the combo fires once
+    pub fn consume(&mut self, sequence: &[&str]) -> bool {
+        let matched = self.matches(sequence);
+        if matched {
+            self.entries.clear();
+        }
+        matched
+    }
+
+    pub fn entries(&self) -> Vec<&str> {
+        self.entries.iter().map(|(action, _)| action.as_str()).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self::new(500.0, 16)
+    }
+}
+
+// Joystick axis used as it is for an axis value (-1.0 - 1.0)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnalogAxis {
+    pub joystick: u32,
+    pub axis: joystick::Axis,
+    pub inverted: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AxisBinding {
+    pub negative: Vec<InputBinding>,
+    pub positive: Vec<InputBinding>,
+    pub analog: Vec<AnalogAxis>,
+}
+
+// Device state the bindings are checked against, Scene implements it
+pub trait InputSource {
+    fn key_down(&self, key: Key) -> bool;
+    fn mouse_down(&self, button: mouse::Button) -> bool;
+    fn joystick_button_down(&self, joystick: u32, button: u32) -> bool;
+    // -100.0 - 100.0 like SFML
+    fn joystick_axis(&self, joystick: u32, axis: joystick::Axis) -> f32;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputConfigError {
+    Io(String),
+    Parse { line: usize, text: String },
+}
+
+impl fmt::Display for InputConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputConfigError::Io(reason) => write!(f, "Can't read input config: {}", reason),
+            InputConfigError::Parse { line, text } => write!(f, "Invalid input binding at line {}: {}", line, text),
+        }
+    }
+}
+
+impl std::error::Error for InputConfigError {}
+
+#[derive(Debug, Clone)]
+pub struct InputMap {
+    actions: HashMap<String, Vec<InputBinding>>,
+    axes: HashMap<String, AxisBinding>,
+    // Joystick axis position (0 - 100) that counts as a press for JoystickAxis bindings
+    pub axis_threshold: f32,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        InputMap {
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+            axis_threshold: 50.0,
+        }
+    }
+
+    // Actions can have any number of bindings, any of them triggers the action
+    pub fn bind(&mut self, action: &str, binding: InputBinding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: InputBinding) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.retain(|existing| *existing != binding);
+        }
+    }
+
+    // Replaces one binding in place, returns false if the action didn't have it
+    pub fn rebind(&mut self, action: &str, old: InputBinding, new: InputBinding) -> bool {
+        let bindings = match self.actions.get_mut(action) {
+            Some(bindings) => bindings,
+            None => return false,
+        };
+        match bindings.iter().position(|existing| *existing == old) {
+            Some(index) => {
+                if bindings.contains(&new) {
+                    bindings.remove(index);
+                } else {
+                    bindings[index] = new;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_action(&mut self, action: &str) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.clear();
+        }
+    }
+
+    pub fn remove_action(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[InputBinding] {
+        self.actions.get(action).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn actions(&self) -> Vec<&String> {
+        let mut actions: Vec<&String> = self.actions.keys().collect();
+        actions.sort();
+        actions
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, negative: InputBinding, positive: InputBinding) {
+        let binding = self.axes.entry(axis.to_string()).or_default();
+        if !binding.negative.contains(&negative) {
+            binding.negative.push(negative);
+        }
+        if !binding.positive.contains(&positive) {
+            binding.positive.push(positive);
+        }
+    }
+
+    pub fn bind_analog_axis(&mut self, axis: &str, joystick: u32, joystick_axis: joystick::Axis, inverted: bool) {
+        let analog = AnalogAxis { joystick, axis: joystick_axis, inverted };
+        let binding = self.axes.entry(axis.to_string()).or_default();
+        if !binding.analog.contains(&analog) {
+            binding.analog.push(analog);
+        }
+    }
+
+    pub fn axis_binding(&self, axis: &str) -> Option<&AxisBinding> {
+        self.axes.get(axis)
+    }
+
+    pub fn axis_binding_mut(&mut self, axis: &str) -> Option<&mut AxisBinding> {
+        self.axes.get_mut(axis)
+    }
+
+    pub fn remove_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    pub fn axes(&self) -> Vec<&String> {
+        let mut axes: Vec<&String> = self.axes.keys().collect();
+        axes.sort();
+        axes
+    }
+
+    pub fn is_binding_down(&self, source: &impl InputSource, binding: &InputBinding) -> bool {
+        match *binding {
+            InputBinding::Key(key) => source.key_down(key),
+            InputBinding::Mouse(button) => source.mouse_down(button),
+            InputBinding::JoystickButton { joystick, button } => source.joystick_button_down(joystick, button),
+            InputBinding::JoystickAxis { joystick, axis, direction } => {
+                source.joystick_axis(joystick, axis) * direction.signum() >= self.axis_threshold
+            }
+        }
+    }
+
+    pub fn is_action_pressed(&self, source: &impl InputSource, action: &str) -> bool {
+        self.bindings(action).iter().any(|binding| self.is_binding_down(source, binding))
+    }
+
+    // -1.0 - 1.0, digital and analog bindings are added up
+    pub fn axis_value(&self, source: &impl InputSource, axis: &str) -> f32 {
+        let binding = match self.axes.get(axis) {
+            Some(binding) => binding,
+            None => return 0.0,
+        };
+
+        let mut value = 0.0;
+        if binding.positive.iter().any(|b| self.is_binding_down(source, b)) {
+            value += 1.0;
+        }
+        if binding.negative.iter().any(|b| self.is_binding_down(source, b)) {
+            value -= 1.0;
+        }
+        for analog in binding.analog.iter() {
+            let position = source.joystick_axis(analog.joystick, analog.axis) / 100.0;
+            value += if analog.inverted { -position } else { position };
+        }
+        value.clamp(-1.0, 1.0)
+    }
+
+    // One binding per line:
+    //   action <name> <binding>
+    //   axis <name> negative|positive <binding>
+    //   axis <name> analog <joystick> <axis> [inverted]
+    // where <binding> is "key <Key>", "mouse <Button>", "joybutton <joystick> <button>" or "joyaxis <joystick> <axis> +|-"
+    pub fn to_config_string(&self) -> String {
+        let mut out = String::new();
+        for action in self.actions() {
+            for binding in self.bindings(action) {
+                out.push_str(&format!("action {} {}\n", action, binding_to_string(binding)));
+            }
+        }
+        for axis in self.axes() {
+            let binding = &self.axes[axis];
+            for negative in binding.negative.iter() {
+                out.push_str(&format!("axis {} negative {}\n", axis, binding_to_string(negative)));
+            }
+            for positive in binding.positive.iter() {
+                out.push_str(&format!("axis {} positive {}\n", axis, binding_to_string(positive)));
+            }
+            for analog in binding.analog.iter() {
+                let inverted = if analog.inverted { " inverted" } else { "" };
+                out.push_str(&format!("axis {} analog {} {:?}{}\n", axis, analog.joystick, analog.axis, inverted));
+            }
+        }
+        out
+    }
+
+    pub fn from_config_str(config: &str) -> Result<Self, InputConfigError> {
+        let mut map = InputMap::new();
+        for (index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || InputConfigError::Parse { line: index + 1, text: line.to_string() };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["action", name, binding @ ..] => {
+                    let binding = parse_binding(binding).ok_or_else(error)?;
+                    map.bind(name, binding);
+                }
+                ["axis", name, "analog", joystick, axis, rest @ ..] => {
+                    let joystick = joystick.parse().map_err(|_| error())?;
+                    let axis = parse_joystick_axis(axis).ok_or_else(error)?;
+                    let inverted = match rest {
+                        [] => false,
+                        ["inverted"] => true,
+                        _ => return Err(error()),
+                    };
+                    map.bind_analog_axis(name, joystick, axis, inverted);
+                }
+                ["axis", name, side, binding @ ..] => {
+                    let binding = parse_binding(binding).ok_or_else(error)?;
+                    let axis = map.axes.entry(name.to_string()).or_default();
+                    match *side {
+                        "negative" => axis.negative.push(binding),
+                        "positive" => axis.positive.push(binding),
+                        _ => return Err(error()),
+                    }
+                }
+                _ => return Err(error()),
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_config_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, InputConfigError> {
+        let config = fs::read_to_string(path).map_err(|error| InputConfigError::Io(error.to_string()))?;
+        InputMap::from_config_str(&config)
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn binding_to_string(binding: &InputBinding) -> String {
+    match binding {
+        InputBinding::Key(key) => format!("key {:?}", key),
+        InputBinding::Mouse(button) => format!("mouse {:?}", button),
+        InputBinding::JoystickButton { joystick, button } => format!("joybutton {} {}", joystick, button),
+        InputBinding::JoystickAxis { joystick, axis, direction } => {
+            let sign = if *direction < 0.0 { "-" } else { "+" };
+            format!("joyaxis {} {:?} {}", joystick, axis, sign)
+        }
+    }
+}
+
+fn parse_binding(words: &[&str]) -> Option<InputBinding> {
+    match words {
+        ["key", key] => parse_key(key).map(InputBinding::Key),
+        ["mouse", button] => parse_mouse_button(button).map(InputBinding::Mouse),
+        ["joybutton", joystick, button] => Some(InputBinding::JoystickButton {
+            joystick: joystick.parse().ok()?,
+            button: button.parse().ok()?,
+        }),
+        ["joyaxis", joystick, axis, sign] => Some(InputBinding::JoystickAxis {
+            joystick: joystick.parse().ok()?,
+            axis: parse_joystick_axis(axis)?,
+            direction: match *sign {
+                "+" => 1.0,
+                "-" => -1.0,
+                _ => return None,
+            },
+        }),
+        _ => None,
+    }
+}
+
+pub fn parse_key(name: &str) -> Option<Key> {
+    KEYS.iter().copied().find(|key| format!("{:?}", key) == name)
+}
+
+pub fn parse_mouse_button(name: &str) -> Option<mouse::Button> {
+    [
+        mouse::Button::Left,
+        mouse::Button::Right,
+        mouse::Button::Middle,
+        mouse::Button::XButton1,
+        mouse::Button::XButton2,
+    ]
+    .into_iter()
+    .find(|button| format!("{:?}", button) == name)
+}
+
+pub fn parse_joystick_axis(name: &str) -> Option<joystick::Axis> {
+    [
+        joystick::Axis::X,
+        joystick::Axis::Y,
+        joystick::Axis::Z,
+        joystick::Axis::R,
+        joystick::Axis::U,
+        joystick::Axis::V,
+        joystick::Axis::PovX,
+        joystick::Axis::PovY,
+    ]
+    .into_iter()
+    .find(|axis| format!("{:?}", axis) == name)
+}
+
+// Every key SFML knows, names are the Debug names
+pub const KEYS: [Key; 101] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::Escape, Key::LControl, Key::LShift, Key::LAlt, Key::LSystem, Key::RControl, Key::RShift, Key::RAlt,
+    Key::RSystem, Key::Menu, Key::LBracket, Key::RBracket, Key::Semicolon, Key::Comma, Key::Period, Key::Quote,
+    Key::Slash, Key::Backslash, Key::Tilde, Key::Equal, Key::Hyphen, Key::Space, Key::Enter, Key::Backspace,
+    Key::Tab, Key::PageUp, Key::PageDown, Key::End, Key::Home, Key::Insert, Key::Delete, Key::Add, Key::Subtract,
+    Key::Multiply, Key::Divide, Key::Left, Key::Right, Key::Up, Key::Down, Key::Numpad0, Key::Numpad1,
+    Key::Numpad2, Key::Numpad3, Key::Numpad4, Key::Numpad5, Key::Numpad6, Key::Numpad7, Key::Numpad8,
+    Key::Numpad9, Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+    Key::F11, Key::F12, Key::F13, Key::F14, Key::F15, Key::Pause,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Player one
+action jump key Space
+action jump joybutton 0 1
+action fire mouse Left
+
+axis move_x negative key A
+axis move_x positive key D
+axis move_x positive joyaxis 0 X +
+axis move_y analog 1 Y inverted
+";
+
+    struct Pad {
+        keys: Vec<Key>,
+        y: f32,
+    }
+
+    impl InputSource for Pad {
+        fn key_down(&self, key: Key) -> bool {
+            self.keys.contains(&key)
+        }
+        fn mouse_down(&self, _button: mouse::Button) -> bool {
+            false
+        }
+        fn joystick_button_down(&self, _joystick: u32, _button: u32) -> bool {
+            false
+        }
+        fn joystick_axis(&self, joystick: u32, axis: joystick::Axis) -> f32 {
+            if joystick == 1 && axis == joystick::Axis::Y { self.y } else { 0.0 }
+        }
+    }
+
+    #[test]
+    fn config_reads_actions_and_axes() {
+        let map = InputMap::from_config_str(CONFIG).unwrap();
+        assert_eq!(map.bindings("jump"), &[InputBinding::Key(Key::Space), InputBinding::JoystickButton { joystick: 0, button: 1 }]);
+        assert_eq!(map.bindings("fire"), &[InputBinding::Mouse(mouse::Button::Left)]);
+
+        let move_x = map.axis_binding("move_x").unwrap();
+        assert_eq!(move_x.negative, vec![InputBinding::Key(Key::A)]);
+        assert_eq!(
+            move_x.positive,
+            vec![InputBinding::Key(Key::D), InputBinding::JoystickAxis { joystick: 0, axis: joystick::Axis::X, direction: 1.0 }]
+        );
+        let move_y = map.axis_binding("move_y").unwrap();
+        assert_eq!(move_y.analog, vec![AnalogAxis { joystick: 1, axis: joystick::Axis::Y, inverted: true }]);
+
+        let pad = Pad { keys: vec![Key::A, Key::Space], y: 50.0 };
+        assert!(map.is_action_pressed(&pad, "jump"));
+        assert!(!map.is_action_pressed(&pad, "fire"));
+        assert_eq!(map.axis_value(&pad, "move_x"), -1.0);
+        assert_eq!(map.axis_value(&pad, "move_y"), -0.5);
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let map = InputMap::from_config_str(CONFIG).unwrap();
+        let reloaded = InputMap::from_config_str(&map.to_config_string()).unwrap();
+        for action in map.actions() {
+            assert_eq!(reloaded.bindings(action), map.bindings(action));
+        }
+        for axis in map.axes() {
+            assert_eq!(reloaded.axis_binding(axis), map.axis_binding(axis));
+        }
+        assert_eq!((reloaded.actions().len(), reloaded.axes().len()), (2, 2));
+    }
+
+    #[test]
+    fn config_rejects_unknown_names_with_the_line() {
+        let config = "action jump key Space\n\naction dash key Hyperspace\n";
+        assert_eq!(
+            InputMap::from_config_str(config).unwrap_err(),
+            InputConfigError::Parse { line: 3, text: "action dash key Hyperspace".to_string() }
+        );
+        assert!(InputMap::from_config_str("axis move_x sideways key A").is_err());
+        assert!(InputMap::from_config_str("axis move_y analog 0 Q").is_err());
+        assert_eq!(parse_key("Unknown"), None);
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use sfml::{
+    audio::SoundBuffer,
+    graphics::{Image, RcTexture},
+};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssetKind {
+    Texture,
+    Font,
+    Sound,
+}
+
+#[derive(Debug, Clone)]
+struct LoadJob {
+    id: String,
+    path: String,
+    kind: AssetKind,
+}
+
+// What the workers send back. Images and sounds are decoded on the workers, fonts are only read
+// since FreeType parses them lazily. Textures, fonts and sound buffers are built from this on the main thread.
+enum Decoded {
+    Pixels { width: u32, height: u32, pixels: Vec<u8> },
+    FontData(Vec<u8>),
+    Samples { samples: Vec<i16>, channel_count: u32, sample_rate: u32 },
+}
+
+struct LoadMessage {
+    id: String,
+    path: String,
+    bytes: u64,
+    result: Result<Decoded, AssetError>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadProgress {
+    pub loaded_items: usize,
+    pub failed_items: usize,
+    pub total_items: usize,
+    pub loaded_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl LoadProgress {
+    // 0.0 - 1.0, by bytes when sizes are known, otherwise by items
+    pub fn fraction(&self) -> f32 {
+        if self.total_bytes > 0 {
+            return (self.loaded_bytes as f64 / self.total_bytes as f64).min(1.0) as f32;
+        }
+        if self.total_items == 0 {
+            return 1.0;
+        }
+        (self.loaded_items + self.failed_items) as f32 / self.total_items as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.loaded_items + self.failed_items >= self.total_items
+    }
+}
+
+fn decode(job: &LoadJob, sources: &AssetSources) -> (u64, Result<Decoded, AssetError>) {
+    let data = match sources.read(&job.path) {
+        Ok(data) => data,
+        Err(error) => return (0, Err(error)),
+    };
+    let bytes = data.len() as u64;
+    let format_error = || AssetError::Format { path: job.path.clone() };
+
+    let result = match job.kind {
+        AssetKind::Texture => Image::from_memory(&data).ok_or_else(format_error).map(|image| Decoded::Pixels {
+            width: image.size().x,
+            height: image.size().y,
+            pixels: image.pixel_data().to_vec(),
+        }),
+        AssetKind::Sound => SoundBuffer::from_memory(&data).map_err(|_| format_error()).map(|buffer| Decoded::Samples {
+            samples: buffer.samples().to_vec(),
+            channel_count: buffer.channel_count(),
+            sample_rate: buffer.sample_rate(),
+        }),
+        // FreeType reads the face lazily, keeping the bytes is all the work there is
+        AssetKind::Font => Ok(Decoded::FontData(data)),
+    };
+    (bytes, result)
+}
+
+pub struct AssetLoader {
+    jobs: Vec<LoadJob>,
+    worker_count: usize,
+    receiver: Option<Receiver<LoadMessage>>,
+    workers: Vec<JoinHandle<()>>,
+    progress: LoadProgress,
+}
+
+impl AssetLoader {
+    pub fn new(worker_count: usize) -> Self {
+        AssetLoader {
+            jobs: Vec::new(),
+            worker_count: worker_count.max(1),
+            receiver: None,
+            workers: Vec::new(),
+            progress: LoadProgress::default(),
+        }
+    }
+
+    fn queue(&mut self, id: &str, path: &str, kind: AssetKind) {
+        if self.receiver.is_some() {
+            println!("Loader already started, {} not queued", id);
+            return;
+        }
+        self.progress.total_items += 1;
+        self.jobs.push(LoadJob { id: id.to_string(), path: path.to_string(), kind });
+    }
+
+    pub fn queue_texture(&mut self, id: &str, path: &str) {
+        self.queue(id, path, AssetKind::Texture);
+    }
+
+    pub fn queue_font(&mut self, id: &str, path: &str) {
+        self.queue(id, path, AssetKind::Font);
+    }
+
+    pub fn queue_sound(&mut self, id: &str, path: &str) {
+        self.queue(id, path, AssetKind::Sound);
+    }
+
+    // Paths are looked up in the given sources, normally the ones of the asset server
+    pub fn start(&mut self, sources: AssetSources) {
+        if self.receiver.is_some() {
+            return;
+        }
+
+        // Unreadable files count as 0 bytes, the worker reports the error
+        self.progress.total_bytes = self.jobs.iter().map(|job| sources.size(&job.path).unwrap_or(0)).sum();
+        let sources = Arc::new(sources);
+
+        let (sender, receiver): (Sender<LoadMessage>, Receiver<LoadMessage>) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(self.jobs.drain(..).collect::<VecDeque<LoadJob>>()));
+        for _ in 0..self.worker_count {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let sources = sources.clone();
+            self.workers.push(thread::spawn(move || loop {
+                let job = match queue.lock() {
+                    Ok(mut queue) => queue.pop_front(),
+                    Err(_) => None,
+                };
+                let job = match job {
+                    Some(job) => job,
+                    None => break,
+                };
+                let (bytes, result) = decode(&job, &sources);
+                let message = LoadMessage { id: job.id, path: job.path, bytes, result };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }));
+        }
+        self.receiver = Some(receiver);
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_started() && self.progress.is_finished()
+    }
+
+    // Creates the SFML objects for everything decoded so far, must be called from the main thread
+    pub fn poll(&mut self, assets: &mut AssetServer) -> LoadProgress {
+        let receiver = match &self.receiver {
+            Some(receiver) => receiver,
+            None => return self.progress,
+        };
+
+        while let Ok(message) = receiver.try_recv() {
+            self.progress.loaded_bytes += message.bytes;
+            let result = message.result.and_then(|decoded| upload(assets, &message.id, &message.path, decoded));
+            match result {
+                Ok(()) => self.progress.loaded_items += 1,
+                Err(error) => {
+                    assets.record_failure(&message.id, error);
+                    self.progress.failed_items += 1;
+                }
+            }
+        }
+
+        if self.progress.is_finished() {
+            for worker in self.workers.drain(..) {
+                let _ = worker.join();
+            }
+        }
+        self.progress
+    }
+}
+
+fn upload(assets: &mut AssetServer, id: &str, path: &str, decoded: Decoded) -> Result<(), AssetError> {
+    let format_error = || AssetError::Format { path: path.to_string() };
+    match decoded {
+        Decoded::Pixels { width, height, pixels } => {
+            let mut texture = RcTexture::new().ok_or_else(format_error)?;
+            if !texture.create(width, height) {
+                return Err(format_error());
+            }
+            // Pixels come from an SFML image, so the size always matches the texture
+            unsafe { texture.update_from_pixels(&pixels, width, height, 0, 0) };
+            assets.insert_texture(id, path, texture);
+        }
+        Decoded::FontData(data) => {
+            assets.insert_font_from_memory(id, path, data)?;
+        }
+        Decoded::Samples { samples, channel_count, sample_rate } => {
+            let sound = SoundBuffer::from_samples(&samples, channel_count, sample_rate).map_err(|_| format_error())?;
+            assets.insert_sound(id, path, sound);
+        }
+    }
+    Ok(())
+}
+
+impl Default for AssetLoader {
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
@@ -4,6 +4,22 @@ pub mod game;
 pub  mod  scene;
 pub mod entity;
 pub mod physical;
+pub mod tween;
+pub mod skeleton;
+pub mod assets;
+pub mod loader;
+pub mod pack;
+pub mod atlas;
+pub mod input;
+pub mod replay;
+pub mod camera;
+pub mod batch;
+pub mod postfx;
+pub mod tilemap;
+pub mod tiled;
+pub mod autotile;
+pub mod parallax;
+pub mod particles;
 
 
 
@@ -14,5 +30,20 @@ pub mod prelude {
     pub use super::scene::*;
     pub use super::entity::*;
     pub use super::physical::*;
+    pub use super::tween::*;
+    pub use super::skeleton::*;
+    pub use super::assets::*;
+    pub use super::loader::*;
+    pub use super::atlas::*;
+    pub use super::input::*;
+    pub use super::replay::*;
+    pub use super::camera::*;
+    pub use super::batch::*;
+    pub use super::postfx::*;
+    pub use super::tilemap::*;
+    pub use super::tiled::*;
+    pub use super::autotile::*;
+    pub use super::parallax::*;
+    pub use super::particles::*;
 }
 
@@ -0,0 +1,523 @@
+// Single file asset pack.
+//
+// Layout (little endian):
+//   magic "GMPK", version u16, entry count u32
+//   index, per entry: name length u16, name (utf8, '/' separated), offset u64,
+//                     stored size u64, original size u64, compression u8, crc32 u32
+//   entry data
+//
+// The checksum is computed over the original (uncompressed) bytes.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"GMPK";
+const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Lzss,
+}
+
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lzss => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lzss),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PackError {
+    Io(io::Error),
+    InvalidFormat(String),
+    NotFound(String),
+    Checksum(String),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackError::Io(error) => write!(f, "{}", error),
+            PackError::InvalidFormat(reason) => write!(f, "Invalid pack: {}", reason),
+            PackError::NotFound(name) => write!(f, "Not in pack: {}", name),
+            PackError::Checksum(name) => write!(f, "Checksum mismatch: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for PackError {}
+
+impl From<io::Error> for PackError {
+    fn from(error: io::Error) -> Self {
+        PackError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PackEntry {
+    pub offset: u64,
+    pub stored_size: u64,
+    pub original_size: u64,
+    pub compression: Compression,
+    pub crc32: u32,
+}
+
+// Index of an opened pack, entries are read from the file on demand
+#[derive(Debug, Clone)]
+pub struct AssetPack {
+    path: PathBuf,
+    entries: HashMap<String, PackEntry>,
+}
+
+// Pack entry names always use '/' and have no leading "./"
+pub fn normalize_name(name: &str) -> String {
+    let name = name.replace('\\', "/");
+    let name = name.trim_start_matches("./");
+    name.trim_start_matches('/').to_string()
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl AssetPack {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PackError> {
+        let mut file = File::open(path.as_ref())?;
+        // Sizes in the index are checked against it before anything is allocated
+        let file_len = file.metadata()?.len();
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(PackError::InvalidFormat("bad magic".to_string()));
+        }
+        let version = read_u16(&mut file)?;
+        if version != VERSION {
+            return Err(PackError::InvalidFormat(format!("unsupported version {}", version)));
+        }
+
+        let count = read_u32(&mut file)?;
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let name_len = read_u16(&mut file)? as usize;
+            if name_len as u64 > file_len {
+                return Err(PackError::InvalidFormat("entry name longer than the pack".to_string()));
+            }
+            let mut name = vec![0u8; name_len];
+            file.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| PackError::InvalidFormat("entry name is not utf8".to_string()))?;
+
+            let offset = read_u64(&mut file)?;
+            let stored_size = read_u64(&mut file)?;
+            let original_size = read_u64(&mut file)?;
+            let mut compression = [0u8; 1];
+            file.read_exact(&mut compression)?;
+            let compression = Compression::from_byte(compression[0])
+                .ok_or_else(|| PackError::InvalidFormat(format!("unknown compression for {}", name)))?;
+            let crc32 = read_u32(&mut file)?;
+
+            let fits = offset.checked_add(stored_size).is_some_and(|end| end <= file_len);
+            // LZSS at best turns 2 stored bytes into MAX_MATCH bytes
+            let max_original = match compression {
+                Compression::None => stored_size,
+                Compression::Lzss => stored_size.saturating_mul(MAX_MATCH as u64 / 2),
+            };
+            if !fits || original_size > max_original {
+                return Err(PackError::InvalidFormat(format!("bad size for {}", name)));
+            }
+
+            entries.insert(name, PackEntry { offset, stored_size, original_size, compression, crc32 });
+        }
+
+        Ok(AssetPack {
+            path: path.as_ref().to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(&normalize_name(name))
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&PackEntry> {
+        self.entries.get(&normalize_name(name))
+    }
+
+    pub fn names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.entries.keys().collect();
+        names.sort();
+        names
+    }
+
+    // Reads, decompresses and verifies one entry
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, PackError> {
+        let entry = self.entry(name).ok_or_else(|| PackError::NotFound(name.to_string()))?;
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut stored = vec![0u8; entry.stored_size as usize];
+        file.read_exact(&mut stored)?;
+
+        let data = match entry.compression {
+            Compression::None => stored,
+            Compression::Lzss => lzss_decompress(&stored, entry.original_size as usize)
+                .ok_or_else(|| PackError::InvalidFormat(format!("corrupt data for {}", name)))?,
+        };
+
+        if data.len() as u64 != entry.original_size || crc32(&data) != entry.crc32 {
+            return Err(PackError::Checksum(name.to_string()));
+        }
+        Ok(data)
+    }
+}
+
+pub struct PackBuilder {
+    entries: Vec<(String, Vec<u8>, Compression)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        PackBuilder { entries: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: &str, data: Vec<u8>, compression: Compression) {
+        let name = normalize_name(name);
+        self.entries.retain(|(existing, _, _)| *existing != name);
+        self.entries.push((name, data, compression));
+    }
+
+    // Adds every file under dir, names are relative to prefix (e.g. dir "assets", prefix "assets")
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P, prefix: &str, compression: Compression) -> Result<usize, PackError> {
+        let mut added = 0;
+        let mut entries: Vec<_> = fs::read_dir(dir.as_ref())?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = if prefix.is_empty() { file_name } else { format!("{}/{}", prefix, file_name) };
+            if path.is_dir() {
+                added += self.add_dir(&path, &name, compression)?;
+            } else {
+                self.add(&name, fs::read(&path)?, compression);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), PackError> {
+        // Compress first, already compressed formats (png, ogg) are stored as they are if it doesn't help
+        let mut blobs: Vec<(&String, Vec<u8>, Compression, u64, u32)> = Vec::new();
+        for (name, data, compression) in self.entries.iter() {
+            let checksum = crc32(data);
+            let (stored, compression) = match compression {
+                Compression::Lzss => {
+                    let compressed = lzss_compress(data);
+                    if compressed.len() < data.len() {
+                        (compressed, Compression::Lzss)
+                    } else {
+                        (data.clone(), Compression::None)
+                    }
+                }
+                Compression::None => (data.clone(), Compression::None),
+            };
+            blobs.push((name, stored, compression, data.len() as u64, checksum));
+        }
+
+        let index_size: u64 = blobs.iter().map(|(name, ..)| 2 + name.len() as u64 + 8 + 8 + 8 + 1 + 4).sum();
+        let mut offset = 4 + 2 + 4 + index_size;
+
+        let mut out = io::BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&(blobs.len() as u32).to_le_bytes())?;
+        for (name, stored, compression, original_size, checksum) in blobs.iter() {
+            out.write_all(&(name.len() as u16).to_le_bytes())?;
+            out.write_all(name.as_bytes())?;
+            out.write_all(&offset.to_le_bytes())?;
+            out.write_all(&(stored.len() as u64).to_le_bytes())?;
+            out.write_all(&original_size.to_le_bytes())?;
+            out.write_all(&[compression.to_byte()])?;
+            out.write_all(&checksum.to_le_bytes())?;
+            offset += stored.len() as u64;
+        }
+        for (_, stored, ..) in blobs.iter() {
+            out.write_all(stored)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for PackBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// LZSS: a flag byte precedes every 8 tokens, bit set = literal byte,
+// bit clear = back reference of 2 bytes (12 bit distance, 4 bit length - MIN_MATCH)
+const WINDOW: usize = 4096;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 15 + MIN_MATCH;
+
+pub fn lzss_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2);
+    // Last position of every 3 byte prefix, keeps the search cheap
+    let mut last_seen: HashMap<[u8; 3], Vec<usize>> = HashMap::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let flag_index = out.len();
+        out.push(0u8);
+        for bit in 0..8 {
+            if pos >= data.len() {
+                break;
+            }
+
+            let mut best_len = 0;
+            let mut best_dist = 0;
+            if pos + MIN_MATCH <= data.len() {
+                let key = [data[pos], data[pos + 1], data[pos + 2]];
+                if let Some(candidates) = last_seen.get(&key) {
+                    for &start in candidates.iter().rev() {
+                        let dist = pos - start;
+                        if dist > WINDOW {
+                            break;
+                        }
+                        let mut len = 0;
+                        while len < MAX_MATCH && pos + len < data.len() && data[start + len] == data[pos + len] {
+                            len += 1;
+                        }
+                        if len > best_len {
+                            best_len = len;
+                            best_dist = dist;
+                        }
+                        if len == MAX_MATCH {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            let advance = if best_len >= MIN_MATCH {
+                let token = (((best_dist - 1) as u16) << 4) | (best_len - MIN_MATCH) as u16;
+                out.extend_from_slice(&token.to_le_bytes());
+                best_len
+            } else {
+                out[flag_index] |= 1 << bit;
+                out.push(data[pos]);
+                1
+            };
+
+            for i in pos..pos + advance {
+                if i + MIN_MATCH <= data.len() {
+                    let candidates = last_seen.entry([data[i], data[i + 1], data[i + 2]]).or_default();
+                    candidates.push(i);
+                    // Old positions are out of the window anyway
+                    if candidates.len() > 64 {
+                        candidates.drain(..32);
+                    }
+                }
+            }
+            pos += advance;
+        }
+    }
+    out
+}
+
+pub fn lzss_decompress(data: &[u8], original_size: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(original_size);
+    let mut pos = 0;
+    while pos < data.len() && out.len() < original_size {
+        let flags = data[pos];
+        pos += 1;
+        for bit in 0..8 {
+            if pos >= data.len() || out.len() >= original_size {
+                break;
+            }
+            if flags & (1 << bit) != 0 {
+                out.push(data[pos]);
+                pos += 1;
+            } else {
+                let token = u16::from_le_bytes([data[pos], *data.get(pos + 1)?]);
+                pos += 2;
+                let dist = (token >> 4) as usize + 1;
+                let len = (token & 0xF) as usize + MIN_MATCH;
+                if dist > out.len() {
+                    return None;
+                }
+                let start = out.len() - dist;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+    if out.len() == original_size {
+        Some(out)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn sample_data() -> Vec<u8> {
+        // Repeated runs with some noise, so both literals and back references show up
+        let mut seed = 7u32;
+        let mut data = Vec::new();
+        for i in 0..20_000u32 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            data.push(if i % 64 < 40 { (i % 7) as u8 } else { (seed >> 16) as u8 });
+        }
+        data
+    }
+
+    fn temp_pack(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("motor_game_{}_{}.pack", name, std::process::id()))
+    }
+
+    #[test]
+    fn crc32_matches_reference_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn lzss_round_trips() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"abcabcabcabcabcabcabcabc".to_vec(),
+            vec![0u8; 10_000],
+            (0..=255u8).cycle().take(5000).collect(),
+            sample_data(),
+        ];
+        for input in inputs {
+            let compressed = lzss_compress(&input);
+            assert_eq!(lzss_decompress(&compressed, input.len()), Some(input.clone()));
+        }
+    }
+
+    #[test]
+    fn lzss_compresses_repetitive_data() {
+        let data = vec![42u8; 10_000];
+        assert!(lzss_compress(&data).len() < data.len() / 5);
+    }
+
+    #[test]
+    fn lzss_rejects_corrupt_data() {
+        // Back reference before any output
+        assert_eq!(lzss_decompress(&[0x00, 0x10, 0x00], 3), None);
+        // Token cut in half
+        assert_eq!(lzss_decompress(&[0x00, 0x10], 3), None);
+        let compressed = lzss_compress(b"hello hello hello");
+        assert_eq!(lzss_decompress(&compressed, 100), None);
+    }
+
+    #[test]
+    fn pack_round_trips() {
+        let path = temp_pack("round_trip");
+        let mut builder = PackBuilder::new();
+        builder.add("assets/a.txt", b"hello hello hello hello".to_vec(), Compression::Lzss);
+        builder.add("./assets\\b.bin", sample_data(), Compression::None);
+        builder.add("assets/empty", Vec::new(), Compression::Lzss);
+        builder.write(&path).unwrap();
+
+        let pack = AssetPack::open(&path).unwrap();
+        assert_eq!(pack.names(), vec!["assets/a.txt", "assets/b.bin", "assets/empty"]);
+        assert_eq!(pack.read("assets/a.txt").unwrap(), b"hello hello hello hello");
+        assert_eq!(pack.read("assets/b.bin").unwrap(), sample_data());
+        assert_eq!(pack.read("assets/empty").unwrap(), Vec::<u8>::new());
+        assert!(matches!(pack.read("assets/missing"), Err(PackError::NotFound(_))));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn pack_detects_corrupt_entry() {
+        let path = temp_pack("corrupt");
+        let mut builder = PackBuilder::new();
+        builder.add("data", vec![1u8; 64], Compression::None);
+        builder.write(&path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(&path, &bytes).unwrap();
+
+        let pack = AssetPack::open(&path).unwrap();
+        assert!(matches!(pack.read("data"), Err(PackError::Checksum(_))));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn pack_rejects_sizes_past_the_file() {
+        let path = temp_pack("oversized");
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(b"huge");
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.push(Compression::None.to_byte());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(matches!(AssetPack::open(&path), Err(PackError::InvalidFormat(_))));
+        fs::remove_file(&path).ok();
+    }
+}
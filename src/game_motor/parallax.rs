@@ -0,0 +1,209 @@
+use sfml::{
+    graphics::{BlendMode, Color, FloatRect, PrimitiveType, RcSprite, RcTexture, RenderStates, RenderTarget, Transform, Vertex},
+    system::Vector2f,
+};
+
+use super::prelude::*;
+
+// Background texture that moves slower (or faster) than the world, drawn through every camera
+#[derive(Clone)]
+pub struct ParallaxLayer {
+    pub name: String,
+    pub texture_id: String,
+    // 1.0 moves with the world, 0.0 stays on screen, 0.5 moves at half the camera speed
+    pub factor: Vector2f,
+    // Repeated axes fill the whole view, the texture needs set_repeated (the scene does it)
+    pub repeat_x: bool,
+    pub repeat_y: bool,
+    // Where the texture sits when the camera is at the world origin
+    pub offset: Vector2f,
+    pub scale: Vector2f,
+    // Pixels per second
+    pub scroll_speed: Vector2f,
+    pub color: Color,
+    // Entity layer it is drawn with, far behind by default
+    pub render_layer: i32,
+    pub visible: bool,
+    scroll: Vector2f,
+    // Only holds the texture, RenderStates can't take an RcTexture directly
+    sprite: Option<RcSprite>,
+}
+
+impl ParallaxLayer {
+    pub fn new(name: &str, texture_id: &str, factor: f32) -> Self {
+        ParallaxLayer {
+            name: name.to_string(),
+            texture_id: texture_id.to_string(),
+            factor: Vector2f::new(factor, factor),
+            repeat_x: true,
+            repeat_y: false,
+            offset: Vector2f::new(0.0, 0.0),
+            scale: Vector2f::new(1.0, 1.0),
+            scroll_speed: Vector2f::new(0.0, 0.0),
+            color: Color::WHITE,
+            render_layer: -100,
+            visible: true,
+            scroll: Vector2f::new(0.0, 0.0),
+            sprite: None,
+        }
+    }
+
+    pub fn with_factor(mut self, x: f32, y: f32) -> Self {
+        self.factor = Vector2f::new(x, y);
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat_x: bool, repeat_y: bool) -> Self {
+        self.repeat_x = repeat_x;
+        self.repeat_y = repeat_y;
+        self
+    }
+
+    pub fn with_offset(mut self, x: f32, y: f32) -> Self {
+        self.offset = Vector2f::new(x, y);
+        self
+    }
+
+    pub fn with_scale(mut self, x: f32, y: f32) -> Self {
+        self.scale = Vector2f::new(x, y);
+        self
+    }
+
+    pub fn with_scroll_speed(mut self, x: f32, y: f32) -> Self {
+        self.scroll_speed = Vector2f::new(x, y);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_render_layer(mut self, render_layer: i32) -> Self {
+        self.render_layer = render_layer;
+        self
+    }
+
+    pub fn set_texture(&mut self, texture: &RcTexture) {
+        self.sprite = Some(RcSprite::with_texture(texture));
+    }
+
+    pub fn has_texture(&self) -> bool {
+        self.sprite.is_some()
+    }
+
+    pub fn scroll(&self) -> Vector2f {
+        self.scroll
+    }
+
+    // Auto scroll, wrapped on repeated axes so it never loses precision
+    pub fn update(&mut self, delta_ms: f32) {
+        self.scroll += self.scroll_speed * (delta_ms / 1000.0);
+        if let Some(size) = self.scaled_size() {
+            self.wrap_scroll(size);
+        }
+    }
+
+    fn wrap_scroll(&mut self, size: Vector2f) {
+        if self.repeat_x && size.x > 0.0 {
+            self.scroll.x %= size.x;
+        }
+        if self.repeat_y && size.y > 0.0 {
+            self.scroll.y %= size.y;
+        }
+    }
+
+    fn scaled_size(&self) -> Option<Vector2f> {
+        let size = self.sprite.as_ref()?.texture()?.size();
+        Some(Vector2f::new(size.x as f32 * self.scale.x, size.y as f32 * self.scale.y))
+    }
+
+    // Texture origin in world coordinates for a camera centered at the given point
+    pub fn origin(&self, camera_center: Vector2f) -> Vector2f {
+        self.offset
+            + self.scroll
+            + Vector2f::new(camera_center.x * (1.0 - self.factor.x), camera_center.y * (1.0 - self.factor.y))
+    }
+
+    // One quad over the visible area, repeated axes get texture coordinates past the texture size
+    pub fn draw(&self, target: &mut dyn RenderTarget, camera_center: Vector2f, visible_area: FloatRect, stats: &mut RenderStats) {
+        if !self.visible || self.scale.x == 0.0 || self.scale.y == 0.0 {
+            return;
+        }
+        let Some(texture) = self.sprite.as_ref().and_then(|sprite| sprite.texture()) else {
+            return;
+        };
+        let size = texture.size();
+        let origin = self.origin(camera_center);
+
+        let (left, right) = match self.repeat_x {
+            true => (visible_area.left, visible_area.left + visible_area.width),
+            false => (origin.x, origin.x + size.x as f32 * self.scale.x),
+        };
+        let (top, bottom) = match self.repeat_y {
+            true => (visible_area.top, visible_area.top + visible_area.height),
+            false => (origin.y, origin.y + size.y as f32 * self.scale.y),
+        };
+        if right < visible_area.left
+            || left > visible_area.left + visible_area.width
+            || bottom < visible_area.top
+            || top > visible_area.top + visible_area.height
+        {
+            return;
+        }
+
+        let corner = |x: f32, y: f32| {
+            let coords = Vector2f::new((x - origin.x) / self.scale.x, (y - origin.y) / self.scale.y);
+            Vertex::new(Vector2f::new(x, y), self.color, coords)
+        };
+        let vertices = [
+            corner(left, top),
+            corner(right, top),
+            corner(right, bottom),
+            corner(left, top),
+            corner(right, bottom),
+            corner(left, bottom),
+        ];
+        let states = RenderStates::new(BlendMode::ALPHA, Transform::IDENTITY, Some(texture), None);
+        target.draw_primitives(&vertices, PrimitiveType::TRIANGLES, &states);
+        stats.draw_calls += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_follows_the_camera_by_factor() {
+        let camera = Vector2f::new(200.0, -100.0);
+        let still = ParallaxLayer::new("sky", "sky", 0.0).with_offset(5.0, 6.0);
+        assert_eq!(still.origin(camera), Vector2f::new(205.0, -94.0));
+        let half = ParallaxLayer::new("hills", "hills", 0.5).with_offset(5.0, 6.0);
+        assert_eq!(half.origin(camera), Vector2f::new(105.0, -44.0));
+        let world = ParallaxLayer::new("ground", "ground", 1.0).with_offset(5.0, 6.0);
+        assert_eq!(world.origin(camera), Vector2f::new(5.0, 6.0));
+        let mixed = ParallaxLayer::new("rain", "rain", 1.0).with_factor(0.5, 0.0);
+        assert_eq!(mixed.origin(camera), Vector2f::new(100.0, -100.0));
+    }
+
+    #[test]
+    fn scroll_moves_the_origin_and_wraps_on_repeated_axes() {
+        let mut layer = ParallaxLayer::new("clouds", "clouds", 0.0).with_scroll_speed(-30.0, 50.0).with_repeat(true, false);
+        // No texture yet, so nothing to wrap by
+        layer.update(1000.0);
+        assert_eq!(layer.scroll(), Vector2f::new(-30.0, 50.0));
+        assert_eq!(layer.origin(Vector2f::new(0.0, 0.0)), Vector2f::new(-30.0, 50.0));
+
+        layer.update(3000.0);
+        layer.wrap_scroll(Vector2f::new(64.0, 32.0));
+        assert_eq!(layer.scroll(), Vector2f::new(-120.0 % 64.0, 200.0));
+
+        layer.repeat_y = true;
+        layer.wrap_scroll(Vector2f::new(64.0, 32.0));
+        assert_eq!(layer.scroll(), Vector2f::new(-56.0, 8.0));
+        // A zero sized texture leaves the scroll alone
+        layer.wrap_scroll(Vector2f::new(0.0, 0.0));
+        assert_eq!(layer.scroll(), Vector2f::new(-56.0, 8.0));
+    }
+}
@@ -0,0 +1,468 @@
+use std::hash::Hasher;
+
+use sfml::{
+    graphics::{BlendMode, Color, FloatRect, IntRect, PrimitiveType, RcSprite, RcTexture, RenderStates, RenderTarget, Transform, Vertex},
+    system::Vector2f,
+};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmitterAnchor {
+    World(Vector2f),
+    // Center of the entity's bounds plus the offset, the emitter stops when the entity is gone
+    Entity { id: String, offset: Vector2f },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vector2f,
+    pub velocity: Vector2f,
+    pub rotation: f32,
+    pub angular_velocity: f32,
+    pub age_ms: f32,
+    pub lifetime_ms: f32,
+    // Index in the emitter regions
+    pub region: usize,
+}
+
+impl Particle {
+    // 0.0 when spawned, 1.0 when it dies
+    pub fn life(&self) -> f32 {
+        (self.age_ms / self.lifetime_ms.max(f32::EPSILON)).clamp(0.0, 1.0)
+    }
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    let channel = |a: u8, b: u8| lerp(a as f32, b as f32, t).round() as u8;
+    Color::rgba(channel(from.r, to.r), channel(from.g, to.g), channel(from.b, to.b), channel(from.a, to.a))
+}
+
+// Ranges are (min, max), values are picked uniformly. The generator is seeded so replays match.
+#[derive(Clone)]
+pub struct ParticleEmitter {
+    pub name: String,
+    pub anchor: EmitterAnchor,
+    pub texture_id: Option<String>,
+    // Sub-rects of the texture, each particle picks one. Empty uses the whole texture.
+    pub regions: Vec<IntRect>,
+    // Particles per second while emitting
+    pub rate: f32,
+    pub emitting: bool,
+    // Stops emitting after it, None emits until stopped
+    pub duration_ms: Option<f32>,
+    // Removed from the scene once it stopped and its particles are gone
+    pub remove_when_done: bool,
+    pub lifetime_ms: (f32, f32),
+    // Pixels per second, direction in degrees (0 is right, 90 is down)
+    pub speed: (f32, f32),
+    pub direction: (f32, f32),
+    // Pixels per second squared
+    pub gravity: Vector2f,
+    // Velocity lost per second, 0.0 keeps it
+    pub damping: f32,
+    // Half size of the box particles spawn in, around the anchor
+    pub spawn_extent: Vector2f,
+    pub angular_velocity: (f32, f32),
+    pub start_color: Color,
+    pub end_color: Color,
+    // Side of the particle quad in pixels
+    pub start_size: f32,
+    pub end_size: f32,
+    pub max_particles: usize,
+    pub additive: bool,
+    // Entity layer it is drawn with, above entities by default
+    pub render_layer: i32,
+    particles: Vec<Particle>,
+    position: Vector2f,
+    spawn_debt: f32,
+    elapsed_ms: f32,
+    seed: u64,
+    // Only holds the texture, RenderStates can't take an RcTexture directly
+    sprite: Option<RcSprite>,
+}
+
+impl ParticleEmitter {
+    pub fn new(name: &str, anchor: EmitterAnchor) -> Self {
+        let position = match &anchor {
+            EmitterAnchor::World(position) => *position,
+            EmitterAnchor::Entity { .. } => Vector2f::new(0.0, 0.0),
+        };
+        // Seeded from the name so emitters differ but replays still match
+        let mut hasher = StateHasher::new();
+        hasher.write_str(name);
+        ParticleEmitter {
+            name: name.to_string(),
+            anchor,
+            texture_id: None,
+            regions: Vec::new(),
+            rate: 20.0,
+            emitting: true,
+            duration_ms: None,
+            remove_when_done: false,
+            lifetime_ms: (500.0, 1000.0),
+            speed: (50.0, 100.0),
+            direction: (0.0, 360.0),
+            gravity: Vector2f::new(0.0, 0.0),
+            damping: 0.0,
+            spawn_extent: Vector2f::new(0.0, 0.0),
+            angular_velocity: (0.0, 0.0),
+            start_color: Color::WHITE,
+            end_color: Color::TRANSPARENT,
+            start_size: 8.0,
+            end_size: 8.0,
+            max_particles: 500,
+            additive: false,
+            render_layer: 1,
+            particles: Vec::new(),
+            position,
+            spawn_debt: 0.0,
+            elapsed_ms: 0.0,
+            seed: hasher.finish().max(1),
+            sprite: None,
+        }
+    }
+
+    // Only bursts, nothing is emitted over time and it goes away when its particles die
+    pub fn one_shot(name: &str, anchor: EmitterAnchor) -> Self {
+        let mut emitter = ParticleEmitter::new(name, anchor);
+        emitter.rate = 0.0;
+        emitter.remove_when_done = true;
+        emitter
+    }
+
+    pub fn with_texture(mut self, texture_id: &str, regions: Vec<IntRect>) -> Self {
+        self.texture_id = Some(texture_id.to_string());
+        self.regions = regions;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn with_duration(mut self, duration_ms: f32) -> Self {
+        self.duration_ms = Some(duration_ms);
+        self
+    }
+
+    pub fn with_lifetime(mut self, min_ms: f32, max_ms: f32) -> Self {
+        self.lifetime_ms = (min_ms, max_ms);
+        self
+    }
+
+    pub fn with_velocity(mut self, min_speed: f32, max_speed: f32, min_direction: f32, max_direction: f32) -> Self {
+        self.speed = (min_speed, max_speed);
+        self.direction = (min_direction, max_direction);
+        self
+    }
+
+    pub fn with_gravity(mut self, x: f32, y: f32) -> Self {
+        self.gravity = Vector2f::new(x, y);
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    pub fn with_spawn_extent(mut self, x: f32, y: f32) -> Self {
+        self.spawn_extent = Vector2f::new(x, y);
+        self
+    }
+
+    pub fn with_spin(mut self, min: f32, max: f32) -> Self {
+        self.angular_velocity = (min, max);
+        self
+    }
+
+    pub fn with_colors(mut self, start: Color, end: Color) -> Self {
+        self.start_color = start;
+        self.end_color = end;
+        self
+    }
+
+    pub fn with_sizes(mut self, start: f32, end: f32) -> Self {
+        self.start_size = start;
+        self.end_size = end;
+        self
+    }
+
+    pub fn with_max_particles(mut self, max_particles: usize) -> Self {
+        self.max_particles = max_particles;
+        self
+    }
+
+    pub fn with_additive(mut self, additive: bool) -> Self {
+        self.additive = additive;
+        self
+    }
+
+    pub fn with_render_layer(mut self, render_layer: i32) -> Self {
+        self.render_layer = render_layer;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed.max(1);
+        self
+    }
+
+    pub fn set_texture(&mut self, texture: &RcTexture) {
+        self.sprite = Some(RcSprite::with_texture(texture));
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn position(&self) -> Vector2f {
+        self.position
+    }
+
+    // Moves the spawn point, particles already out stay where they are
+    pub fn set_position(&mut self, position: Vector2f) {
+        self.position = position;
+    }
+
+    pub fn stop(&mut self) {
+        self.emitting = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.spawn_debt = 0.0;
+    }
+
+    // Stopped emitting and nothing is left on screen
+    pub fn is_finished(&self) -> bool {
+        (!self.emitting || self.rate <= 0.0) && self.particles.is_empty()
+    }
+
+    // Spawns right away at the current position, up to the emitter cap. Returns how many were spawned.
+    pub fn burst(&mut self, count: usize) -> usize {
+        let count = count.min(self.max_particles.saturating_sub(self.particles.len()));
+        for _ in 0..count {
+            self.spawn();
+        }
+        count
+    }
+
+    // Position is the anchor this frame, None when the anchored entity is gone.
+    // Budget is how many particles the scene still allows.
+    pub fn update(&mut self, delta_ms: f32, position: Option<Vector2f>, budget: usize) {
+        match position {
+            Some(position) => self.position = position,
+            None => self.emitting = false,
+        }
+
+        let seconds = delta_ms / 1000.0;
+        let damping = (1.0 - self.damping * seconds).max(0.0);
+        for particle in self.particles.iter_mut() {
+            particle.age_ms += delta_ms;
+            particle.velocity += self.gravity * seconds;
+            particle.velocity *= damping;
+            particle.position += particle.velocity * seconds;
+            particle.rotation += particle.angular_velocity * seconds;
+        }
+        self.particles.retain(|particle| particle.age_ms < particle.lifetime_ms);
+
+        if self.emitting {
+            self.elapsed_ms += delta_ms;
+            if self.duration_ms.map(|duration| self.elapsed_ms >= duration).unwrap_or(false) {
+                self.emitting = false;
+            }
+        }
+        if !self.emitting || self.rate <= 0.0 {
+            self.spawn_debt = 0.0;
+            return;
+        }
+
+        self.spawn_debt += self.rate * seconds;
+        let room = self.max_particles.saturating_sub(self.particles.len()).min(budget);
+        let count = (self.spawn_debt.floor() as usize).min(room);
+        self.spawn_debt -= self.spawn_debt.floor();
+        for _ in 0..count {
+            self.spawn();
+        }
+    }
+
+    // Xorshift, 0.0 - 1.0
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn random_range(&mut self, (min, max): (f32, f32)) -> f32 {
+        lerp(min, max, self.random())
+    }
+
+    fn spawn(&mut self) {
+        let offset = Vector2f::new(
+            (self.random() * 2.0 - 1.0) * self.spawn_extent.x,
+            (self.random() * 2.0 - 1.0) * self.spawn_extent.y,
+        );
+        let speed = self.random_range(self.speed);
+        let direction = self.random_range(self.direction).to_radians();
+        let region = match self.regions.len() {
+            0 => 0,
+            count => ((self.random() * count as f32) as usize).min(count - 1),
+        };
+        let particle = Particle {
+            position: self.position + offset,
+            velocity: Vector2f::new(direction.cos(), direction.sin()) * speed,
+            rotation: self.random() * 360.0,
+            angular_velocity: self.random_range(self.angular_velocity),
+            age_ms: 0.0,
+            lifetime_ms: self.random_range(self.lifetime_ms),
+            region,
+        };
+        self.particles.push(particle);
+    }
+
+    // All particles in one draw
+    pub fn draw(&self, target: &mut dyn RenderTarget, visible_area: FloatRect, stats: &mut RenderStats) {
+        if self.particles.is_empty() {
+            return;
+        }
+        let texture = self.sprite.as_ref().and_then(|sprite| sprite.texture());
+        let full_rect = texture
+            .map(|texture| IntRect::new(0, 0, texture.size().x as i32, texture.size().y as i32))
+            .unwrap_or_default();
+
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(self.particles.len() * 6);
+        for particle in self.particles.iter() {
+            let life = particle.life();
+            let half = lerp(self.start_size, self.end_size, life) / 2.0;
+            let bounds = FloatRect::new(particle.position.x - half, particle.position.y - half, half * 2.0, half * 2.0);
+            if visible_area.intersection(&bounds).is_none() {
+                continue;
+            }
+
+            let color = lerp_color(self.start_color, self.end_color, life);
+            let rect = self.regions.get(particle.region).copied().unwrap_or(full_rect);
+            let (sin, cos) = particle.rotation.to_radians().sin_cos();
+            let corner = |x: f32, y: f32, u: i32, v: i32| {
+                let position = particle.position + Vector2f::new(x * cos - y * sin, x * sin + y * cos);
+                Vertex::new(position, color, Vector2f::new(u as f32, v as f32))
+            };
+            let top_left = corner(-half, -half, rect.left, rect.top);
+            let top_right = corner(half, -half, rect.left + rect.width, rect.top);
+            let bottom_right = corner(half, half, rect.left + rect.width, rect.top + rect.height);
+            let bottom_left = corner(-half, half, rect.left, rect.top + rect.height);
+            vertices.extend_from_slice(&[top_left, top_right, bottom_right, top_left, bottom_right, bottom_left]);
+        }
+        if vertices.is_empty() {
+            return;
+        }
+
+        let blend_mode = if self.additive { BlendMode::ADD } else { BlendMode::ALPHA };
+        let states = RenderStates::new(blend_mode, Transform::IDENTITY, texture, None);
+        target.draw_primitives(&vertices, PrimitiveType::TRIANGLES, &states);
+        stats.draw_calls += 1;
+        stats.batches += 1;
+        stats.batched_sprites += (vertices.len() / 6) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_seed_follows_the_name() {
+        let anchor = EmitterAnchor::World(Vector2f::new(0.0, 0.0));
+        let seed = |name: &str| ParticleEmitter::new(name, anchor.clone()).seed;
+        assert_eq!(seed("smoke"), seed("smoke"));
+        assert_ne!(seed("smoke"), seed("sparks"));
+        assert_ne!(seed(""), 0);
+        assert_eq!(ParticleEmitter::new("smoke", anchor.clone()).with_seed(0).seed, 1);
+    }
+
+    fn emitter() -> ParticleEmitter {
+        ParticleEmitter::new("test", EmitterAnchor::World(Vector2f::new(10.0, 20.0)))
+    }
+
+    #[test]
+    fn spawn_rate_carries_fractions_over_frames() {
+        // 10 per second at 16 ms frames is 0.16 per frame, one particle lands every 6 or 7 frames
+        let mut emitter = emitter().with_rate(10.0).with_lifetime(10_000.0, 10_000.0);
+        for _ in 0..62 {
+            emitter.update(16.0, Some(Vector2f::new(10.0, 20.0)), usize::MAX);
+        }
+        assert_eq!(emitter.particle_count(), 9);
+        emitter.update(16.0, Some(Vector2f::new(10.0, 20.0)), usize::MAX);
+        assert_eq!(emitter.particle_count(), 10);
+    }
+
+    #[test]
+    fn emitter_cap_and_scene_budget_limit_spawning() {
+        let mut emitter = emitter().with_rate(1000.0).with_lifetime(10_000.0, 10_000.0).with_max_particles(30);
+        assert_eq!(emitter.burst(25), 25);
+        assert_eq!(emitter.burst(25), 5);
+        assert_eq!(emitter.particle_count(), 30);
+        emitter.update(100.0, Some(Vector2f::new(0.0, 0.0)), usize::MAX);
+        assert_eq!(emitter.particle_count(), 30);
+
+        let mut emitter = self::emitter().with_rate(1000.0).with_lifetime(10_000.0, 10_000.0);
+        emitter.update(100.0, Some(Vector2f::new(0.0, 0.0)), 7);
+        assert_eq!(emitter.particle_count(), 7);
+        emitter.update(100.0, Some(Vector2f::new(0.0, 0.0)), 0);
+        assert_eq!(emitter.particle_count(), 7);
+    }
+
+    #[test]
+    fn particles_die_after_their_lifetime() {
+        let mut emitter = emitter().with_rate(0.0).with_lifetime(100.0, 200.0);
+        emitter.burst(50);
+        emitter.update(99.0, None, 0);
+        assert_eq!(emitter.particle_count(), 50);
+        emitter.update(50.0, None, 0);
+        assert!(emitter.particles().iter().all(|particle| particle.age_ms < particle.lifetime_ms));
+        emitter.update(51.0, None, 0);
+        assert_eq!(emitter.particle_count(), 0);
+    }
+
+    #[test]
+    fn duration_stops_emitting_and_one_shots_finish() {
+        let mut emitter = emitter().with_rate(100.0).with_duration(500.0).with_lifetime(100.0, 100.0);
+        for _ in 0..5 {
+            emitter.update(100.0, Some(Vector2f::new(0.0, 0.0)), usize::MAX);
+        }
+        assert!(!emitter.emitting);
+        assert_eq!(emitter.particle_count(), 0);
+        assert!(emitter.is_finished());
+
+        let mut one_shot = ParticleEmitter::one_shot("boom", EmitterAnchor::World(Vector2f::new(0.0, 0.0))).with_lifetime(100.0, 100.0);
+        assert!(one_shot.remove_when_done);
+        one_shot.burst(10);
+        one_shot.update(50.0, Some(Vector2f::new(0.0, 0.0)), usize::MAX);
+        assert!(!one_shot.is_finished());
+        one_shot.update(50.0, Some(Vector2f::new(0.0, 0.0)), usize::MAX);
+        assert!(one_shot.is_finished());
+    }
+
+    #[test]
+    fn losing_the_anchor_stops_emitting() {
+        let mut emitter = emitter().with_rate(100.0).with_lifetime(10_000.0, 10_000.0);
+        emitter.update(100.0, Some(Vector2f::new(5.0, 5.0)), usize::MAX);
+        let count = emitter.particle_count();
+        assert!(count > 0);
+        emitter.update(100.0, None, usize::MAX);
+        assert!(!emitter.emitting);
+        assert_eq!(emitter.particle_count(), count);
+        assert_eq!(emitter.position(), Vector2f::new(5.0, 5.0));
+    }
+}
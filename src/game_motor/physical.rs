@@ -22,8 +22,8 @@ pub struct PhysicalProperties{
     pub collision_type: CollisionType,
 }
 
-impl PhysicalProperties{
-    pub fn default() -> Self{
+impl Default for PhysicalProperties{
+    fn default() -> Self{
         PhysicalProperties{
             collision_type: CollisionType::Rectangular,
             speed:1.0,
@@ -39,8 +39,9 @@ impl PhysicalProperties{
 
         }
     }
+}
 
-
+impl PhysicalProperties{
     pub fn new_static()->Self{
         PhysicalProperties{
             collision_type: CollisionType::Rectangular,
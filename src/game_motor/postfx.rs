@@ -0,0 +1,269 @@
+use sfml::{
+    graphics::{glsl, Color, RenderTexture, Shader},
+    system::{Vector2f, Vector3f, Vector2u},
+};
+
+// Every pass gets "texture" (the frame so far), "resolution" in pixels and "time" in seconds
+pub const VIGNETTE_SHADER: &str = r#"
+uniform sampler2D texture;
+uniform float strength;
+uniform float radius;
+
+void main() {
+    vec2 uv = gl_TexCoord[0].xy;
+    vec4 color = texture2D(texture, uv) * gl_Color;
+    float shade = smoothstep(radius, radius - 0.45, distance(uv, vec2(0.5)));
+    color.rgb *= mix(1.0, shade, strength);
+    gl_FragColor = color;
+}
+"#;
+
+pub const CRT_SHADER: &str = r#"
+uniform sampler2D texture;
+uniform vec2 resolution;
+uniform float curvature;
+uniform float scanlines;
+
+void main() {
+    vec2 uv = gl_TexCoord[0].xy * 2.0 - 1.0;
+    vec2 offset = uv.yx * curvature;
+    uv = (uv + uv * offset * offset) * 0.5 + 0.5;
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec4 color = texture2D(texture, uv) * gl_Color;
+    float line = sin(uv.y * resolution.y * 3.14159) * 0.5 + 0.5;
+    color.rgb *= mix(1.0, line, scanlines);
+    gl_FragColor = color;
+}
+"#;
+
+pub const BLOOM_SHADER: &str = r#"
+uniform sampler2D texture;
+uniform vec2 resolution;
+uniform float threshold;
+uniform float intensity;
+
+void main() {
+    vec2 uv = gl_TexCoord[0].xy;
+    vec2 pixel = 2.0 / resolution;
+    vec4 base = texture2D(texture, uv);
+    vec3 glow = vec3(0.0);
+    for (int x = -2; x <= 2; x++) {
+        for (int y = -2; y <= 2; y++) {
+            vec3 texel = texture2D(texture, uv + vec2(float(x), float(y)) * pixel).rgb;
+            glow += max(texel - vec3(threshold), vec3(0.0));
+        }
+    }
+    gl_FragColor = vec4(base.rgb + glow / 25.0 * intensity, base.a) * gl_Color;
+}
+"#;
+
+pub const COLOR_GRADING_SHADER: &str = r#"
+uniform sampler2D texture;
+uniform float brightness;
+uniform float contrast;
+uniform float saturation;
+uniform vec4 tint;
+
+void main() {
+    vec4 color = texture2D(texture, gl_TexCoord[0].xy) * gl_Color;
+    vec3 graded = color.rgb + brightness;
+    graded = (graded - 0.5) * contrast + 0.5;
+    float luma = dot(graded, vec3(0.299, 0.587, 0.114));
+    graded = mix(vec3(luma), graded, saturation) * tint.rgb;
+    gl_FragColor = vec4(graded, color.a);
+}
+"#;
+
+// Shader ids the built in passes use, the scene loads them on first use
+pub fn builtin_post_shader(shader_id: &str) -> Option<&'static str> {
+    match shader_id {
+        "postfx/vignette" => Some(VIGNETTE_SHADER),
+        "postfx/crt" => Some(CRT_SHADER),
+        "postfx/bloom" => Some(BLOOM_SHADER),
+        "postfx/color_grading" => Some(COLOR_GRADING_SHADER),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShaderUniform {
+    Float(f32),
+    Vec2(Vector2f),
+    Vec3(Vector3f),
+    Color(Color),
+}
+
+impl ShaderUniform {
+    pub fn apply(&self, shader: &mut Shader, name: &str) {
+        match *self {
+            ShaderUniform::Float(value) => shader.set_uniform_float(name, value),
+            ShaderUniform::Vec2(value) => shader.set_uniform_vec2(name, value),
+            ShaderUniform::Vec3(value) => shader.set_uniform_vec3(name, value),
+            ShaderUniform::Color(value) => shader.set_uniform_vec4(name, glsl::Vec4::from(value)),
+        }
+    }
+}
+
+// One full screen fragment shader pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostPass {
+    pub name: String,
+    // Id in the scene shaders
+    pub shader_id: String,
+    pub enabled: bool,
+    pub uniforms: Vec<(String, ShaderUniform)>,
+}
+
+impl PostPass {
+    pub fn new(name: &str, shader_id: &str) -> Self {
+        PostPass {
+            name: name.to_string(),
+            shader_id: shader_id.to_string(),
+            enabled: true,
+            uniforms: Vec::new(),
+        }
+    }
+
+    pub fn with_uniform(mut self, name: &str, value: ShaderUniform) -> Self {
+        self.set_uniform(name, value);
+        self
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: ShaderUniform) {
+        match self.uniforms.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, uniform)) => *uniform = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+    }
+
+    // Darkens the corners, radius is in texture space (0.5 reaches the edges)
+    pub fn vignette(strength: f32, radius: f32) -> Self {
+        PostPass::new("vignette", "postfx/vignette")
+            .with_uniform("strength", ShaderUniform::Float(strength))
+            .with_uniform("radius", ShaderUniform::Float(radius))
+    }
+
+    pub fn crt(curvature: f32, scanlines: f32) -> Self {
+        PostPass::new("crt", "postfx/crt")
+            .with_uniform("curvature", ShaderUniform::Float(curvature))
+            .with_uniform("scanlines", ShaderUniform::Float(scanlines))
+    }
+
+    // Colors brighter than the threshold glow into their neighbours
+    pub fn bloom(threshold: f32, intensity: f32) -> Self {
+        PostPass::new("bloom", "postfx/bloom")
+            .with_uniform("threshold", ShaderUniform::Float(threshold))
+            .with_uniform("intensity", ShaderUniform::Float(intensity))
+    }
+
+    pub fn color_grading(brightness: f32, contrast: f32, saturation: f32, tint: Color) -> Self {
+        PostPass::new("color_grading", "postfx/color_grading")
+            .with_uniform("brightness", ShaderUniform::Float(brightness))
+            .with_uniform("contrast", ShaderUniform::Float(contrast))
+            .with_uniform("saturation", ShaderUniform::Float(saturation))
+            .with_uniform("tint", ShaderUniform::Color(tint))
+    }
+}
+
+// Scene render target and the pass chain. When enabled the scene is drawn into a texture,
+// each pass draws it into the other texture and the last one draws into the window.
+pub struct PostProcess {
+    pub enabled: bool,
+    pub clear_color: Color,
+    pub passes: Vec<PostPass>,
+    time_ms: f32,
+    targets: Option<(RenderTexture, RenderTexture)>,
+    size: Vector2u,
+    // Render textures couldn't be made at this size, not retried until the size changes
+    failed_size: Option<Vector2u>,
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        PostProcess {
+            enabled: false,
+            clear_color: Color::BLACK,
+            passes: Vec::new(),
+            time_ms: 0.0,
+            targets: None,
+            size: Vector2u::new(0, 0),
+            failed_size: None,
+        }
+    }
+
+    pub fn add_pass(&mut self, pass: PostPass) {
+        self.passes.push(pass);
+    }
+
+    pub fn pass(&self, name: &str) -> Option<&PostPass> {
+        self.passes.iter().find(|pass| pass.name == name)
+    }
+
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut PostPass> {
+        self.passes.iter_mut().find(|pass| pass.name == name)
+    }
+
+    pub fn remove_pass(&mut self, name: &str) -> Option<PostPass> {
+        let index = self.passes.iter().position(|pass| pass.name == name)?;
+        Some(self.passes.remove(index))
+    }
+
+    pub fn set_pass_enabled(&mut self, name: &str, enabled: bool) {
+        if let Some(pass) = self.pass_mut(name) {
+            pass.enabled = enabled;
+        }
+    }
+
+    pub fn advance(&mut self, delta_ms: f32) {
+        self.time_ms += delta_ms;
+    }
+
+    pub fn time_seconds(&self) -> f32 {
+        self.time_ms / 1000.0
+    }
+
+    // Render textures are made again when the window size changes, None if they can't be created.
+    // A failure is logged once, the scene is then drawn without post processing.
+    pub fn take_targets(&mut self, size: Vector2u) -> Option<(RenderTexture, RenderTexture)> {
+        if self.size != size {
+            self.targets = None;
+        }
+        if let Some(targets) = self.targets.take() {
+            return Some(targets);
+        }
+        if self.failed_size == Some(size) {
+            return None;
+        }
+        match (RenderTexture::new(size.x, size.y), RenderTexture::new(size.x, size.y)) {
+            (Some(scene_target), Some(pass_target)) => {
+                self.size = size;
+                self.failed_size = None;
+                Some((scene_target, pass_target))
+            }
+            _ => {
+                println!("Can't create {}x{} render textures, drawing without post processing", size.x, size.y);
+                self.failed_size = Some(size);
+                None
+            }
+        }
+    }
+
+    pub fn return_targets(&mut self, targets: (RenderTexture, RenderTexture)) {
+        self.targets = Some(targets);
+    }
+
+    // Also allows another try after a failure
+    pub fn release_targets(&mut self) {
+        self.targets = None;
+        self.failed_size = None;
+    }
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
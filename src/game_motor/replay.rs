@@ -0,0 +1,317 @@
+use std::{collections::VecDeque, fmt, fs, hash::Hasher, io, path::Path};
+
+use sfml::window::{joystick, mouse, Event, Key};
+
+use super::prelude::*;
+
+// Input the game loop feeds into the scene, recorded instead of raw window events
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordedInput {
+    KeyPressed(Key),
+    KeyReleased(Key),
+    MouseMoved { x: i32, y: i32 },
+    MouseButton { button: mouse::Button, pressed: bool, x: i32, y: i32 },
+    MouseWheel { wheel: mouse::Wheel, delta: f32 },
+    JoystickButton { joystick: u32, button: u32, pressed: bool },
+    JoystickMoved { joystick: u32, axis: joystick::Axis, position: f32 },
+    JoystickDisconnected { joystick: u32 },
+}
+
+impl RecordedInput {
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let input = match *event {
+            Event::KeyPressed { code, .. } => return RecordedInput::from_key(code, true),
+            Event::KeyReleased { code, .. } => return RecordedInput::from_key(code, false),
+            Event::MouseMoved { x, y } => RecordedInput::MouseMoved { x, y },
+            Event::MouseButtonPressed { button, x, y } => RecordedInput::MouseButton { button, pressed: true, x, y },
+            Event::MouseButtonReleased { button, x, y } => RecordedInput::MouseButton { button, pressed: false, x, y },
+            Event::MouseWheelScrolled { wheel, delta, .. } => RecordedInput::MouseWheel { wheel, delta },
+            Event::JoystickButtonPressed { joystickid, button } => {
+                RecordedInput::JoystickButton { joystick: joystickid, button, pressed: true }
+            }
+            Event::JoystickButtonReleased { joystickid, button } => {
+                RecordedInput::JoystickButton { joystick: joystickid, button, pressed: false }
+            }
+            Event::JoystickMoved { joystickid, axis, position } => {
+                RecordedInput::JoystickMoved { joystick: joystickid, axis, position }
+            }
+            Event::JoystickDisconnected { joystickid } => RecordedInput::JoystickDisconnected { joystick: joystickid },
+            _ => return None,
+        };
+        Some(input)
+    }
+
+    // Unknown keys (media keys and the like) can't be bound or written to a recording, they are dropped
+    fn from_key(key: Key, pressed: bool) -> Option<Self> {
+        match (key, pressed) {
+            (Key::Unknown, _) => None,
+            (key, true) => Some(RecordedInput::KeyPressed(key)),
+            (key, false) => Some(RecordedInput::KeyReleased(key)),
+        }
+    }
+
+    fn to_line(self) -> String {
+        let state = |pressed: bool| if pressed { "pressed" } else { "released" };
+        match self {
+            RecordedInput::KeyPressed(key) => format!("key_pressed {:?}", key),
+            RecordedInput::KeyReleased(key) => format!("key_released {:?}", key),
+            RecordedInput::MouseMoved { x, y } => format!("mouse_moved {} {}", x, y),
+            RecordedInput::MouseButton { button, pressed, x, y } => {
+                format!("mouse_button {:?} {} {} {}", button, state(pressed), x, y)
+            }
+            RecordedInput::MouseWheel { wheel, delta } => format!("mouse_wheel {:?} {}", wheel, delta),
+            RecordedInput::JoystickButton { joystick, button, pressed } => {
+                format!("joystick_button {} {} {}", joystick, button, state(pressed))
+            }
+            RecordedInput::JoystickMoved { joystick, axis, position } => {
+                format!("joystick_moved {} {:?} {}", joystick, axis, position)
+            }
+            RecordedInput::JoystickDisconnected { joystick } => format!("joystick_disconnected {}", joystick),
+        }
+    }
+
+    fn parse(words: &[&str]) -> Option<Self> {
+        let pressed = |word: &str| match word {
+            "pressed" => Some(true),
+            "released" => Some(false),
+            _ => None,
+        };
+        let input = match words {
+            ["key_pressed", key] => RecordedInput::KeyPressed(parse_key(key)?),
+            ["key_released", key] => RecordedInput::KeyReleased(parse_key(key)?),
+            ["mouse_moved", x, y] => RecordedInput::MouseMoved { x: x.parse().ok()?, y: y.parse().ok()? },
+            ["mouse_button", button, state, x, y] => RecordedInput::MouseButton {
+                button: parse_mouse_button(button)?,
+                pressed: pressed(state)?,
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+            },
+            ["mouse_wheel", wheel, delta] => RecordedInput::MouseWheel {
+                wheel: match *wheel {
+                    "VerticalWheel" => mouse::Wheel::VerticalWheel,
+                    "HorizontalWheel" => mouse::Wheel::HorizontalWheel,
+                    _ => return None,
+                },
+                delta: delta.parse().ok()?,
+            },
+            ["joystick_button", joystick, button, state] => RecordedInput::JoystickButton {
+                joystick: joystick.parse().ok()?,
+                button: button.parse().ok()?,
+                pressed: pressed(state)?,
+            },
+            ["joystick_moved", joystick, axis, position] => RecordedInput::JoystickMoved {
+                joystick: joystick.parse().ok()?,
+                axis: parse_joystick_axis(axis)?,
+                position: position.parse().ok()?,
+            },
+            ["joystick_disconnected", joystick] => RecordedInput::JoystickDisconnected { joystick: joystick.parse().ok()? },
+            _ => return None,
+        };
+        Some(input)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    Io(String),
+    Parse { line: usize, text: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(reason) => write!(f, "Can't read recording: {}", reason),
+            ReplayError::Parse { line, text } => write!(f, "Invalid recording line {}: {}", line, text),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+// Inputs with the tick they were fed on. Replays are only exact with the same fixed delta.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputRecording {
+    pub fixed_delta_ms: Option<f32>,
+    pub inputs: Vec<(u64, RecordedInput)>,
+}
+
+impl InputRecording {
+    pub fn new(fixed_delta_ms: Option<f32>) -> Self {
+        InputRecording {
+            fixed_delta_ms,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, input: RecordedInput) {
+        self.inputs.push((tick, input));
+    }
+
+    pub fn last_tick(&self) -> u64 {
+        self.inputs.last().map(|(tick, _)| *tick).unwrap_or(0)
+    }
+
+    // One input per line: "<tick> <input>", the fixed delta is stored in the header
+    pub fn to_file_string(&self) -> String {
+        let mut out = String::from("# input recording\n");
+        if let Some(delta) = self.fixed_delta_ms {
+            out.push_str(&format!("fixed_delta_ms {}\n", delta));
+        }
+        for (tick, input) in self.inputs.iter() {
+            out.push_str(&format!("{} {}\n", tick, input.to_line()));
+        }
+        out
+    }
+
+    pub fn from_file_str(text: &str) -> Result<Self, ReplayError> {
+        let mut recording = InputRecording::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || ReplayError::Parse { line: index + 1, text: line.to_string() };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["fixed_delta_ms", delta] => recording.fixed_delta_ms = Some(delta.parse().map_err(|_| error())?),
+                [tick, input @ ..] => {
+                    let tick = tick.parse().map_err(|_| error())?;
+                    let input = RecordedInput::parse(input).ok_or_else(error)?;
+                    recording.push(tick, input);
+                }
+                _ => return Err(error()),
+            }
+        }
+        // Ticks only go forward, replay relies on it
+        recording.inputs.sort_by_key(|(tick, _)| *tick);
+        Ok(recording)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_file_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        let text = fs::read_to_string(path).map_err(|error| ReplayError::Io(error.to_string()))?;
+        InputRecording::from_file_str(&text)
+    }
+}
+
+// Hands out the recorded inputs tick by tick
+#[derive(Debug, Clone)]
+pub struct InputReplay {
+    pending: VecDeque<(u64, RecordedInput)>,
+    last_tick: u64,
+}
+
+impl InputReplay {
+    pub fn new(recording: &InputRecording) -> Self {
+        InputReplay {
+            pending: recording.inputs.iter().copied().collect(),
+            last_tick: recording.last_tick(),
+        }
+    }
+
+    // Inputs of the given tick, inputs of skipped ticks come out too
+    pub fn inputs_until(&mut self, tick: u64) -> Vec<RecordedInput> {
+        let mut inputs = Vec::new();
+        while self.pending.front().map(|(at, _)| *at <= tick).unwrap_or(false) {
+            if let Some((_, input)) = self.pending.pop_front() {
+                inputs.push(input);
+            }
+        }
+        inputs
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn last_tick(&self) -> u64 {
+        self.last_tick
+    }
+}
+
+// FNV-1a. DefaultHasher may change between Rust releases, saved state hashes have to stay comparable.
+#[derive(Debug, Clone, Copy)]
+pub struct StateHasher(u64);
+
+impl StateHasher {
+    pub fn new() -> Self {
+        StateHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    // Strings end with a marker so "ab" + "c" and "a" + "bc" differ
+    pub fn write_str(&mut self, text: &str) {
+        self.write(text.as_bytes());
+        self.write(&[0xff]);
+    }
+
+    // Little endian, the same on every platform
+    pub fn write_f32(&mut self, value: f32) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StateHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_hasher_is_fnv1a() {
+        assert_eq!(StateHasher::new().finish(), 0xcbf2_9ce4_8422_2325);
+        let mut hasher = StateHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn recording_round_trips_through_the_file_format() {
+        let mut recording = InputRecording::new(Some(16.0));
+        recording.push(3, RecordedInput::KeyPressed(Key::D));
+        recording.push(9, RecordedInput::MouseButton { button: mouse::Button::Left, pressed: true, x: 10, y: -4 });
+        recording.push(12, RecordedInput::KeyReleased(Key::D));
+        assert_eq!(InputRecording::from_file_str(&recording.to_file_string()), Ok(recording));
+    }
+
+    #[test]
+    fn unknown_keys_are_not_recorded() {
+        let released = |code: Key| Event::KeyReleased { code, alt: false, ctrl: false, shift: false, system: false };
+        assert_eq!(RecordedInput::from_event(&released(Key::Unknown)), None);
+        let mut recording = InputRecording::new(None);
+        for (tick, input) in [
+            RecordedInput::from_key(Key::A, true),
+            RecordedInput::from_key(Key::Unknown, true),
+            RecordedInput::from_event(&released(Key::Unknown)),
+            RecordedInput::from_event(&released(Key::A)),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if let Some(input) = input {
+                recording.push(tick as u64, input);
+            }
+        }
+        assert_eq!(recording.inputs, vec![(0, RecordedInput::KeyPressed(Key::A)), (3, RecordedInput::KeyReleased(Key::A))]);
+        assert_eq!(InputRecording::from_file_str(&recording.to_file_string()), Ok(recording));
+    }
+}
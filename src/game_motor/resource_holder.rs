@@ -1,49 +1,186 @@
-
 use {
     sfml::{
-        audio::{Sound, SoundBuffer},
-        graphics::{Color, RcTexture, RenderTarget, RenderWindow, Sprite, Texture},
-        window::{Event, Key, Style},
-        SfBox, SfResource,
+        audio::SoundBuffer,
+        graphics::{RcFont, RcTexture, Shader, ShaderType},
+        SfBox,
     },
-    std::{collections::HashMap, hash::Hash},
+    std::{collections::HashMap, fs, hash::Hash, ops::{Deref, DerefMut}},
 };
 
+use super::prelude::*;
+
 #[derive(Debug)]
-pub struct ResourceHolder<Resource: SfResource, Identifier: Hash + Eq> {
-    resource_map: HashMap<Identifier, SfBox<Resource>>,
+pub struct ResourceHolder<Resource: ResLoad, Identifier: Hash + Eq> {
+    resource_map: HashMap<Identifier, Resource::Stored>,
 }
 
-impl<Resource: SfResource + ResLoad, Identifier: Hash + Eq> ResourceHolder<Resource, Identifier> {
-    pub fn load(&mut self, identifier: Identifier, filename: &str) {
-        let res = Resource::load(filename);
+impl<Resource: ResLoad, Identifier: Hash + Eq> ResourceHolder<Resource, Identifier> {
+    pub fn load(&mut self, identifier: Identifier, filename: &str) -> Result<(), AssetError> {
+        let res = Resource::load(filename)?;
+        self.resource_map.insert(identifier, res);
+        Ok(())
+    }
+
+    pub fn load_from_memory(&mut self, identifier: Identifier, data: &[u8]) -> Result<(), AssetError> {
+        let res = Resource::load_from_memory(data)?;
         self.resource_map.insert(identifier, res);
+        Ok(())
     }
+
+    // Panics on missing ids, use try_get when the resource may not be loaded
     pub fn get(&self, id: Identifier) -> &Resource {
         &self.resource_map[&id]
     }
+
+    pub fn try_get(&self, id: &Identifier) -> Option<&Resource> {
+        self.resource_map.get(id).map(|res| res.deref())
+    }
+
+    pub fn contains(&self, id: &Identifier) -> bool {
+        self.resource_map.contains_key(id)
+    }
+
+    pub fn remove(&mut self, id: &Identifier) -> bool {
+        self.resource_map.remove(id).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.resource_map.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.resource_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resource_map.is_empty()
+    }
+
+    pub fn ids(&self) -> Vec<&Identifier> {
+        self.resource_map.keys().collect()
+    }
+
+    // Approximate size in bytes, textures are counted as uncompressed RGBA on the GPU
+    pub fn memory_usage(&self, id: &Identifier) -> Option<usize> {
+        self.resource_map.get(id).map(Resource::memory_usage)
+    }
+
+    pub fn total_memory_usage(&self) -> usize {
+        self.resource_map.values().map(Resource::memory_usage).sum()
+    }
+}
+
+impl<Resource: ResLoad, Identifier: Hash + Eq> ResourceHolder<Resource, Identifier>
+where
+    Resource::Stored: DerefMut,
+{
+    // Shaders need it to set their uniforms
+    pub fn try_get_mut(&mut self, id: &Identifier) -> Option<&mut Resource> {
+        self.resource_map.get_mut(id).map(|res| res.deref_mut())
+    }
+}
+
+pub trait ResLoad: Sized {
+    // What the holder keeps, derefs to the resource
+    type Stored: Deref<Target = Self>;
+
+    fn load(filename: &str) -> Result<Self::Stored, AssetError>;
+    fn load_from_memory(data: &[u8]) -> Result<Self::Stored, AssetError>;
+    fn memory_usage(stored: &Self::Stored) -> usize;
 }
 
-trait ResLoad: SfResource {
-    fn load(filename: &str) -> SfBox<Self>;
+fn read_file(filename: &str) -> Result<Vec<u8>, AssetError> {
+    fs::read(filename).map_err(|error| AssetError::File { path: filename.to_string(), reason: error.to_string() })
 }
 
-impl ResLoad for Texture {
-    fn load(filename: &str) -> SfBox<Self> {
-        Self::from_file(filename).unwrap()
+fn format_error(filename: &str) -> AssetError {
+    AssetError::Format { path: filename.to_string() }
+}
+
+// Textures, fonts and sounds are decoded and owned the same way as in the AssetServer
+
+impl ResLoad for RcTexture {
+    type Stored = Box<RcTexture>;
+
+    fn load(filename: &str) -> Result<Self::Stored, AssetError> {
+        decode_texture(filename, &read_file(filename)?).map(Box::new)
+    }
+
+    fn load_from_memory(data: &[u8]) -> Result<Self::Stored, AssetError> {
+        decode_texture("<memory>", data).map(Box::new)
+    }
+
+    fn memory_usage(stored: &Self::Stored) -> usize {
+        let size = stored.size();
+        size.x as usize * size.y as usize * 4
     }
 }
 
 impl ResLoad for SoundBuffer {
-    fn load(filename: &str) -> SfBox<Self> {
-        Self::from_file(filename).unwrap()
+    type Stored = SfBox<SoundBuffer>;
+
+    fn load(filename: &str) -> Result<Self::Stored, AssetError> {
+        decode_sound(filename, &read_file(filename)?)
+    }
+
+    fn load_from_memory(data: &[u8]) -> Result<Self::Stored, AssetError> {
+        decode_sound("<memory>", data)
+    }
+
+    fn memory_usage(stored: &Self::Stored) -> usize {
+        std::mem::size_of_val(stored.samples())
     }
 }
 
+impl ResLoad for RcFont {
+    type Stored = FontAsset;
 
+    fn load(filename: &str) -> Result<Self::Stored, AssetError> {
+        decode_font(filename, read_file(filename)?)
+    }
 
+    fn load_from_memory(data: &[u8]) -> Result<Self::Stored, AssetError> {
+        decode_font("<memory>", data.to_vec())
+    }
+
+    fn memory_usage(stored: &Self::Stored) -> usize {
+        stored.memory_usage()
+    }
+}
+
+// Single stage shaders, the stage comes from the extension (.vert, .geom, anything else is fragment)
+fn shader_type(filename: &str) -> ShaderType {
+    if filename.ends_with(".vert") {
+        ShaderType::Vertex
+    } else if filename.ends_with(".geom") {
+        ShaderType::Geometry
+    } else {
+        ShaderType::Fragment
+    }
+}
+
+impl ResLoad for Shader<'static> {
+    type Stored = Box<Shader<'static>>;
+
+    fn load(filename: &str) -> Result<Self::Stored, AssetError> {
+        let source = read_file(filename)?;
+        let source = String::from_utf8(source).map_err(|_| format_error(filename))?;
+        Shader::from_memory(&source, shader_type(filename)).map(Box::new).map_err(|_| format_error(filename))
+    }
+
+    // Memory shaders are fragment shaders
+    fn load_from_memory(data: &[u8]) -> Result<Self::Stored, AssetError> {
+        let source = std::str::from_utf8(data).map_err(|_| format_error("<memory>"))?;
+        Shader::from_memory(source, ShaderType::Fragment).map(Box::new).map_err(|_| format_error("<memory>"))
+    }
+
+    // Compiled programs live in the driver, there is nothing to count here
+    fn memory_usage(_stored: &Self::Stored) -> usize {
+        0
+    }
+}
 
-impl<Resource: SfResource, Identifier: Hash + Eq> Default for ResourceHolder<Resource, Identifier> {
+impl<Resource: ResLoad, Identifier: Hash + Eq> Default for ResourceHolder<Resource, Identifier> {
     fn default() -> Self {
         Self {
             resource_map: HashMap::default(),
@@ -1,14 +1,40 @@
 use core::f32;
-use std::{cell::RefCell, char::MAX, cmp, collections::HashMap, f32::INFINITY};
+use std::{cell::{Cell, Ref, RefCell, RefMut}, char::MAX, cmp, collections::{HashMap, HashSet}, f32::INFINITY, hash::Hasher};
 
 use indexmap::IndexMap;
 use sfml::{
     audio::SoundBuffer, graphics::{
-        glsl::Vec2, CircleShape, Color, Drawable, FloatRect, IntRect, RcFont, RcTexture, Rect, RectangleShape, RenderTarget, RenderWindow, Shape, TextStyle, Texture, Transformable
-    }, system::Vector2f, window::{Event, Key}
+        glsl::Vec2, BlendMode, CircleShape, Color, Drawable, FloatRect, IntRect, RcFont, RcTexture, Rect, RectangleShape, RenderStates, RenderTarget, RenderWindow, Shader, Shape, Sprite, TextStyle, Texture, Transform, Transformable
+    }, system::{Vector2f, Vector2i, Vector2u}, window::{joystick, mouse, Event, Key}
 };
 
-use super::{animation, entity, prelude::*};
+use super::{entity, prelude::*};
+
+// How entities with the same z index are ordered inside a layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerSort {
+    // Order they were added in
+    Insertion,
+    // Lower bottom edge first, for top-down games
+    YSort,
+}
+
+// Things drawn between entity layers that aren't entities
+enum LayerItem<'a> {
+    Tiles(&'a Tilemap, usize),
+    Parallax(&'a ParallaxLayer),
+    Particles(&'a ParticleEmitter),
+}
+
+impl<'a> LayerItem<'a> {
+    fn draw(&self, target: &mut dyn RenderTarget, camera: &Camera, visible_area: FloatRect, stats: &mut RenderStats) {
+        match self {
+            LayerItem::Tiles(tilemap, index) => tilemap.draw_layer(target, *index, visible_area, stats),
+            LayerItem::Parallax(layer) => layer.draw(target, camera.center, visible_area, stats),
+            LayerItem::Particles(emitter) => emitter.draw(target, visible_area, stats),
+        }
+    }
+}
 
 pub struct Scene<T>
 where
@@ -22,12 +48,47 @@ where
     pub on_start: fn(&mut Self),
     pub first_render: bool,
     pub on_collision: fn(&mut Self, String, String, FloatRect) -> (),
-    textures: HashMap<String, RcTexture>,
-    pub fonts: ResourceHolder<RcFont, String>,
-    pub sounds: ResourceHolder<SoundBuffer, String>,
+    pub on_assets_loaded: fn(&mut Self, LoadProgress) -> (),
+    assets: SharedAssets,
     pub entities: IndexMap<String, RefCell<T>>,
     keyboard: HashMap<Key, bool>,
+    mouse_buttons: HashMap<mouse::Button, bool>,
+    mouse_position: Vector2i,
+    mouse_world_position: Vector2f,
+    // x is the horizontal wheel, y the vertical one, summed over the current frame
+    mouse_wheel: Vector2f,
+    joystick_buttons: HashMap<(u32, u32), bool>,
+    joystick_axes: HashMap<(u32, joystick::Axis), f32>,
+    // Axis positions (0 - 100) below this read as 0
+    pub joystick_dead_zone: f32,
     pub gravity: Vec2,
+    pub tweens: TweenManager<T>,
+    pub input: InputMap,
+    pub camera: Camera,
+    // Drawn after the main camera, in the order they were added
+    cameras: IndexMap<String, Camera>,
+    layer_sort: HashMap<i32, LayerSort>,
+    // Batches sprites sharing a texture, off draws every sprite on its own
+    pub batching: bool,
+    render_stats: Cell<RenderStats>,
+    // Recent action presses for combos, see Scene::check_combo
+    pub input_buffer: InputBuffer,
+    buttons: PressTracker<InputButton>,
+    actions: PressTracker<String>,
+    // Shaders aren't shared between scenes, they are looked up by id
+    pub shaders: ResourceHolder<Shader<'static>, String>,
+    // Fonts and sounds only this scene uses, shared ones go through the asset server
+    pub fonts: ResourceHolder<RcFont, String>,
+    pub sounds: ResourceHolder<SoundBuffer, String>,
+    // Offscreen rendering and full screen shader passes, off by default
+    pub post_process: PostProcess,
+    tilemaps: IndexMap<String, Tilemap>,
+    parallax_layers: Vec<ParallaxLayer>,
+    emitters: Vec<ParticleEmitter>,
+    // Particles alive across all emitters, emitters stop spawning when it is reached
+    pub max_particles: usize,
+    missing_textures: RefCell<HashSet<String>>,
+    loader: Option<AssetLoader>,
 }
 
 impl<T> Scene<T>
@@ -35,7 +96,19 @@ where
     T: Drawable + Transformable + EntityTrait,
 {
     pub fn new(name: &str) -> Self {
-        let mut scn = Scene {
+        Scene::with_assets(name, AssetServer::shared())
+    }
+
+    // Scenes created with the same asset server share every loaded asset
+    pub fn with_assets(name: &str, assets: SharedAssets) -> Self {
+        let mut scn = Scene::headless(name, assets);
+        scn.load_empty_texture();
+        scn
+    }
+
+    // Doesn't load the empty texture, for tests and tools that never draw
+    pub fn headless(name: &str, assets: SharedAssets) -> Self {
+        Scene {
             gravity: Vec2::new(0.0, 0.0),
             on_keystate_changed: |_s, _k| {},
             first_render: true,
@@ -45,39 +118,168 @@ where
             on_update: |_s| {},
             on_event: |_s, _e| {},
             on_collision: |_s, _e1, _e2, _r| {},
-            textures: HashMap::new(),
-            fonts: ResourceHolder::default(),
-            sounds: ResourceHolder::default(),
+            on_assets_loaded: |_s, _p| {},
+            assets,
             entities: IndexMap::new(),
             keyboard: HashMap::new(),
-        };
+            mouse_buttons: HashMap::new(),
+            mouse_position: Vector2i::new(0, 0),
+            mouse_world_position: Vector2f::new(0.0, 0.0),
+            mouse_wheel: Vector2f::new(0.0, 0.0),
+            joystick_buttons: HashMap::new(),
+            joystick_axes: HashMap::new(),
+            joystick_dead_zone: 15.0,
+            tweens: TweenManager::new(),
+            input: InputMap::new(),
+            camera: Camera::new(),
+            cameras: IndexMap::new(),
+            layer_sort: HashMap::new(),
+            batching: true,
+            render_stats: Cell::new(RenderStats::default()),
+            input_buffer: InputBuffer::default(),
+            buttons: PressTracker::new(),
+            actions: PressTracker::new(),
+            shaders: ResourceHolder::default(),
+            fonts: ResourceHolder::default(),
+            sounds: ResourceHolder::default(),
+            post_process: PostProcess::new(),
+            tilemaps: IndexMap::new(),
+            parallax_layers: Vec::new(),
+            emitters: Vec::new(),
+            max_particles: 5000,
+            missing_textures: RefCell::new(HashSet::new()),
+            loader: None,
+        }
+    }
 
-        scn.load_texture("empty", "assets/404.png");
-        return scn;
+    // Failure is kept in the asset server, see AssetServer::failed_assets
+    fn load_empty_texture(&mut self) {
+        if self.get_texture("empty").is_none() {
+            let path = self.assets().empty_texture_path().to_string();
+            let _ = self.load_texture("empty", &path);
+        }
+    }
+
+    // Asset paths are relative to the root, the empty texture is retried from the new root
+    pub fn set_asset_root(&mut self, root: &str) {
+        self.assets.borrow_mut().set_asset_root(root);
+        self.load_empty_texture();
+    }
+
+    pub fn mount_pack(&mut self, path: &str) -> Result<(), AssetError> {
+        self.assets.borrow_mut().mount_pack(path)?;
+        self.load_empty_texture();
+        Ok(())
     }
 
     pub fn set_gravity(&mut self, gravity: Vec2) {
         self.gravity = gravity;
     }
 
-    pub fn load_texture(&mut self, name: &str, path: &str) {
-        if let Ok(texture) = RcTexture::from_file(path) {
-            self.textures.insert(name.to_string(), texture);
+    pub fn assets(&self) -> Ref<'_, AssetServer> {
+        self.assets.borrow()
+    }
+
+    pub fn assets_mut(&self) -> RefMut<'_, AssetServer> {
+        self.assets.borrow_mut()
+    }
+
+    pub fn shared_assets(&self) -> SharedAssets {
+        self.assets.clone()
+    }
+
+    pub fn load_texture(&mut self, name: &str, path: &str) -> Result<Handle<RcTexture>, AssetError> {
+        self.assets.borrow_mut().load_texture(name, path)
+    }
+
+    pub fn load_texture_custom(&mut self, name: &str, path: &str, manup: fn(RcTexture)->RcTexture) -> Result<Handle<RcTexture>, AssetError> {
+        self.assets.borrow_mut().load_texture_custom(name, path, manup)
+    }
+
+    // Reads and decodes the queued assets on worker threads, on_assets_loaded fires when all are done
+    pub fn load_async(&mut self, loader: AssetLoader) {
+        let mut loader = loader;
+        loader.start(self.assets.borrow().sources().clone());
+        self.loader = Some(loader);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    pub fn loading_progress(&self) -> Option<LoadProgress> {
+        self.loader.as_ref().map(|loader| loader.progress())
+    }
+
+    // Moves finished assets into the asset server, returns the final progress once everything is loaded
+    pub fn poll_loading(&mut self) -> Option<LoadProgress> {
+        let loader = self.loader.as_mut()?;
+        let progress = loader.poll(&mut self.assets.borrow_mut());
+        if !progress.is_finished() {
+            return None;
         }
+        self.loader = None;
+        Some(progress)
     }
 
-    pub fn load_texture_custom(&mut self, name: &str, path: &str, manup: fn(RcTexture)->RcTexture) {
-        if let Ok(texture) = RcTexture::from_file(path) {
-            let texture = manup(texture);
-            self.textures.insert(name.to_string(), texture);
+    // Refreshes entities whose current animation uses a texture that was reloaded from disk
+    pub fn poll_asset_changes(&mut self) {
+        let reloaded = self.assets.borrow_mut().poll_changes(get_render_ms());
+        if reloaded.is_empty() {
+            return;
+        }
+
+        for entity in self.entities.values() {
+            let uses_reloaded = match entity.borrow().get_current_animation() {
+                Some(animation) => reloaded.contains(&animation.textute_id),
+                None => false,
+            };
+            if uses_reloaded {
+                entity.borrow_mut().set_animation_changed(true);
+            }
         }
     }
 
-    pub fn get_texture(&self, name: &str) -> Option<&RcTexture> {
-        if let Some(txt) = self.textures.get(name) {
-            return Some(txt);
+    pub fn get_texture(&self, name: &str) -> Option<Ref<'_, RcTexture>> {
+        Ref::filter_map(self.assets.borrow(), |assets| assets.texture(name)).ok()
+    }
+
+    // Texture ids the scene draws with, entities and tiles hold ids instead of handles
+    pub fn texture_ids_in_use(&self) -> HashSet<String> {
+        let mut ids: HashSet<String> = HashSet::new();
+        ids.insert("empty".to_string());
+        for entity in self.entities.values() {
+            let entity = entity.borrow();
+            ids.extend(entity.get_animations().values().map(|animation| animation.textute_id.clone()));
+            if let Some(skeleton) = entity.get_skeleton() {
+                ids.extend(skeleton.get_parts().iter().map(|part| part.texture_id.clone()));
+            }
         }
-        None
+        for tilemap in self.tilemaps.values() {
+            ids.extend(tilemap.tilesets.iter().map(|tileset| tileset.texture_id.clone()));
+        }
+        ids.extend(self.parallax_layers.iter().map(|layer| layer.texture_id.clone()));
+        ids.extend(self.emitters.iter().filter_map(|emitter| emitter.texture_id.clone()));
+        ids
+    }
+
+    // Tells the asset server which textures this scene draws, under the scene name.
+    // Every scene sharing the server has to do this before any of them unloads unused assets.
+    pub fn register_asset_use(&self) {
+        let ids = self.texture_ids_in_use();
+        self.assets.borrow_mut().set_user(&self.name, ids);
+    }
+
+    pub fn release_asset_use(&self) {
+        self.assets.borrow_mut().remove_user(&self.name);
+    }
+
+    // Registers this scene's textures, then drops every asset on the server that has no live handles
+    // and isn't registered by any user. Textures of other scenes on the server are only kept if they
+    // registered them too, see register_asset_use.
+    pub fn unload_unused_assets(&mut self) -> Vec<String> {
+        self.register_asset_use();
+        self.assets.borrow_mut().unload_unused()
     }
 
     pub fn entity(&self, id: &str) -> &RefCell<T> {
@@ -87,12 +289,85 @@ where
         self.entities.get(id).unwrap()
     }
 
+    // Hash of every entity's id, transform and animation frame, equal hashes mean a replay reproduced the run
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        for (id, entity) in self.entities.iter() {
+            let entity = entity.borrow();
+            hasher.write_str(id);
+            let position = entity.position();
+            let scale = entity.get_scale();
+            for value in [position.x, position.y, entity.rotation(), scale.x, scale.y] {
+                hasher.write_f32(value);
+            }
+            hasher.write_str(entity.current_animation());
+            if let Some(animation) = entity.get_current_animation() {
+                hasher.write(&animation.current_frame.to_le_bytes());
+            }
+        }
+        hasher.finish()
+    }
+
     pub fn try_entity(&self, id: &str) -> Option<&RefCell<T>> {
         self.entities.get(id)
     }
 
-    pub fn add_entity(&mut self, entity: T) {
+    // Adds the entity even if some animations are broken. Missing textures fall back to the empty texture
+    // at runtime, animations whose frames don't fit their texture are removed.
+    pub fn add_entity(&mut self, mut entity: T) {
+        if let Err(errors) = self.validate_entity(&entity) {
+            for error in errors {
+                println!("Entity {}: {}", entity.get_id(), error);
+                match error {
+                    AnimationError::ZeroSizeRect { animation, .. } | AnimationError::RectIndexOutOfRange { animation, .. } => {
+                        entity.remove_animation(&animation);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.entities.insert(entity.get_id(), RefCell::new(entity));
+    }
+
+    // Only adds the entity if all of its animations are valid
+    pub fn try_add_entity(&mut self, entity: T) -> Result<(), Vec<AnimationError>> {
+        self.validate_entity(&entity)?;
         self.entities.insert(entity.get_id(), RefCell::new(entity));
+        Ok(())
+    }
+
+    pub fn validate_entity(&self, entity: &T) -> Result<(), Vec<AnimationError>> {
+        let animations = entity.get_animations();
+        let mut names: Vec<&String> = animations.keys().collect();
+        names.sort();
+
+        let mut errors: Vec<AnimationError> = Vec::new();
+        for name in names {
+            let animation = &animations[name];
+            let texture_size = self.get_texture(&animation.textute_id).map(|texture| texture.size());
+            errors.extend(animation.validate(name, texture_size));
+
+            let return_state = match &animation.type_ {
+                AnimationType::Once { return_state, .. } => Some(return_state),
+                AnimationType::FiniteLoop { return_state, .. } => Some(return_state),
+                _ => None,
+            };
+            if let Some(return_state) = return_state {
+                let deletes = return_state.is_empty() || return_state == "deleted";
+                if !deletes && !animations.contains_key(return_state) {
+                    errors.push(AnimationError::MissingReturnState {
+                        animation: name.clone(),
+                        return_state: return_state.clone(),
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
     pub fn check_key(&self, key: Key) -> bool {
@@ -113,117 +388,236 @@ where
 
     pub fn set_key(&mut self, key: Key, value: bool) {
         self.keyboard.insert(key, value);
+        self.track_button(InputButton::Key(key), value);
+    }
+
+    fn track_button(&mut self, button: InputButton, value: bool) {
+        if value {
+            self.buttons.press(button);
+        } else {
+            self.buttons.release(button);
+        }
+    }
+
+    // Called by the game loop before the frame's events are handled, ends last frame's just pressed/released state
+    pub fn begin_input_frame(&mut self, delta_ms: f32) {
+        self.mouse_wheel = Vector2f::new(0.0, 0.0);
+        self.buttons.begin_frame(delta_ms);
+        self.actions.begin_frame(delta_ms);
+        self.input_buffer.advance(delta_ms);
+    }
+
+    // Called by the game loop after the frame's events, turns bindings into action presses and releases
+    pub fn update_actions(&mut self) {
+        let actions: Vec<String> = self.input.actions().into_iter().cloned().collect();
+        for action in actions {
+            let pressed = self.is_action_pressed(&action);
+            if pressed && !self.actions.is_held(&action) {
+                self.input_buffer.push(&action);
+                self.actions.press(action);
+            } else if !pressed {
+                self.actions.release(action);
+            }
+        }
+    }
+
+    pub fn is_just_pressed(&self, button: impl Into<InputButton>) -> bool {
+        self.buttons.is_just_pressed(&button.into())
+    }
+
+    pub fn is_just_released(&self, button: impl Into<InputButton>) -> bool {
+        self.buttons.is_just_released(&button.into())
+    }
+
+    // Milliseconds the button has been held, 0.0 if it isn't
+    pub fn held_duration(&self, button: impl Into<InputButton>) -> f32 {
+        self.buttons.held_duration(&button.into())
+    }
+
+    pub fn is_double_tap(&self, button: impl Into<InputButton>) -> bool {
+        self.buttons.is_double_tap(&button.into())
+    }
+
+    pub fn is_action_just_pressed(&self, action: &str) -> bool {
+        self.actions.is_just_pressed(&action.to_string())
+    }
+
+    pub fn is_action_just_released(&self, action: &str) -> bool {
+        self.actions.is_just_released(&action.to_string())
+    }
+
+    pub fn action_held_duration(&self, action: &str) -> f32 {
+        self.actions.held_duration(&action.to_string())
+    }
+
+    pub fn is_action_double_tap(&self, action: &str) -> bool {
+        self.actions.is_double_tap(&action.to_string())
+    }
+
+    // True once when the latest action presses are the sequence, e.g. ["down", "right", "attack"]
+    pub fn check_combo(&mut self, sequence: &[&str]) -> bool {
+        self.input_buffer.consume(sequence)
+    }
+
+    // Double tap time for buttons and actions
+    pub fn set_double_tap_ms(&mut self, double_tap_ms: f32) {
+        self.buttons.double_tap_ms = double_tap_ms;
+        self.actions.double_tap_ms = double_tap_ms;
+    }
+
+    pub fn check_mouse_button(&self, button: mouse::Button) -> bool {
+        self.mouse_buttons.get(&button).copied().unwrap_or(false)
+    }
+
+    pub fn set_mouse_button(&mut self, button: mouse::Button, value: bool) {
+        self.mouse_buttons.insert(button, value);
+        self.track_button(InputButton::Mouse(button), value);
+    }
+
+    // Pixels, relative to the window
+    pub fn mouse_position(&self) -> Vector2i {
+        self.mouse_position
+    }
+
+    pub fn set_mouse_position(&mut self, position: Vector2i) {
+        self.mouse_position = position;
+    }
+
+    // Mouse position in the world, through the window's current view
+    pub fn mouse_world_position(&self) -> Vector2f {
+        self.mouse_world_position
+    }
+
+    pub fn set_mouse_world_position(&mut self, position: Vector2f) {
+        self.mouse_world_position = position;
+    }
+
+    // Vertical wheel movement this frame, positive is up
+    pub fn mouse_wheel_delta(&self) -> f32 {
+        self.mouse_wheel.y
+    }
+
+    // Horizontal wheel movement this frame, positive is left
+    pub fn mouse_wheel_delta_horizontal(&self) -> f32 {
+        self.mouse_wheel.x
+    }
+
+    pub fn add_mouse_wheel(&mut self, wheel: mouse::Wheel, delta: f32) {
+        match wheel {
+            mouse::Wheel::VerticalWheel => self.mouse_wheel.y += delta,
+            mouse::Wheel::HorizontalWheel => self.mouse_wheel.x += delta,
+        }
+    }
+
+    pub fn check_joystick_button(&self, joystick: u32, button: u32) -> bool {
+        self.joystick_buttons.get(&(joystick, button)).copied().unwrap_or(false)
+    }
+
+    pub fn set_joystick_button(&mut self, joystick: u32, button: u32, value: bool) {
+        self.joystick_buttons.insert((joystick, button), value);
+        self.track_button(InputButton::Joystick { joystick, button }, value);
+    }
+
+    // -100.0 - 100.0 with the dead zone removed and the rest rescaled, so small moves still start at 0
+    pub fn joystick_axis(&self, joystick: u32, axis: joystick::Axis) -> f32 {
+        let position = self.joystick_axes.get(&(joystick, axis)).copied().unwrap_or(0.0);
+        let dead_zone = self.joystick_dead_zone.clamp(0.0, 99.0);
+        if position.abs() <= dead_zone {
+            return 0.0;
+        }
+        position.signum() * (position.abs() - dead_zone) / (100.0 - dead_zone) * 100.0
+    }
+
+    // Raw position as reported by SFML
+    pub fn joystick_axis_raw(&self, joystick: u32, axis: joystick::Axis) -> f32 {
+        self.joystick_axes.get(&(joystick, axis)).copied().unwrap_or(0.0)
+    }
+
+    pub fn set_joystick_axis(&mut self, joystick: u32, axis: joystick::Axis, position: f32) {
+        self.joystick_axes.insert((joystick, axis), position);
+    }
+
+    // Forgets everything about a joystick, e.g. when it is disconnected
+    pub fn clear_joystick(&mut self, joystick: u32) {
+        let held: Vec<u32> = self
+            .joystick_buttons
+            .iter()
+            .filter(|((id, _), &down)| *id == joystick && down)
+            .map(|((_, button), _)| *button)
+            .collect();
+        for button in held {
+            self.buttons.release(InputButton::Joystick { joystick, button });
+        }
+        self.joystick_buttons.retain(|(id, _), _| *id != joystick);
+        self.joystick_axes.retain(|(id, _), _| *id != joystick);
+    }
+
+    // True if any binding of the action is held, see Scene::input
+    pub fn is_action_pressed(&self, action: &str) -> bool {
+        self.input.is_action_pressed(self, action)
+    }
+
+    // -1.0 - 1.0
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.input.axis_value(self, axis)
+    }
+
+    // Falls back to the "empty" texture so a bad texture id doesn't crash the game mid-frame
+    fn animation_texture(&self, entity_id: &str, texture_id: &str) -> Option<Ref<'_, RcTexture>> {
+        if let Some(texture) = self.get_texture(texture_id) {
+            return Some(texture);
+        }
+
+        // Warn once per entity and texture, not every frame
+        let key = format!("{}/{}", entity_id, texture_id);
+        if self.missing_textures.borrow_mut().insert(key) {
+            println!("Texture not found: {} (entity {}), using empty texture", texture_id, entity_id);
+        }
+        self.get_texture("empty")
     }
 
     fn animate(&self, entity: &RefCell<T>) {
+        let animation = match entity.borrow().get_current_animation() {
+            Some(animation) => animation.clone(),
+            None => return,
+        };
+        let entity_id = entity.borrow().get_id();
+        let texture = match self.animation_texture(&entity_id, &animation.textute_id) {
+            Some(texture) => texture,
+            None => return,
+        };
+
         if entity.borrow().is_animation_changed() {
-            let texture_id: String = entity.borrow().get_current_animation().unwrap().textute_id.clone(); // Clone the texture ID
-            let texture: &RcTexture = self.get_texture(&texture_id).unwrap();
-            let rect = entity
-            .borrow()
-            .get_current_animation()
-            .unwrap()
-            .get_current_rect(texture.size());
-            entity.borrow_mut().set_texture(texture, true);
-            if rect.is_some() {
-                entity.borrow_mut().set_texture_rect(rect.unwrap());
+            let rect = animation.get_current_rect(texture.size());
+            entity.borrow_mut().set_texture(&texture, true);
+            if let Some(rect) = rect {
+                entity.borrow_mut().set_texture_rect(rect);
             }
             entity.borrow_mut().set_animation_changed(false);
         }
 
+        if matches!(animation.type_, AnimationType::Static) {
+            return; // Do nothing for static animations
+        }
 
-        let animation_type: animation::AnimationType = entity
-            .borrow()
-            .get_current_animation()
-            .unwrap()
-            .type_
-            .clone(); // Clone or copy the animation type if needed
-        match animation_type {
-            AnimationType::Static => {} // Do nothing for static animations
-            AnimationType::Loop { rect_index_range } => {
-                let next_frame = entity
-                    .borrow()
-                    .get_current_animation()
-                    .unwrap()
-                    .time_to_next_frame()
-                    .clone();
-
-                if next_frame {
-                    let texture_id: String = entity
-                        .borrow()
-                        .get_current_animation()
-                        .unwrap()
-                        .textute_id
-                        .clone();
-                    let texture: &RcTexture = self.get_texture(&texture_id).unwrap();
-                    let (flip_x, flip_y) = entity.borrow().get_flip();
-
-                    let current_rect = entity
-                        .borrow()
-                        .get_current_animation()
-                        .unwrap()
-                        .get_current_rect_flipped(texture.size(), flip_x, flip_y);
-                    entity
-                        .borrow_mut()
-                        .get_current_animation_mut()
-                        .unwrap()
-                        .safe_increment_frame(texture.size());
-                    entity
-                        .borrow_mut()
-                        .get_current_animation_mut()
-                        .unwrap()
-                        .reset_duration();
-
-                    if current_rect.is_some() {
-                        entity.borrow_mut().set_texture_rect(current_rect.unwrap());
-                    }
-                }
-                entity
-                    .borrow_mut()
-                    .get_current_animation_mut()
-                    .unwrap()
-                    .increment_duration(get_render_ms());
-            }
-            AnimationType::FiniteLoop {
-                loops,
-                current_loop,
-                return_state,
-                ..
-            } => {
-                let next_frame = entity
-                    .borrow()
-                    .get_current_animation()
-                    .unwrap()
-                    .time_to_next_frame()
-                    .clone();
-
-                if next_frame {
-                    let texture_id: String = entity
-                        .borrow()
-                        .get_current_animation()
-                        .unwrap()
-                        .textute_id
-                        .clone();
-                    let texture: &RcTexture = self.get_texture(&texture_id).unwrap();
-                    let (flip_x, flip_y) = entity.borrow().get_flip();
-
-                    let current_rect = entity
-                        .borrow()
-                        .get_current_animation()
-                        .unwrap()
-                        .get_current_rect_flipped(texture.size(), flip_x, flip_y);
-                    let looped = entity
-                        .borrow_mut()
-                        .get_current_animation_mut()
-                        .unwrap()
-                        .safe_increment_frame(texture.size());
-
+        if animation.time_to_next_frame() {
+            let current_rect = animation.get_current_rect(texture.size());
+            let looped = match entity.borrow_mut().get_current_animation_mut() {
+                Some(current) => current.safe_increment_frame(texture.size()),
+                None => false,
+            };
+
+            match &animation.type_ {
+                AnimationType::FiniteLoop {
+                    loops,
+                    current_loop,
+                    return_state,
+                    ..
+                } => {
                     if looped {
-                        entity
-                            .borrow_mut()
-                            .get_current_animation_mut()
-                            .unwrap()
-                            .increment_loop();
+                        if let Some(current) = entity.borrow_mut().get_current_animation_mut() {
+                            current.increment_loop();
+                        }
                     }
 
                     if current_loop >= loops {
@@ -231,76 +625,64 @@ where
                             .borrow_mut()
                             .set_current_animation(return_state.as_str());
                     }
+                }
+                AnimationType::Once { return_state, .. } if looped => {
                     entity
                         .borrow_mut()
-                        .get_current_animation_mut()
-                        .unwrap()
-                        .reset_duration();
-                    if current_rect.is_some() {
-                        entity.borrow_mut().set_texture_rect(current_rect.unwrap());
-                    }
+                        .set_current_animation(return_state.as_str());
                 }
+                _ => {}
+            }
 
-                entity
-                    .borrow_mut()
-                    .get_current_animation_mut()
-                    .unwrap()
-                    .increment_duration(get_render_ms());
-            }
-            AnimationType::Once {
-                return_state,
-                rect_index_range,
-            } => {
-                let next_frame = entity
-                    .borrow()
-                    .get_current_animation()
-                    .unwrap()
-                    .time_to_next_frame()
-                    .clone();
-
-                if next_frame {
-                    let texture_id: String = entity
-                        .borrow()
-                        .get_current_animation()
-                        .unwrap()
-                        .textute_id
-                        .clone();
-                    let texture: &RcTexture = self.get_texture(&texture_id).unwrap();
-                    let (flip_x, flip_y) = entity.borrow().get_flip();
-
-                    let current_rect = entity
-                        .borrow()
-                        .get_current_animation()
-                        .unwrap()
-                        .get_current_rect_flipped(texture.size(), flip_x, flip_y);
-                    let looped = entity
-                        .borrow_mut()
-                        .get_current_animation_mut()
-                        .unwrap()
-                        .safe_increment_frame(texture.size());
+            if let Some(current) = entity.borrow_mut().get_current_animation_mut() {
+                current.reset_duration();
+            }
+            if let Some(rect) = current_rect {
+                entity.borrow_mut().set_texture_rect(rect);
+            }
+        }
 
-                    if looped {
-                        entity
-                            .borrow_mut()
-                            .set_current_animation(return_state.as_str());
-                    }
+        if let Some(current) = entity.borrow_mut().get_current_animation_mut() {
+            current.increment_duration(get_render_ms());
+        }
+    }
 
-                    entity
-                        .borrow_mut()
-                        .get_current_animation_mut()
-                        .unwrap()
-                        .reset_duration();
-                    if current_rect.is_some() {
-                        entity.borrow_mut().set_texture_rect(current_rect.unwrap());
-                    }
-                }
+    fn crossfade(&self, entity: &RefCell<T>) {
+        let mut entity = entity.borrow_mut();
+        if entity.get_crossfade().is_none() {
+            return;
+        }
 
-                entity
-                    .borrow_mut()
-                    .get_current_animation_mut()
-                    .unwrap()
-                    .increment_duration(get_render_ms());
-            }
+        // Copy the real sprite transform, it carries the orientation
+        let position = entity.get_sprite().position();
+        let origin = entity.get_sprite().origin();
+        let scale = entity.get_sprite().get_scale();
+        let rotation = entity.get_sprite().rotation();
+
+        let fade = entity.get_crossfade_mut().unwrap();
+        fade.increment_duration(get_render_ms());
+        if fade.is_finished() {
+            entity.stop_crossfade();
+            return;
+        }
+
+        // Outgoing frame follows the entity while fading out
+        fade.outgoing.set_position(position);
+        fade.outgoing.set_origin(origin);
+        fade.outgoing.set_scale(scale);
+        fade.outgoing.set_rotation(rotation);
+        let outgoing_color = fade.outgoing_color();
+        let incoming_color = fade.incoming_color();
+        fade.outgoing.set_color(outgoing_color);
+        entity.get_sprite_mut().set_color(incoming_color);
+    }
+
+    fn animate_skeleton(&self, entity: &RefCell<T>) {
+        let mut entity = entity.borrow_mut();
+        if let Some(skeleton) = entity.get_skeleton_mut() {
+            let assets = self.assets.borrow();
+            skeleton.bind_textures(|id| assets.texture(id));
+            skeleton.update(get_render_ms());
         }
     }
 
@@ -631,27 +1013,538 @@ where
     }
 
 
-    pub fn render(&self, wnd: &mut RenderWindow) {
+    // Tileset textures that aren't loaded yet are loaded from their image path
+    pub fn add_tilemap(&mut self, name: &str, mut tilemap: Tilemap) {
+        for tileset in tilemap.tilesets.iter_mut() {
+            if self.get_texture(&tileset.texture_id).is_none() && !tileset.image_path.is_empty() {
+                if let Err(error) = self.load_texture(&tileset.texture_id, &tileset.image_path) {
+                    println!("Tilemap {}: {}", name, error);
+                }
+            }
+            match self.get_texture(&tileset.texture_id) {
+                Some(texture) => tileset.set_texture(&texture),
+                None => println!("Tilemap {}: no texture {} for tileset {}", name, tileset.texture_id, tileset.name),
+            }
+        }
+        tilemap.rebuild();
+        self.tilemaps.insert(name.to_string(), tilemap);
+    }
+
+    // Tiled .tmx or .json map
+    pub fn load_tilemap(&mut self, name: &str, path: &str) -> Result<(), TiledError> {
+        let tilemap = load_tiled_map(path)?;
+        self.add_tilemap(name, tilemap);
+        Ok(())
+    }
+
+    pub fn tilemap(&self, name: &str) -> Option<&Tilemap> {
+        self.tilemaps.get(name)
+    }
+
+    pub fn tilemap_mut(&mut self, name: &str) -> Option<&mut Tilemap> {
+        self.tilemaps.get_mut(name)
+    }
+
+    pub fn remove_tilemap(&mut self, name: &str) -> Option<Tilemap> {
+        self.tilemaps.shift_remove(name)
+    }
+
+    // Calls spawn for every object of the map, positions are in world coordinates. Returns how many entities were added.
+    pub fn spawn_tilemap_objects(&mut self, name: &str, spawn: fn(&mut Self, &TileObject) -> Option<T>) -> usize {
+        let objects: Vec<TileObject> = match self.tilemaps.get(name) {
+            Some(tilemap) => tilemap
+                .objects()
+                .map(|object| {
+                    let mut object = object.clone();
+                    object.x += tilemap.position.x;
+                    object.y += tilemap.position.y;
+                    object
+                })
+                .collect(),
+            None => return 0,
+        };
+
+        let mut spawned = 0;
+        for object in objects.iter() {
+            if let Some(entity) = spawn(self, object) {
+                self.add_entity(entity);
+                spawned += 1;
+            }
+        }
+        spawned
+    }
+
+    // Rebuilds changed tiles and pushes entities out of solid tiles, called by the game loop after entity collisions
+    pub fn update_tilemaps(&mut self) {
+        for tilemap in self.tilemaps.values_mut() {
+            tilemap.rebuild();
+        }
+
         for entity in self.entities.values() {
-            let show_collider_box = entity.borrow().get_physics().show_collider;
-            let show_solid_box = entity.borrow().get_physics().show_solid_box;
+            let mut entity = entity.borrow_mut();
+            let physics = entity.get_physics();
+            if physics.static_object || physics.ghost || !physics.collision_enabled {
+                continue;
+            }
+            let margin = physics.solid_box_margin;
+            for tilemap in self.tilemaps.values() {
+                for rect in tilemap.collision_rects() {
+                    let mut bounds = entity.global_bounds();
+                    bounds.left += margin.0;
+                    bounds.top += margin.1;
+                    bounds.width -= margin.0 * 2.0;
+                    bounds.height -= margin.1 * 2.0;
+                    let Some(overlap) = bounds.intersection(rect) else {
+                        continue;
+                    };
+
+                    // Out along the shortest axis, away from the tiles
+                    let entity_center = bounds.position() + bounds.size() / 2.0;
+                    let rect_center = rect.position() + rect.size() / 2.0;
+                    let push = if overlap.width < overlap.height {
+                        let direction = if entity_center.x < rect_center.x { -1.0 } else { 1.0 };
+                        Vector2f::new(overlap.width * direction, 0.0)
+                    } else {
+                        let direction = if entity_center.y < rect_center.y { -1.0 } else { 1.0 };
+                        Vector2f::new(0.0, overlap.height * direction)
+                    };
+                    entity.move_(push);
+                }
+            }
+        }
+    }
 
-            wnd.draw(entity.borrow().get_sprite());
-             // RENDER COLLIDER BOX
-             if show_collider_box {
-                let e = entity.borrow();
-                let hitbox = e.get_hitbox();
-                wnd.draw(&hitbox);
+    // The texture must be loaded, repeat is turned on for it when the layer repeats
+    pub fn add_parallax_layer(&mut self, mut layer: ParallaxLayer) {
+        if layer.repeat_x || layer.repeat_y {
+            if let Some(texture) = self.assets_mut().texture_mut(&layer.texture_id) {
+                texture.set_repeated(true);
             }
-            
-            // RENDER SOLID BOX
-            if show_solid_box {
-                let margin = entity.borrow().get_physics().solid_box_margin;
+        }
+        match self.get_texture(&layer.texture_id) {
+            Some(texture) => layer.set_texture(&texture),
+            None => println!("Parallax layer {}: no texture {}", layer.name, layer.texture_id),
+        }
+        self.parallax_layers.push(layer);
+    }
+
+    pub fn parallax_layer(&self, name: &str) -> Option<&ParallaxLayer> {
+        self.parallax_layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn parallax_layer_mut(&mut self, name: &str) -> Option<&mut ParallaxLayer> {
+        self.parallax_layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    pub fn remove_parallax_layer(&mut self, name: &str) -> Option<ParallaxLayer> {
+        let index = self.parallax_layers.iter().position(|layer| layer.name == name)?;
+        Some(self.parallax_layers.remove(index))
+    }
+
+    // Auto scroll, called by the game loop
+    pub fn update_parallax(&mut self) {
+        let delta = get_render_ms();
+        for layer in self.parallax_layers.iter_mut() {
+            layer.update(delta);
+        }
+    }
+
+    fn anchor_position(&self, anchor: &EmitterAnchor) -> Option<Vector2f> {
+        match anchor {
+            EmitterAnchor::World(position) => Some(*position),
+            EmitterAnchor::Entity { id, offset } => self.try_entity(id).map(|entity| {
+                let bounds = entity.borrow().get_sprite().global_bounds();
+                Vector2f::new(bounds.left + bounds.width / 2.0, bounds.top + bounds.height / 2.0) + *offset
+            }),
+        }
+    }
+
+    // Emitters with the same name are replaced, the texture must be loaded
+    pub fn add_emitter(&mut self, mut emitter: ParticleEmitter) {
+        if let Some(texture_id) = emitter.texture_id.clone() {
+            match self.get_texture(&texture_id) {
+                Some(texture) => emitter.set_texture(&texture),
+                None => println!("Emitter {}: no texture {}", emitter.name, texture_id),
+            }
+        }
+        if let Some(position) = self.anchor_position(&emitter.anchor) {
+            emitter.set_position(position);
+        }
+        self.emitters.retain(|existing| existing.name != emitter.name);
+        self.emitters.push(emitter);
+    }
+
+    pub fn emitter(&self, name: &str) -> Option<&ParticleEmitter> {
+        self.emitters.iter().find(|emitter| emitter.name == name)
+    }
+
+    pub fn emitter_mut(&mut self, name: &str) -> Option<&mut ParticleEmitter> {
+        self.emitters.iter_mut().find(|emitter| emitter.name == name)
+    }
+
+    pub fn remove_emitter(&mut self, name: &str) -> Option<ParticleEmitter> {
+        let index = self.emitters.iter().position(|emitter| emitter.name == name)?;
+        Some(self.emitters.remove(index))
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.emitters.iter().map(ParticleEmitter::particle_count).sum()
+    }
+
+    // Spawns right away, within the emitter and scene caps. Returns how many were spawned.
+    pub fn burst(&mut self, name: &str, count: usize) -> usize {
+        let budget = self.max_particles.saturating_sub(self.particle_count());
+        match self.emitter_mut(name) {
+            Some(emitter) => emitter.burst(count.min(budget)),
+            None => 0,
+        }
+    }
+
+    // Moves and spawns particles, finished one shot emitters are removed. Called by the game loop.
+    pub fn update_particles(&mut self) {
+        let delta = get_render_ms();
+        let positions: Vec<Option<Vector2f>> = self.emitters.iter().map(|emitter| self.anchor_position(&emitter.anchor)).collect();
+        let mut budget = self.max_particles.saturating_sub(self.particle_count());
+        for (emitter, position) in self.emitters.iter_mut().zip(positions) {
+            let before = emitter.particle_count();
+            emitter.update(delta, position, budget);
+            budget = budget.saturating_sub(emitter.particle_count().saturating_sub(before));
+        }
+        self.emitters.retain(|emitter| !(emitter.remove_when_done && emitter.is_finished()));
+    }
+
+    // Extra cameras for split screen or a minimap, each with its own viewport and layers
+    pub fn add_camera(&mut self, name: &str, camera: Camera) {
+        self.cameras.insert(name.to_string(), camera);
+    }
+
+    pub fn get_camera(&self, name: &str) -> Option<&Camera> {
+        self.cameras.get(name)
+    }
+
+    pub fn get_camera_mut(&mut self, name: &str) -> Option<&mut Camera> {
+        self.cameras.get_mut(name)
+    }
+
+    pub fn remove_camera(&mut self, name: &str) -> Option<Camera> {
+        self.cameras.shift_remove(name)
+    }
+
+    // Main camera first, then the others in draw order
+    pub fn all_cameras(&self) -> Vec<&Camera> {
+        std::iter::once(&self.camera).chain(self.cameras.values()).collect()
+    }
+
+    // Called by the game loop when it knows the window size
+    pub fn set_window_size(&mut self, size: Vector2u) {
+        self.camera.set_window_size(size);
+        for camera in self.cameras.values_mut() {
+            camera.set_window_size(size);
+        }
+    }
+
+    fn camera_target(&self, camera: &Camera) -> Option<Vector2f> {
+        camera.target().and_then(|id| self.try_entity(id)).map(|entity| {
+            let bounds = entity.borrow().get_sprite().global_bounds();
+            Vector2f::new(bounds.left + bounds.width / 2.0, bounds.top + bounds.height / 2.0)
+        })
+    }
+
+    // Moves the cameras towards their targets, called by the game loop after on_update
+    pub fn update_camera(&mut self) {
+        let target = self.camera_target(&self.camera);
+        self.camera.update(get_render_ms(), target);
+
+        let targets: Vec<Option<Vector2f>> = self.cameras.values().map(|camera| self.camera_target(camera)).collect();
+        for (camera, target) in self.cameras.values_mut().zip(targets) {
+            camera.update(get_render_ms(), target);
+        }
+    }
+
+    // Through the top most enabled camera under the point
+    pub fn screen_to_world(&self, point: Vector2i) -> Vector2f {
+        let camera = self
+            .all_cameras()
+            .into_iter()
+            .rev()
+            .find(|camera| camera.enabled && camera.contains_screen_point(point))
+            .unwrap_or(&self.camera);
+        camera.screen_to_world(point)
+    }
+
+    // Through the main camera only, a point can be on screen in several cameras
+    pub fn world_to_screen(&self, point: Vector2f) -> Vector2i {
+        self.camera.world_to_screen(point)
+    }
+
+    // Through one of the extra cameras, None if there is no camera with that name
+    pub fn world_to_screen_with(&self, camera: &str, point: Vector2f) -> Option<Vector2i> {
+        self.get_camera(camera).map(|camera| camera.world_to_screen(point))
+    }
+
+    pub fn set_layer_sort(&mut self, layer: i32, sort: LayerSort) {
+        self.layer_sort.insert(layer, sort);
+    }
+
+    pub fn layer_sort(&self, layer: i32) -> LayerSort {
+        self.layer_sort.get(&layer).copied().unwrap_or(LayerSort::Insertion)
+    }
+
+    // Entities in draw order: layer, z index, then insertion order or y. The sort is stable.
+    pub fn render_order(&self) -> Vec<&RefCell<T>> {
+        let mut order: Vec<(i32, i32, f32, &RefCell<T>)> = self
+            .entities
+            .values()
+            .map(|entity| {
                 let e = entity.borrow();
-                let hitbox = e.get_hitbox_with_margin(margin);
-                wnd.draw(&hitbox);
+                let layer = e.get_layer();
+                let y = match self.layer_sort(layer) {
+                    LayerSort::YSort => {
+                        let bounds = e.get_sprite().global_bounds();
+                        bounds.top + bounds.height
+                    }
+                    LayerSort::Insertion => 0.0,
+                };
+                (layer, e.get_z_index(), y, entity)
+            })
+            .collect();
+        order.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.total_cmp(&b.2)));
+        order.into_iter().map(|(_, _, _, entity)| entity).collect()
+    }
+
+    // Loads a shader for entities or post passes, "texture" is bound to the texture being drawn
+    pub fn load_shader(&mut self, id: &str, path: &str) -> Result<(), AssetError> {
+        self.shaders.load(id.to_string(), path)?;
+        if let Some(shader) = self.shaders.try_get_mut(&id.to_string()) {
+            shader.set_uniform_current_texture("texture");
+        }
+        Ok(())
+    }
+
+    pub fn load_shader_from_memory(&mut self, id: &str, source: &str) -> Result<(), AssetError> {
+        self.shaders.load_from_memory(id.to_string(), source.as_bytes())?;
+        if let Some(shader) = self.shaders.try_get_mut(&id.to_string()) {
+            shader.set_uniform_current_texture("texture");
+        }
+        Ok(())
+    }
+
+    // Built in passes load their shader the first time they're added
+    pub fn add_post_pass(&mut self, pass: PostPass) -> Result<(), AssetError> {
+        if !self.shaders.contains(&pass.shader_id) {
+            if let Some(source) = builtin_post_shader(&pass.shader_id) {
+                self.load_shader_from_memory(&pass.shader_id, source)?;
+            }
+        }
+        self.post_process.add_pass(pass);
+        Ok(())
+    }
+
+    // Draw calls and batches of the last rendered frame
+    pub fn render_stats(&self) -> RenderStats {
+        self.render_stats.get()
+    }
+
+    fn draw_entity<'a>(&self, wnd: &mut dyn RenderTarget, entity: &'a T, batch: &mut SpriteBatch<'a>, stats: &mut RenderStats) {
+        let show_collider_box = entity.get_physics().show_collider;
+        let show_solid_box = entity.get_physics().show_solid_box;
+        let shader = entity.get_shader().and_then(|id| self.shaders.try_get(&id.to_string()));
+
+        if let Some(shader) = shader {
+            // Can't share a batch, the shader applies to this entity only
+            batch.flush(wnd, stats);
+            let states = RenderStates::new(BlendMode::ALPHA, Transform::IDENTITY, None, Some(shader));
+            if let Some(fade) = entity.get_crossfade() {
+                wnd.draw_with_renderstates(&fade.outgoing, &states);
+                stats.draw_calls += 1;
+            }
+            wnd.draw_with_renderstates(entity.get_sprite(), &states);
+            stats.draw_calls += 1;
+            if let Some(skeleton) = entity.get_skeleton() {
+                let mut skeleton_states = states;
+                skeleton_states.transform = *entity.get_sprite().transform();
+                skeleton.draw(wnd, &skeleton_states);
+                stats.draw_calls += skeleton.drawn_parts() as u32;
+            }
+        } else if self.batching {
+            if let Some(fade) = entity.get_crossfade() {
+                batch.push(wnd, &fade.outgoing, stats);
+            }
+            batch.push(wnd, entity.get_sprite(), stats);
+            if let Some(skeleton) = entity.get_skeleton() {
+                skeleton.draw_batched(batch, wnd, entity.get_sprite().transform(), stats);
+            }
+        } else {
+            if let Some(fade) = entity.get_crossfade() {
+                wnd.draw(&fade.outgoing);
+                stats.draw_calls += 1;
+            }
+            wnd.draw(entity.get_sprite());
+            stats.draw_calls += 1;
+            if let Some(skeleton) = entity.get_skeleton() {
+                let mut skeleton_states = RenderStates::default();
+                skeleton_states.transform = *entity.get_sprite().transform();
+                skeleton.draw(wnd, &skeleton_states);
+                stats.draw_calls += skeleton.drawn_parts() as u32;
+            }
+        }
+
+        if !show_collider_box && !show_solid_box {
+            return;
+        }
+        // Boxes go over the sprite, what is batched so far is drawn first
+        batch.flush(wnd, stats);
+
+         // RENDER COLLIDER BOX
+         if show_collider_box {
+            let hitbox = entity.get_hitbox();
+            wnd.draw(&hitbox);
+            stats.draw_calls += 1;
+        }
+        
+        // RENDER SOLID BOX
+        if show_solid_box {
+            let margin = entity.get_physics().solid_box_margin;
+            let hitbox = entity.get_hitbox_with_margin(margin);
+            wnd.draw(&hitbox);
+            stats.draw_calls += 1;
+        }
+    }
+
+    // Visible area grown to cover rotation and shake
+    fn culling_area(camera: &Camera) -> FloatRect {
+        let area = camera.visible_area();
+        let side = (area.width * area.width + area.height * area.height).sqrt() + camera.max_shake_offset * 2.0;
+        FloatRect::new(area.left + area.width / 2.0 - side / 2.0, area.top + area.height / 2.0 - side / 2.0, side, side)
+    }
+
+    fn draw_cameras(&self, target: &mut dyn RenderTarget, stats: &mut RenderStats) {
+        let entities: Vec<Ref<T>> = self.render_order().into_iter().map(|entity| entity.borrow()).collect();
+        // Parallax first, so it stays behind tiles of the same layer, particles go over tiles
+        let mut layer_items: Vec<(i32, LayerItem)> = self
+            .parallax_layers
+            .iter()
+            .map(|layer| (layer.render_layer, LayerItem::Parallax(layer)))
+            .collect();
+        for tilemap in self.tilemaps.values() {
+            for (index, layer) in tilemap.layers().iter().enumerate() {
+                layer_items.push((layer.render_layer, LayerItem::Tiles(tilemap, index)));
+            }
+        }
+        for emitter in self.emitters.iter() {
+            layer_items.push((emitter.render_layer, LayerItem::Particles(emitter)));
+        }
+        layer_items.sort_by_key(|(render_layer, _)| *render_layer);
+
+        for camera in self.all_cameras() {
+            if !camera.enabled {
+                continue;
+            }
+
+            target.set_view(&camera.view());
+            let visible_area = Self::culling_area(camera);
+            let mut batch = SpriteBatch::new();
+            let mut next_item = 0;
+            for entity in entities.iter() {
+                let layer = entity.get_layer();
+                if !camera.shows_layer(layer) {
+                    continue;
+                }
+                // Tiles, parallax and particles go under the entities of their layer
+                while next_item < layer_items.len() && layer_items[next_item].0 <= layer {
+                    let (item_layer, item) = &layer_items[next_item];
+                    if camera.shows_layer(*item_layer) {
+                        batch.flush(target, stats);
+                        item.draw(target, camera, visible_area, stats);
+                    }
+                    next_item += 1;
+                }
+                self.draw_entity(target, entity, &mut batch, stats);
+            }
+            batch.flush(target, stats);
+            for (item_layer, item) in layer_items[next_item..].iter() {
+                if camera.shows_layer(*item_layer) {
+                    item.draw(target, camera, visible_area, stats);
+                }
+            }
+
+            // SCREEN FADE AND FLASH
+            let overlays = camera.overlays();
+            if !overlays.is_empty() {
+                target.set_view(&camera.screen_view());
+                let mut overlay = RectangleShape::with_size(camera.viewport_size());
+                for color in overlays {
+                    overlay.set_fill_color(color);
+                    target.draw(&overlay);
+                    stats.draw_calls += 1;
+                }
+            }
+        }
+    }
+
+    pub fn render(&mut self, wnd: &mut RenderWindow) {
+        let mut stats = RenderStats::default();
+        for tilemap in self.tilemaps.values_mut() {
+            tilemap.rebuild();
+        }
+        self.post_process.advance(get_render_ms());
+
+        let targets = if self.post_process.enabled {
+            self.post_process.take_targets(wnd.size())
+        } else {
+            None
+        };
+        let Some((mut scene_target, mut pass_target)) = targets else {
+            self.draw_cameras(wnd, &mut stats);
+            self.render_stats.set(stats);
+            return;
+        };
+
+        scene_target.clear(self.post_process.clear_color);
+        self.draw_cameras(&mut scene_target, &mut stats);
+        scene_target.display();
+
+        // POST PROCESSING, the frame goes back and forth between the two textures
+        let size = wnd.size();
+        let resolution = Vec2::new(size.x as f32, size.y as f32);
+        let time = self.post_process.time_seconds();
+        let passes: Vec<PostPass> = self.post_process.passes.iter().filter(|pass| pass.enabled).cloned().collect();
+        let view = wnd.default_view().to_owned();
+        wnd.set_view(&view);
+
+        for (index, pass) in passes.iter().enumerate() {
+            let Some(shader) = self.shaders.try_get_mut(&pass.shader_id) else {
+                println!("Post pass {} has no shader: {}", pass.name, pass.shader_id);
+                continue;
+            };
+            shader.set_uniform_vec2("resolution", resolution);
+            shader.set_uniform_float("time", time);
+            for (name, value) in pass.uniforms.iter() {
+                value.apply(shader, name);
+            }
+
+            let states = RenderStates::new(BlendMode::ALPHA, Transform::IDENTITY, None, Some(&*shader));
+            let frame = Sprite::with_texture(scene_target.texture());
+            stats.draw_calls += 1;
+            if index + 1 == passes.len() {
+                wnd.draw_with_renderstates(&frame, &states);
+                drop(frame);
+                self.render_stats.set(stats);
+                self.post_process.return_targets((scene_target, pass_target));
+                return;
             }
+            pass_target.clear(self.post_process.clear_color);
+            pass_target.draw_with_renderstates(&frame, &states);
+            pass_target.display();
+            drop(frame);
+            std::mem::swap(&mut scene_target, &mut pass_target);
         }
+
+        // No pass left for the window, the frame is drawn as it is
+        wnd.draw(&Sprite::with_texture(scene_target.texture()));
+        stats.draw_calls += 1;
+        self.render_stats.set(stats);
+        self.post_process.return_targets((scene_target, pass_target));
     }
 
     fn apply_gravity(&self, entity: &RefCell<T>) {
@@ -678,14 +1571,21 @@ where
                 continue;
             }
             
-            if entity.borrow().get_current_animation().is_none() {
-                let empty = self.get_texture("empty").unwrap();
-                entity.borrow_mut().set_texture(empty, true);
+            // SKELETAL ANIMATION
+            self.animate_skeleton(entity);
+
+            if entity.borrow().get_current_animation().is_none() && entity.borrow().get_skeleton().is_none() {
+                if let Some(empty) = self.get_texture("empty") {
+                    entity.borrow_mut().set_texture(&empty, true);
+                }
                 continue;
             }
 
             // ANIMATION STUFF
-            self.animate(entity);
+            if entity.borrow().get_current_animation().is_some() {
+                self.animate(entity);
+            }
+            self.crossfade(entity);
             
             // PHYSICS STUFF
             if entity.borrow().get_physics().collision_enabled {
@@ -703,6 +1603,12 @@ where
             i += 1;
         }
 
+        // TWEENS
+        let finished_tweens = self.tweens.update(get_render_ms(), &self.entities);
+        for (id, on_complete) in finished_tweens {
+            on_complete(self, id);
+        }
+
         for coll in collisions.iter() {
             (self.on_collision)(self, coll.0.clone(), coll.1.clone(), coll.2.clone());
         }
@@ -738,4 +1644,26 @@ pub fn closest_distance(rect1:Rect<f32>, rect2:Rect<f32>)->Vector2f{
     }
 
     return distance;
-    }
\ No newline at end of file
+    }
+
+// State tracked from window events, joystick axes go through the dead zone
+impl<T> InputSource for Scene<T>
+where
+    T: Drawable + Transformable + EntityTrait,
+{
+    fn key_down(&self, key: Key) -> bool {
+        self.check_key(key)
+    }
+
+    fn mouse_down(&self, button: mouse::Button) -> bool {
+        self.check_mouse_button(button)
+    }
+
+    fn joystick_button_down(&self, joystick: u32, button: u32) -> bool {
+        self.check_joystick_button(joystick, button)
+    }
+
+    fn joystick_axis(&self, joystick: u32, axis: joystick::Axis) -> f32 {
+        Scene::joystick_axis(self, joystick, axis)
+    }
+}
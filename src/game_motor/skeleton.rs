@@ -0,0 +1,469 @@
+use std::{collections::HashMap, fmt};
+
+use sfml::{
+    graphics::{IntRect, RcSprite, RcTexture, RenderStates, RenderTarget, Transform, Transformable},
+    system::Vector2f,
+};
+
+use super::prelude::*;
+
+// Translation, rotation (degrees) and scale of a bone relative to its parent
+#[derive(Debug, Clone, Copy)]
+pub struct BoneTransform {
+    pub translation: Vector2f,
+    pub rotation: f32,
+    pub scale: Vector2f,
+}
+
+impl BoneTransform {
+    pub fn new(translation: Vector2f, rotation: f32, scale: Vector2f) -> Self {
+        BoneTransform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Self {
+        BoneTransform::new(Vector2f::new(0.0, 0.0), 0.0, Vector2f::new(1.0, 1.0))
+    }
+
+    pub fn at(x: f32, y: f32) -> Self {
+        BoneTransform::new(Vector2f::new(x, y), 0.0, Vector2f::new(1.0, 1.0))
+    }
+
+    pub fn to_transform(self) -> Transform {
+        let mut transform = Transform::IDENTITY;
+        transform.translate(self.translation.x, self.translation.y);
+        transform.rotate(self.rotation);
+        transform.scale(self.scale.x, self.scale.y);
+        transform
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bone {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub rest: BoneTransform,
+    pub pose: BoneTransform,
+}
+
+// Sprite piece that moves with a bone
+#[derive(Clone)]
+pub struct BonePart {
+    pub bone: usize,
+    pub texture_id: String,
+    pub texture_rect: IntRect,
+    pub origin: Vector2f,
+    pub z: i32,
+    sprite: RcSprite,
+}
+
+pub trait Interpolate: Copy {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+impl Interpolate for Vector2f {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+// Easing is used on the way from this key to the next one
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe<V: Interpolate> {
+    pub time_ms: f32,
+    pub value: V,
+    pub easing: Easing,
+}
+
+impl<V: Interpolate> Keyframe<V> {
+    pub fn new(time_ms: f32, value: V, easing: Easing) -> Self {
+        Keyframe { time_ms, value, easing }
+    }
+}
+
+fn sample_keys<V: Interpolate>(keys: &[Keyframe<V>], time_ms: f32) -> Option<V> {
+    let first = keys.first()?;
+    if time_ms <= first.time_ms {
+        return Some(first.value);
+    }
+
+    for pair in keys.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if time_ms < b.time_ms {
+            let span = b.time_ms - a.time_ms;
+            let t = if span <= 0.0 { 1.0 } else { (time_ms - a.time_ms) / span };
+            return Some(V::interpolate(a.value, b.value, a.easing.apply(t)));
+        }
+    }
+
+    keys.last().map(|key| key.value)
+}
+
+// Tracks are offsets on top of the rest pose, rotation and translation are added and scale is multiplied
+#[derive(Debug, Clone)]
+pub enum BoneTrack {
+    Translation { bone: String, keys: Vec<Keyframe<Vector2f>> },
+    Rotation { bone: String, keys: Vec<Keyframe<f32>> },
+    Scale { bone: String, keys: Vec<Keyframe<Vector2f>> },
+}
+
+impl BoneTrack {
+    fn bone(&self) -> &str {
+        match self {
+            BoneTrack::Translation { bone, .. } => bone,
+            BoneTrack::Rotation { bone, .. } => bone,
+            BoneTrack::Scale { bone, .. } => bone,
+        }
+    }
+
+    fn sort_keys(&mut self) {
+        match self {
+            BoneTrack::Translation { keys, .. } => keys.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms)),
+            BoneTrack::Rotation { keys, .. } => keys.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms)),
+            BoneTrack::Scale { keys, .. } => keys.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SkeletalAnimation {
+    pub duration_ms: f32,
+    pub looped: bool,
+    pub tracks: Vec<BoneTrack>,
+}
+
+impl SkeletalAnimation {
+    pub fn new(duration_ms: f32, looped: bool) -> Self {
+        SkeletalAnimation {
+            duration_ms,
+            looped,
+            tracks: Vec::new(),
+        }
+    }
+
+    pub fn add_track(&mut self, track: BoneTrack) {
+        let mut track = track;
+        track.sort_keys();
+        self.tracks.push(track);
+    }
+
+    pub fn rotation_track(&mut self, bone: &str, keys: Vec<Keyframe<f32>>) {
+        self.add_track(BoneTrack::Rotation { bone: bone.to_string(), keys });
+    }
+
+    pub fn translation_track(&mut self, bone: &str, keys: Vec<Keyframe<Vector2f>>) {
+        self.add_track(BoneTrack::Translation { bone: bone.to_string(), keys });
+    }
+
+    pub fn scale_track(&mut self, bone: &str, keys: Vec<Keyframe<Vector2f>>) {
+        self.add_track(BoneTrack::Scale { bone: bone.to_string(), keys });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkeletonError {
+    DuplicateBone { bone: String },
+    MissingParent { bone: String, parent: String },
+    DuplicateAnimation { animation: String },
+}
+
+impl fmt::Display for SkeletonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkeletonError::DuplicateBone { bone } => write!(f, "Bone already exists: {}", bone),
+            SkeletonError::MissingParent { bone, parent } => write!(f, "Parent bone not found: {}, needed by {}", parent, bone),
+            SkeletonError::DuplicateAnimation { animation } => write!(f, "Skeletal animation already exists: {}", animation),
+        }
+    }
+}
+
+impl std::error::Error for SkeletonError {}
+
+#[derive(Clone)]
+pub struct Skeleton {
+    bones: Vec<Bone>,
+    parts: Vec<BonePart>,
+    animations: HashMap<String, SkeletalAnimation>,
+    current_animation: Option<String>,
+    time_ms: f32,
+}
+
+impl Skeleton {
+    pub fn new() -> Self {
+        Skeleton {
+            bones: Vec::new(),
+            parts: Vec::new(),
+            animations: HashMap::new(),
+            current_animation: None,
+            time_ms: 0.0,
+        }
+    }
+
+    // Parents have to be added before their children. Duplicates are skipped with a warning.
+    // A bone whose parent is missing is attached to the root
+    pub fn add_bone(&mut self, name: &str, parent: Option<&str>, rest: BoneTransform) {
+        match self.try_add_bone(name, parent, rest) {
+            Ok(()) => {}
+            Err(error @ SkeletonError::MissingParent { .. }) => {
+                println!("{}, attaching {} to root", error, name);
+                let _ = self.try_add_bone(name, None, rest);
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    pub fn try_add_bone(&mut self, name: &str, parent: Option<&str>, rest: BoneTransform) -> Result<(), SkeletonError> {
+        if self.bone_index(name).is_some() {
+            return Err(SkeletonError::DuplicateBone { bone: name.to_string() });
+        }
+
+        let parent = match parent {
+            Some(parent_name) => Some(self.bone_index(parent_name).ok_or_else(|| SkeletonError::MissingParent {
+                bone: name.to_string(),
+                parent: parent_name.to_string(),
+            })?),
+            None => None,
+        };
+
+        self.bones.push(Bone {
+            name: name.to_string(),
+            parent,
+            rest,
+            pose: rest,
+        });
+        Ok(())
+    }
+
+    // Origin is the pivot inside the texture rect that sits on the bone
+    pub fn add_part(&mut self, bone: &str, texture_id: &str, texture_rect: IntRect, origin: Vector2f, z: i32) {
+        let bone_index = match self.bone_index(bone) {
+            Some(index) => index,
+            None => {
+                println!("Bone not found: {}", bone);
+                return;
+            }
+        };
+
+        let mut sprite = RcSprite::new();
+        sprite.set_origin(origin);
+        self.parts.push(BonePart {
+            bone: bone_index,
+            texture_id: texture_id.to_string(),
+            texture_rect,
+            origin,
+            z,
+            sprite,
+        });
+        self.parts.sort_by_key(|part| part.z);
+    }
+
+    pub fn add_animation(&mut self, name: &str, animation: SkeletalAnimation) {
+        if let Err(error) = self.try_add_animation(name, animation) {
+            println!("{}", error);
+        }
+    }
+
+    pub fn try_add_animation(&mut self, name: &str, animation: SkeletalAnimation) -> Result<(), SkeletonError> {
+        if self.animations.contains_key(name) {
+            return Err(SkeletonError::DuplicateAnimation { animation: name.to_string() });
+        }
+
+        if self.animations.is_empty() {
+            self.current_animation = Some(name.to_string());
+        }
+
+        self.animations.insert(name.to_string(), animation);
+        Ok(())
+    }
+
+    pub fn set_current_animation(&mut self, name: &str) {
+        if !self.animations.contains_key(name) {
+            println!("Skeletal animation not found: {}", name);
+            return;
+        }
+
+        if self.current_animation.as_deref() == Some(name) {
+            return;
+        }
+
+        self.current_animation = Some(name.to_string());
+        self.time_ms = 0.0;
+    }
+
+    pub fn current_animation(&self) -> Option<&str> {
+        self.current_animation.as_deref()
+    }
+
+    pub fn bone_index(&self, name: &str) -> Option<usize> {
+        self.bones.iter().position(|bone| bone.name == name)
+    }
+
+    pub fn get_bone(&self, name: &str) -> Option<&Bone> {
+        self.bones.iter().find(|bone| bone.name == name)
+    }
+
+    pub fn get_bones(&self) -> &Vec<Bone> {
+        &self.bones
+    }
+
+    pub fn get_parts(&self) -> &Vec<BonePart> {
+        &self.parts
+    }
+
+    // Gives every part its texture, parts whose texture is missing stay invisible
+    pub fn bind_textures<'a>(&mut self, get_texture: impl Fn(&str) -> Option<&'a RcTexture>) {
+        for part in self.parts.iter_mut() {
+            if part.sprite.texture().is_some() {
+                continue;
+            }
+            if let Some(texture) = get_texture(&part.texture_id) {
+                part.sprite.set_texture(texture, false);
+                part.sprite.set_texture_rect(part.texture_rect);
+            }
+        }
+    }
+
+    pub fn update(&mut self, duration: f32) {
+        let animation = match self.current_animation.as_ref().and_then(|name| self.animations.get(name)) {
+            Some(animation) => animation,
+            None => return,
+        };
+
+        self.time_ms += duration;
+        if animation.duration_ms > 0.0 {
+            if animation.looped {
+                self.time_ms %= animation.duration_ms;
+            } else {
+                self.time_ms = self.time_ms.min(animation.duration_ms);
+            }
+        }
+
+        for bone in self.bones.iter_mut() {
+            bone.pose = bone.rest;
+        }
+
+        for track in animation.tracks.iter() {
+            let index = match self.bones.iter().position(|bone| bone.name == track.bone()) {
+                Some(index) => index,
+                None => continue,
+            };
+            let bone = &mut self.bones[index];
+            match track {
+                BoneTrack::Translation { keys, .. } => {
+                    if let Some(value) = sample_keys(keys, self.time_ms) {
+                        bone.pose.translation = bone.rest.translation + value;
+                    }
+                }
+                BoneTrack::Rotation { keys, .. } => {
+                    if let Some(value) = sample_keys(keys, self.time_ms) {
+                        bone.pose.rotation = bone.rest.rotation + value;
+                    }
+                }
+                BoneTrack::Scale { keys, .. } => {
+                    if let Some(value) = sample_keys(keys, self.time_ms) {
+                        bone.pose.scale = Vector2f::new(bone.rest.scale.x * value.x, bone.rest.scale.y * value.y);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.current_animation.as_ref().and_then(|name| self.animations.get(name)) {
+            Some(animation) => !animation.looped && self.time_ms >= animation.duration_ms,
+            None => true,
+        }
+    }
+
+    // Bone transforms in skeleton space, same order as bones
+    pub fn world_transforms(&self) -> Vec<Transform> {
+        let mut transforms: Vec<Transform> = Vec::with_capacity(self.bones.len());
+        for bone in self.bones.iter() {
+            let local = bone.pose.to_transform();
+            let world = match bone.parent {
+                Some(parent) => {
+                    let mut world = transforms[parent];
+                    world.combine(&local);
+                    world
+                }
+                None => local,
+            };
+            transforms.push(world);
+        }
+        transforms
+    }
+
+    // Parts go on top of states.transform and keep its blend mode and shader
+    pub fn draw(&self, target: &mut dyn RenderTarget, states: &RenderStates) {
+        let transforms = self.world_transforms();
+        for part in self.parts.iter() {
+            if part.sprite.texture().is_none() {
+                continue;
+            }
+            let mut part_states = *states;
+            part_states.transform.combine(&transforms[part.bone]);
+            target.draw_with_renderstates(&part.sprite, &part_states);
+        }
+    }
+
+    // Parts that have a texture, each one is a draw call
+    pub fn drawn_parts(&self) -> usize {
+        self.parts.iter().filter(|part| part.sprite.texture().is_some()).count()
+    }
+
+    // Same as draw, but the parts go through the batch
+    pub fn draw_batched<'a>(
+        &'a self,
+        batch: &mut SpriteBatch<'a>,
+        target: &mut dyn RenderTarget,
+        base: &Transform,
+        stats: &mut RenderStats,
+    ) {
+        let transforms = self.world_transforms();
+        for part in self.parts.iter() {
+            let mut transform = *base;
+            transform.combine(&transforms[part.bone]);
+            batch.push_transformed(target, &part.sprite, &transform, stats);
+        }
+    }
+}
+
+impl Default for Skeleton {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_add_bone_reports_missing_parents_and_duplicates() {
+        let mut skeleton = Skeleton::new();
+        assert_eq!(skeleton.try_add_bone("root", None, BoneTransform::identity()), Ok(()));
+        assert_eq!(
+            skeleton.try_add_bone("hand", Some("arm"), BoneTransform::identity()),
+            Err(SkeletonError::MissingParent { bone: "hand".to_string(), parent: "arm".to_string() })
+        );
+        assert_eq!(skeleton.bone_index("hand"), None);
+        assert_eq!(
+            skeleton.try_add_bone("root", None, BoneTransform::identity()),
+            Err(SkeletonError::DuplicateBone { bone: "root".to_string() })
+        );
+
+        // add_bone still attaches an orphan to the root
+        skeleton.add_bone("hand", Some("arm"), BoneTransform::identity());
+        let hand = skeleton.bone_index("hand").unwrap();
+        assert_eq!(skeleton.get_bones()[hand].parent, None);
+    }
+}
@@ -0,0 +1,1107 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use sfml::system::Vector2f;
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TiledError {
+    Io { path: String, reason: String },
+    Parse { path: String, reason: String },
+    // Infinite maps, compressed layer data, non orthogonal maps
+    Unsupported { path: String, reason: String },
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Io { path, reason } => write!(f, "Can't read map {}: {}", path, reason),
+            TiledError::Parse { path, reason } => write!(f, "Invalid map {}: {}", path, reason),
+            TiledError::Unsupported { path, reason } => write!(f, "Unsupported map {}: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+// Loads a Tiled map saved as .tmx or .json/.tmj. Tileset images are given as paths
+// relative to the working directory, they are also used as texture ids.
+pub fn load_tiled_map<P: AsRef<Path>>(path: P) -> Result<Tilemap, TiledError> {
+    let path = path.as_ref();
+    let text = read_text(path)?;
+    let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tmx") => parse_tmx(&text, &base).map_err(|error| error.with_path(path)),
+        Some("json") | Some("tmj") => parse_tiled_json(&text, &base).map_err(|error| error.with_path(path)),
+        _ => Err(TiledError::Unsupported {
+            path: path.display().to_string(),
+            reason: "expected a .tmx, .tmj or .json file".to_string(),
+        }),
+    }
+}
+
+fn read_text(path: &Path) -> Result<String, TiledError> {
+    fs::read_to_string(path).map_err(|error| TiledError::Io { path: path.display().to_string(), reason: error.to_string() })
+}
+
+// Errors inside the parsers don't know the file yet
+#[derive(Debug)]
+enum MapError {
+    Parse(String),
+    Unsupported(String),
+    File(TiledError),
+}
+
+impl MapError {
+    fn with_path(self, path: &Path) -> TiledError {
+        let path = path.display().to_string();
+        match self {
+            MapError::Parse(reason) => TiledError::Parse { path, reason },
+            MapError::Unsupported(reason) => TiledError::Unsupported { path, reason },
+            MapError::File(error) => error,
+        }
+    }
+}
+
+fn parse_error(reason: &str) -> MapError {
+    MapError::Parse(reason.to_string())
+}
+
+fn new_map(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Result<Tilemap, MapError> {
+    if width.checked_mul(height).is_none() {
+        return Err(parse_error("map size overflows"));
+    }
+    Ok(Tilemap::new(width, height, tile_width, tile_height))
+}
+
+// Every tile layer covers the whole map
+fn check_layer_size(map: &Tilemap, width: u32, height: u32, tiles: usize) -> Result<(), MapError> {
+    let count = width.checked_mul(height).ok_or_else(|| parse_error("layer size overflows"))?;
+    if width != map.width || height != map.height {
+        return Err(MapError::Parse(format!("layer is {}x{} tiles but the map is {}x{}", width, height, map.width, map.height)));
+    }
+    if tiles != count as usize {
+        return Err(parse_error("layer data doesn't match the layer size"));
+    }
+    Ok(())
+}
+
+fn join_path(base: &Path, relative: &str) -> PathBuf {
+    let mut path = base.to_path_buf();
+    for part in relative.split('/') {
+        match part {
+            "." | "" => {}
+            ".." => {
+                if !path.pop() {
+                    path.push("..");
+                }
+            }
+            part => path.push(part),
+        }
+    }
+    path
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Layer properties the scene understands: "solid" (bool) and "render_layer" (int)
+fn apply_layer_properties(layer: &mut TileLayer) {
+    if let Some(solid) = layer.properties.get("solid") {
+        layer.solid = solid == "true";
+    }
+    if let Some(render_layer) = layer.properties.get("render_layer").and_then(|value| value.parse().ok()) {
+        layer.render_layer = render_layer;
+    }
+}
+
+// Layers with an "autotile" property ("4" or "blob") are retiled with the tileset named by
+// "autotile_tileset", laid out like AutotileRule::four_neighbour or AutotileRule::blob
+fn apply_autotile_properties(map: &mut Tilemap) -> Result<(), MapError> {
+    let mut rules = Vec::new();
+    for (index, layer) in map.layers().iter().enumerate() {
+        let Some(mode) = layer.properties.get("autotile") else {
+            continue;
+        };
+        let tileset_name = layer.properties.get("autotile_tileset").map(String::as_str).unwrap_or_default();
+        let tileset = map
+            .tileset(tileset_name)
+            .ok_or_else(|| MapError::Parse(format!("layer {} autotiles with unknown tileset {}", layer.name, tileset_name)))?;
+        let rule = match mode.as_str() {
+            "4" => AutotileRule::four_neighbour(&layer.name, tileset.first_gid),
+            "blob" => AutotileRule::blob(&layer.name, tileset.first_gid),
+            other => return Err(MapError::Parse(format!("unknown autotile mode {}", other))),
+        };
+        rules.push((index, rule));
+    }
+    for (index, rule) in rules {
+        map.add_autotile_rule(index, rule);
+    }
+    Ok(())
+}
+
+// Tiles with a "solid" property or collision shapes drawn in the tileset editor collide
+fn mark_solid_tile(tileset: &mut Tileset, local_id: u32, has_collision_shapes: bool) {
+    let solid_property = tileset
+        .tile_properties
+        .get(&local_id)
+        .and_then(|properties| properties.get("solid"))
+        .map(|value| value == "true");
+    if solid_property.unwrap_or(has_collision_shapes) {
+        tileset.solid_tiles.insert(local_id);
+    }
+}
+
+// TMX
+
+fn parse_tmx(text: &str, base: &Path) -> Result<Tilemap, MapError> {
+    let root = XmlElement::parse(text).map_err(MapError::Parse)?;
+    if root.name != "map" {
+        return Err(parse_error("root element isn't <map>"));
+    }
+    if root.attr("orientation").map(|value| value != "orthogonal").unwrap_or(false) {
+        return Err(MapError::Unsupported("only orthogonal maps are supported".to_string()));
+    }
+    if root.attr("infinite") == Some("1") {
+        return Err(MapError::Unsupported("infinite maps aren't supported".to_string()));
+    }
+
+    let mut map = new_map(
+        root.attr_u32("width")?,
+        root.attr_u32("height")?,
+        root.attr_u32("tilewidth")?,
+        root.attr_u32("tileheight")?,
+    )?;
+    map.properties = tmx_properties(&root);
+
+    for tileset in root.children_named("tileset") {
+        map.add_tileset(tmx_tileset(tileset, base)?);
+    }
+    tmx_layers(&root, &mut map, Vector2f::new(0.0, 0.0))?;
+    apply_autotile_properties(&mut map)?;
+    Ok(map)
+}
+
+fn tmx_properties(element: &XmlElement) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    for group in element.children_named("properties") {
+        for property in group.children_named("property") {
+            if let Some(name) = property.attr("name") {
+                // Multi line strings are stored as text
+                let value = property.attr("value").map(str::to_string).unwrap_or_else(|| property.text.clone());
+                properties.insert(name.to_string(), value);
+            }
+        }
+    }
+    properties
+}
+
+fn tmx_tileset(element: &XmlElement, base: &Path) -> Result<Tileset, MapError> {
+    let first_gid = element.attr_u32("firstgid")?;
+    match element.attr("source") {
+        // External .tsx, image paths inside are relative to it
+        Some(source) => {
+            let path = join_path(base, source);
+            let text = read_text(&path).map_err(MapError::File)?;
+            let root = XmlElement::parse(&text).map_err(MapError::Parse)?;
+            let tileset_base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            tmx_tileset_body(&root, first_gid, &tileset_base)
+        }
+        None => tmx_tileset_body(element, first_gid, base),
+    }
+}
+
+fn tmx_tileset_body(element: &XmlElement, first_gid: u32, base: &Path) -> Result<Tileset, MapError> {
+    let image = element
+        .children_named("image")
+        .next()
+        .ok_or_else(|| MapError::Unsupported("image collection tilesets aren't supported".to_string()))?;
+    let image_path = path_string(&join_path(base, image.attr("source").unwrap_or_default()));
+    let tile_width = element.attr_u32("tilewidth")?;
+    let tile_height = element.attr_u32("tileheight")?;
+    let spacing = element.attr_u32_or("spacing", 0);
+    let margin = element.attr_u32_or("margin", 0);
+    let image_width = image.attr_u32_or("width", 0);
+    let columns = element.attr_u32_or("columns", (image_width.saturating_sub(margin * 2) + spacing) / (tile_width + spacing).max(1));
+    let tile_count = element.attr_u32_or("tilecount", columns);
+
+    let mut tileset = Tileset::new(element.attr("name").unwrap_or_default(), &image_path, first_gid, tile_width, tile_height, columns, tile_count);
+    tileset.image_path = image_path;
+    tileset.spacing = spacing;
+    tileset.margin = margin;
+
+    for tile in element.children_named("tile") {
+        let local_id = tile.attr_u32("id")?;
+        let properties = tmx_properties(tile);
+        if !properties.is_empty() {
+            tileset.tile_properties.insert(local_id, properties);
+        }
+        let has_shapes = tile.children_named("objectgroup").any(|group| !group.children.is_empty());
+        mark_solid_tile(&mut tileset, local_id, has_shapes);
+    }
+    Ok(tileset)
+}
+
+// Layers in groups are flattened, group offsets add up
+fn tmx_layers(element: &XmlElement, map: &mut Tilemap, offset: Vector2f) -> Result<(), MapError> {
+    for child in element.children.iter() {
+        let child_offset = offset + Vector2f::new(child.attr_f32_or("offsetx", 0.0), child.attr_f32_or("offsety", 0.0));
+        match child.name.as_str() {
+            "layer" => {
+                let width = child.attr_u32("width")?;
+                let height = child.attr_u32("height")?;
+                let data = child.children_named("data").next().ok_or_else(|| parse_error("layer without <data>"))?;
+                let tiles = tmx_layer_data(data)?;
+                check_layer_size(map, width, height, tiles.len())?;
+                let mut layer = TileLayer::with_tiles(child.attr("name").unwrap_or_default(), width, height, tiles);
+                layer.visible = child.attr("visible") != Some("0");
+                layer.opacity = child.attr_f32_or("opacity", 1.0);
+                layer.offset = child_offset;
+                layer.properties = tmx_properties(child);
+                apply_layer_properties(&mut layer);
+                map.add_layer(layer);
+            }
+            "objectgroup" => {
+                let mut layer = ObjectLayer {
+                    name: child.attr("name").unwrap_or_default().to_string(),
+                    objects: Vec::new(),
+                    properties: tmx_properties(child),
+                };
+                for object in child.children_named("object") {
+                    let mut tile_object = TileObject::new(
+                        object.attr_u32_or("id", 0),
+                        object.attr("name").unwrap_or_default(),
+                        object.attr("type").or_else(|| object.attr("class")).unwrap_or_default(),
+                        object.attr_f32_or("x", 0.0) + child_offset.x,
+                        object.attr_f32_or("y", 0.0) + child_offset.y,
+                    );
+                    tile_object.width = object.attr_f32_or("width", 0.0);
+                    tile_object.height = object.attr_f32_or("height", 0.0);
+                    tile_object.rotation = object.attr_f32_or("rotation", 0.0);
+                    tile_object.gid = object.attr("gid").and_then(|gid| gid.parse().ok());
+                    tile_object.visible = object.attr("visible") != Some("0");
+                    tile_object.properties = tmx_properties(object);
+                    layer.objects.push(tile_object);
+                }
+                map.object_layers.push(layer);
+            }
+            "group" => tmx_layers(child, map, child_offset)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn tmx_layer_data(data: &XmlElement) -> Result<Vec<u32>, MapError> {
+    if data.attr("compression").is_some() {
+        return Err(MapError::Unsupported("compressed layer data isn't supported, save with CSV or uncompressed Base64".to_string()));
+    }
+    match data.attr("encoding") {
+        Some("csv") => data
+            .text
+            .split(',')
+            .map(|gid| gid.trim().parse::<u32>().map_err(|_| parse_error("invalid CSV gid")))
+            .collect(),
+        Some("base64") => gids_from_base64(&data.text),
+        None => data
+            .children_named("tile")
+            .map(|tile| Ok(tile.attr("gid").and_then(|gid| gid.parse().ok()).unwrap_or(0)))
+            .collect(),
+        Some(other) => Err(MapError::Unsupported(format!("unknown layer encoding {}", other))),
+    }
+}
+
+fn gids_from_base64(text: &str) -> Result<Vec<u32>, MapError> {
+    let bytes = decode_base64(text).ok_or_else(|| parse_error("invalid Base64 layer data"))?;
+    if bytes.len() % 4 != 0 {
+        return Err(parse_error("Base64 layer data isn't a list of 32 bit gids"));
+    }
+    Ok(bytes.chunks(4).map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]])).collect())
+}
+
+// JSON
+
+fn parse_tiled_json(text: &str, base: &Path) -> Result<Tilemap, MapError> {
+    let root = JsonValue::parse(text).map_err(MapError::Parse)?;
+    if root.get("orientation").and_then(JsonValue::as_str).map(|value| value != "orthogonal").unwrap_or(false) {
+        return Err(MapError::Unsupported("only orthogonal maps are supported".to_string()));
+    }
+    if root.get("infinite").and_then(JsonValue::as_bool).unwrap_or(false) {
+        return Err(MapError::Unsupported("infinite maps aren't supported".to_string()));
+    }
+
+    let mut map = new_map(root.u32_field("width")?, root.u32_field("height")?, root.u32_field("tilewidth")?, root.u32_field("tileheight")?)?;
+    map.properties = json_properties(&root);
+
+    for tileset in root.array_field("tilesets") {
+        map.add_tileset(json_tileset(tileset, base)?);
+    }
+    json_layers(root.array_field("layers"), &mut map, Vector2f::new(0.0, 0.0))?;
+    apply_autotile_properties(&mut map)?;
+    Ok(map)
+}
+
+fn json_properties(value: &JsonValue) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    for property in value.array_field("properties") {
+        if let Some(name) = property.get("name").and_then(JsonValue::as_str) {
+            let value = match property.get("value") {
+                Some(JsonValue::String(text)) => text.clone(),
+                Some(JsonValue::Number(number)) => number.to_string(),
+                Some(JsonValue::Bool(flag)) => flag.to_string(),
+                _ => String::new(),
+            };
+            properties.insert(name.to_string(), value);
+        }
+    }
+    properties
+}
+
+fn json_tileset(value: &JsonValue, base: &Path) -> Result<Tileset, MapError> {
+    let first_gid = value.u32_field("firstgid")?;
+    match value.get("source").and_then(JsonValue::as_str) {
+        Some(source) => {
+            let path = join_path(base, source);
+            let text = read_text(&path).map_err(MapError::File)?;
+            let tileset_base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            // Tilesets referenced from JSON maps can still be .tsx files
+            if source.ends_with(".tsx") {
+                let root = XmlElement::parse(&text).map_err(MapError::Parse)?;
+                tmx_tileset_body(&root, first_gid, &tileset_base)
+            } else {
+                let root = JsonValue::parse(&text).map_err(MapError::Parse)?;
+                json_tileset_body(&root, first_gid, &tileset_base)
+            }
+        }
+        None => json_tileset_body(value, first_gid, base),
+    }
+}
+
+fn json_tileset_body(value: &JsonValue, first_gid: u32, base: &Path) -> Result<Tileset, MapError> {
+    let image = value
+        .get("image")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| MapError::Unsupported("image collection tilesets aren't supported".to_string()))?;
+    let image_path = path_string(&join_path(base, image));
+    let name = value.get("name").and_then(JsonValue::as_str).unwrap_or_default();
+
+    let mut tileset = Tileset::new(
+        name,
+        &image_path,
+        first_gid,
+        value.u32_field("tilewidth")?,
+        value.u32_field("tileheight")?,
+        value.u32_field("columns")?,
+        value.u32_field("tilecount")?,
+    );
+    tileset.image_path = image_path;
+    tileset.spacing = value.u32_field_or("spacing", 0);
+    tileset.margin = value.u32_field_or("margin", 0);
+
+    for tile in value.array_field("tiles") {
+        let local_id = tile.u32_field("id")?;
+        let properties = json_properties(tile);
+        if !properties.is_empty() {
+            tileset.tile_properties.insert(local_id, properties);
+        }
+        let has_shapes = tile.get("objectgroup").map(|group| !group.array_field("objects").is_empty()).unwrap_or(false);
+        mark_solid_tile(&mut tileset, local_id, has_shapes);
+    }
+    Ok(tileset)
+}
+
+fn json_layers(layers: &[JsonValue], map: &mut Tilemap, offset: Vector2f) -> Result<(), MapError> {
+    for value in layers {
+        let layer_offset = offset + Vector2f::new(value.f32_field_or("offsetx", 0.0), value.f32_field_or("offsety", 0.0));
+        let name = value.get("name").and_then(JsonValue::as_str).unwrap_or_default();
+        match value.get("type").and_then(JsonValue::as_str) {
+            Some("tilelayer") => {
+                if value.get("compression").and_then(JsonValue::as_str).map(|value| !value.is_empty()).unwrap_or(false) {
+                    return Err(MapError::Unsupported("compressed layer data isn't supported".to_string()));
+                }
+                let width = value.u32_field("width")?;
+                let height = value.u32_field("height")?;
+                let tiles = match value.get("data") {
+                    Some(JsonValue::Array(gids)) => gids.iter().map(|gid| gid.as_f64().unwrap_or(0.0) as u32).collect(),
+                    Some(JsonValue::String(text)) => gids_from_base64(text)?,
+                    _ => return Err(parse_error("tile layer without data")),
+                };
+                check_layer_size(map, width, height, tiles.len())?;
+                let mut layer = TileLayer::with_tiles(name, width, height, tiles);
+                layer.visible = value.get("visible").and_then(JsonValue::as_bool).unwrap_or(true);
+                layer.opacity = value.f32_field_or("opacity", 1.0);
+                layer.offset = layer_offset;
+                layer.properties = json_properties(value);
+                apply_layer_properties(&mut layer);
+                map.add_layer(layer);
+            }
+            Some("objectgroup") => {
+                let mut layer = ObjectLayer {
+                    name: name.to_string(),
+                    objects: Vec::new(),
+                    properties: json_properties(value),
+                };
+                for object in value.array_field("objects") {
+                    let kind = object
+                        .get("type")
+                        .and_then(JsonValue::as_str)
+                        .filter(|kind| !kind.is_empty())
+                        .or_else(|| object.get("class").and_then(JsonValue::as_str))
+                        .unwrap_or_default();
+                    let mut tile_object = TileObject::new(
+                        object.u32_field_or("id", 0),
+                        object.get("name").and_then(JsonValue::as_str).unwrap_or_default(),
+                        kind,
+                        object.f32_field_or("x", 0.0) + layer_offset.x,
+                        object.f32_field_or("y", 0.0) + layer_offset.y,
+                    );
+                    tile_object.width = object.f32_field_or("width", 0.0);
+                    tile_object.height = object.f32_field_or("height", 0.0);
+                    tile_object.rotation = object.f32_field_or("rotation", 0.0);
+                    tile_object.gid = object.get("gid").and_then(JsonValue::as_f64).map(|gid| gid as u32);
+                    tile_object.visible = object.get("visible").and_then(JsonValue::as_bool).unwrap_or(true);
+                    tile_object.properties = json_properties(object);
+                    layer.objects.push(tile_object);
+                }
+                map.object_layers.push(layer);
+            }
+            Some("group") => json_layers(value.array_field("layers"), map, layer_offset)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Small XML reader, enough for Tiled files: elements, attributes, text, comments
+
+#[derive(Debug, Clone, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn parse(text: &str) -> Result<XmlElement, String> {
+        let mut reader = XmlReader { text, position: 0 };
+        reader.skip_prolog()?;
+        reader.element()
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn attr_u32(&self, name: &str) -> Result<u32, MapError> {
+        self.attr(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| MapError::Parse(format!("<{}> is missing a valid {}", self.name, name)))
+    }
+
+    fn attr_u32_or(&self, name: &str, default: u32) -> u32 {
+        self.attr(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+    }
+
+    fn attr_f32_or(&self, name: &str, default: f32) -> f32 {
+        self.attr(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+struct XmlReader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), String> {
+        match self.rest().find(end) {
+            Some(index) => {
+                self.position += index + end.len();
+                Ok(())
+            }
+            None => Err(format!("unclosed {}", end)),
+        }
+    }
+
+    // Declaration, comments and doctype before the root element
+    fn skip_prolog(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        self.position += end;
+        rest[..end].to_string()
+    }
+
+    fn element(&mut self) -> Result<XmlElement, String> {
+        if !self.rest().starts_with('<') {
+            return Err("expected an element".to_string());
+        }
+        self.position += 1;
+        let mut element = XmlElement {
+            name: self.name(),
+            ..Default::default()
+        };
+
+        // Attributes
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.position += 2;
+                return Ok(element);
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                break;
+            }
+            if rest.is_empty() {
+                return Err(format!("unclosed <{}>", element.name));
+            }
+            let key = self.name();
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(format!("attribute {} without a value", key));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            let quote = self.rest().chars().next().filter(|c| *c == '"' || *c == '\'').ok_or("unquoted attribute value")?;
+            self.position += 1;
+            let end = self.rest().find(quote).ok_or("unclosed attribute value")?;
+            let value = unescape_xml(&self.rest()[..end]);
+            self.position += end + 1;
+            element.attributes.push((key, value));
+        }
+
+        // Content
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.position += 2;
+                let name = self.name();
+                if name != element.name {
+                    return Err(format!("<{}> closed by </{}>", element.name, name));
+                }
+                self.skip_past(">")?;
+                element.text = element.text.trim().to_string();
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = cdata.find("]]>").ok_or("unclosed CDATA")?;
+                element.text.push_str(&cdata[..end]);
+                self.position += "<![CDATA[".len() + end + 3;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element()?);
+            } else if rest.is_empty() {
+                return Err(format!("unclosed <{}>", element.name));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&unescape_xml(&rest[..end]));
+                self.position += end;
+            }
+        }
+    }
+}
+
+fn unescape_xml(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#10;", "\n")
+        .replace("&amp;", "&")
+}
+
+// Small JSON reader
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    fn parse(text: &str) -> Result<JsonValue, String> {
+        let mut reader = JsonReader { chars: text.chars().collect(), position: 0 };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.position != reader.chars.len() {
+            return Err("trailing characters after the JSON value".to_string());
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(flag) => Some(*flag),
+            _ => None,
+        }
+    }
+
+    // Empty when missing
+    fn array_field(&self, key: &str) -> &[JsonValue] {
+        match self.get(key) {
+            Some(JsonValue::Array(values)) => values,
+            _ => &[],
+        }
+    }
+
+    fn u32_field(&self, key: &str) -> Result<u32, MapError> {
+        self.get(key)
+            .and_then(JsonValue::as_f64)
+            .filter(|number| *number >= 0.0)
+            .map(|number| number as u32)
+            .ok_or_else(|| MapError::Parse(format!("missing a valid {}", key)))
+    }
+
+    fn u32_field_or(&self, key: &str, default: u32) -> u32 {
+        self.get(key).and_then(JsonValue::as_f64).map(|number| number as u32).unwrap_or(default)
+    }
+
+    fn f32_field_or(&self, key: &str, default: f32) -> f32 {
+        self.get(key).and_then(JsonValue::as_f64).map(|number| number as f32).unwrap_or(default)
+    }
+}
+
+struct JsonReader {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl JsonReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map(char::is_whitespace).unwrap_or(false) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(format!("expected '{}' at character {}", expected, self.position));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        let end = self.position + word.len();
+        if end <= self.chars.len() && self.chars[self.position..end].iter().copied().eq(word.chars()) {
+            self.position = end;
+            Ok(value)
+        } else {
+            Err(format!("invalid value at character {}", self.position))
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        Some('}') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Object(fields));
+                        }
+                        _ => return Err(format!("expected ',' or '}}' at character {}", self.position)),
+                    }
+                }
+            }
+            Some('[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        Some(']') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Array(values));
+                        }
+                        _ => return Err(format!("expected ',' or ']' at character {}", self.position)),
+                    }
+                }
+            }
+            Some('"') => Ok(JsonValue::String(self.string()?)),
+            Some('t') => self.keyword("true", JsonValue::Bool(true)),
+            Some('f') => self.keyword("false", JsonValue::Bool(false)),
+            Some('n') => self.keyword("null", JsonValue::Null),
+            Some(_) => {
+                let start = self.position;
+                while self.peek().map(|c| c.is_ascii_digit() || "+-.eE".contains(c)).unwrap_or(false) {
+                    self.position += 1;
+                }
+                let number: String = self.chars[start..self.position].iter().collect();
+                number
+                    .parse()
+                    .map(JsonValue::Number)
+                    .map_err(|_| format!("invalid number at character {}", start))
+            }
+            None => Err("unexpected end of JSON".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some('"') {
+            return Err(format!("expected a string at character {}", self.position));
+        }
+        self.position += 1;
+        let mut text = String::new();
+        loop {
+            let c = self.peek().ok_or("unclosed string")?;
+            self.position += 1;
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = self.peek().ok_or("unclosed string")?;
+                    self.position += 1;
+                    match escaped {
+                        'n' => text.push('\n'),
+                        't' => text.push('\t'),
+                        'r' => text.push('\r'),
+                        'b' => text.push('\u{8}'),
+                        'f' => text.push('\u{c}'),
+                        'u' => {
+                            let end = self.position + 4;
+                            let hex: String = self.chars.get(self.position..end).ok_or("invalid unicode escape")?.iter().collect();
+                            let mut code = u32::from_str_radix(&hex, 16).map_err(|_| "invalid unicode escape")?;
+                            self.position = end;
+                            // Characters outside the BMP are written as a \uD800-\uDBFF \uDC00-\uDFFF surrogate pair
+                            if (0xd800..0xdc00).contains(&code) && self.chars.get(self.position..self.position + 2) == Some(&['\\', 'u']) {
+                                let low: String = self.chars.get(self.position + 2..self.position + 6).unwrap_or_default().iter().collect();
+                                if let Ok(low @ 0xdc00..=0xdfff) = u32::from_str_radix(&low, 16) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                    self.position += 6;
+                                }
+                            }
+                            text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        other => text.push(other),
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => break,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    const GIDS: [u32; 6] = [1, 2, 0, 3, 0x8000_0002, 4];
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let value = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(ALPHABET[(value >> (18 - i * 6) & 63) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    fn gids_base64(gids: &[u32]) -> String {
+        encode_base64(&gids.iter().flat_map(|gid| gid.to_le_bytes()).collect::<Vec<u8>>())
+    }
+
+    fn tmx_map(body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="ground.png" width="32" height="32"/>
+ </tileset>
+{}
+</map>"#,
+            body
+        )
+    }
+
+    #[test]
+    fn tmx_layer_encodings_read_the_same_gids() {
+        let csv = GIDS.iter().map(u32::to_string).collect::<Vec<_>>().join(",\n");
+        let tiles: String = GIDS.iter().map(|gid| format!("<tile gid=\"{}\"/>", gid)).collect();
+        let text = tmx_map(&format!(
+            r#"<layer name="csv" width="3" height="2"><data encoding="csv">{}</data></layer>
+<layer name="base64" width="3" height="2"><data encoding="base64">
+   {}
+  </data></layer>
+<layer name="xml" width="3" height="2"><data>{}</data></layer>"#,
+            csv,
+            gids_base64(&GIDS),
+            tiles
+        ));
+        let map = parse_tmx(&text, Path::new("")).unwrap();
+        assert_eq!(map.layers().len(), 3);
+        for layer in map.layers() {
+            assert_eq!(layer.tiles(), &GIDS[..], "layer {}", layer.name);
+        }
+        assert_eq!(map.tileset("ground").unwrap().image_path, "ground.png");
+    }
+
+    #[test]
+    fn tmx_external_tileset_is_read_relative_to_the_map() {
+        let dir = env::temp_dir().join(format!("motor_game_tiled_{}", std::process::id()));
+        fs::create_dir_all(dir.join("tilesets")).unwrap();
+        fs::write(
+            dir.join("tilesets/terrain.tsx"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="terrain" tilewidth="16" tileheight="16" spacing="1" margin="2" tilecount="6" columns="3">
+ <image source="../images/terrain.png" width="55" height="38"/>
+ <tile id="4"><properties><property name="solid" type="bool" value="true"/></properties></tile>
+</tileset>"#,
+        )
+        .unwrap();
+        let map_path = dir.join("level.tmx");
+        fs::write(
+            &map_path,
+            r#"<map orientation="orthogonal" width="1" height="1" tilewidth="16" tileheight="16">
+ <tileset firstgid="10" source="tilesets/terrain.tsx"/>
+ <layer name="ground" width="1" height="1"><data encoding="csv">14</data></layer>
+</map>"#,
+        )
+        .unwrap();
+
+        let map = load_tiled_map(&map_path);
+        fs::remove_dir_all(&dir).unwrap();
+        let map = map.unwrap();
+        let tileset = map.tileset("terrain").unwrap();
+        assert_eq!(tileset.first_gid, 10);
+        assert_eq!((tileset.columns, tileset.tile_count, tileset.spacing, tileset.margin), (3, 6, 1, 2));
+        assert_eq!(tileset.image_path, path_string(&dir.join("images/terrain.png")));
+        assert!(tileset.tile_properties.get(&4).is_some_and(|properties| properties["solid"] == "true"));
+        assert_eq!(map.tileset_for(14).map(|tileset| tileset.name.as_str()), Some("terrain"));
+    }
+
+    #[test]
+    fn tmx_missing_external_tileset_names_the_tileset_file() {
+        let text = tmx_map(r#"<tileset firstgid="5" source="missing.tsx"/>"#);
+        match parse_tmx(&text, Path::new("")) {
+            Err(MapError::File(TiledError::Io { path, .. })) => assert_eq!(path, "missing.tsx"),
+            other => panic!("expected an Io error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn tmx_nested_groups_add_their_offsets() {
+        let text = tmx_map(
+            r#"<group name="outer" offsetx="10" offsety="4">
+ <group name="inner" offsetx="5">
+  <layer name="deep" width="3" height="2" offsetx="1" offsety="1"><data encoding="csv">1,1,1,1,1,1</data></layer>
+  <objectgroup name="markers"><object id="1" name="spawn" x="2" y="3"/></objectgroup>
+ </group>
+ <layer name="shallow" width="3" height="2"><data encoding="csv">0,0,0,0,0,0</data></layer>
+</group>"#,
+        );
+        let map = parse_tmx(&text, Path::new("")).unwrap();
+        assert_eq!(map.layer("deep").unwrap().offset, Vector2f::new(16.0, 5.0));
+        assert_eq!(map.layer("shallow").unwrap().offset, Vector2f::new(10.0, 4.0));
+        let spawn = map.objects().find(|object| object.name == "spawn").unwrap();
+        assert_eq!((spawn.x, spawn.y), (17.0, 7.0));
+    }
+
+    #[test]
+    fn tmx_object_layers_keep_objects_and_properties() {
+        let text = tmx_map(
+            r#"<objectgroup name="things">
+ <properties><property name="layer_kind" value="logic"/></properties>
+ <object id="3" name="door" type="trigger" x="32" y="16" width="16" height="32" rotation="90">
+  <properties><property name="target" value="level2"/><property name="note">two
+lines</property></properties>
+ </object>
+ <object id="4" class="pickup" x="1" y="2" gid="2147483650" visible="0"/>
+</objectgroup>"#,
+        );
+        let map = parse_tmx(&text, Path::new("")).unwrap();
+        let layer = map.object_layer("things").unwrap();
+        assert_eq!(layer.properties["layer_kind"], "logic");
+        let door = &layer.objects[0];
+        assert_eq!((door.id, door.kind.as_str()), (3, "trigger"));
+        assert_eq!((door.width, door.height, door.rotation), (16.0, 32.0, 90.0));
+        assert_eq!(door.property("target"), Some("level2"));
+        assert_eq!(door.property("note"), Some("two\nlines"));
+        let pickup = &layer.objects[1];
+        assert_eq!(pickup.kind, "pickup");
+        assert_eq!(pickup.gid, Some(0x8000_0002));
+        assert!(!pickup.visible);
+    }
+
+    #[test]
+    fn tmx_rejects_layers_that_dont_fit_the_map() {
+        let smaller = tmx_map(r#"<layer name="small" width="2" height="2"><data encoding="csv">1,1,1,1</data></layer>"#);
+        assert!(matches!(parse_tmx(&smaller, Path::new("")), Err(MapError::Parse(_))));
+        let short = tmx_map(r#"<layer name="short" width="3" height="2"><data encoding="csv">1,1,1</data></layer>"#);
+        assert!(matches!(parse_tmx(&short, Path::new("")), Err(MapError::Parse(_))));
+        let overflow = tmx_map(r#"<layer name="huge" width="65536" height="65536"><data encoding="csv">1</data></layer>"#);
+        assert!(matches!(parse_tmx(&overflow, Path::new("")), Err(MapError::Parse(_))));
+        let huge_map = r#"<map width="65536" height="65536" tilewidth="16" tileheight="16"/>"#;
+        assert!(matches!(parse_tmx(huge_map, Path::new("")), Err(MapError::Parse(_))));
+    }
+
+    #[test]
+    fn json_maps_read_arrays_base64_groups_and_objects() {
+        let text = format!(
+            r#"{{
+ "orientation": "orthogonal", "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16, "infinite": false,
+ "properties": [{{"name": "title", "type": "string", "value": "Café 😀 \ud83d!"}}],
+ "tilesets": [{{"firstgid": 1, "name": "ground", "image": "ground.png", "tilewidth": 16, "tileheight": 16, "columns": 2, "tilecount": 4}}],
+ "layers": [
+  {{"type": "tilelayer", "name": "array", "width": 3, "height": 2, "data": [{}]}},
+  {{"type": "group", "name": "group", "offsetx": 8, "layers": [
+   {{"type": "tilelayer", "name": "base64", "width": 3, "height": 2, "offsety": 2, "encoding": "base64", "data": "{}"}},
+   {{"type": "objectgroup", "name": "things", "objects": [
+    {{"id": 7, "name": "chest", "type": "", "class": "loot", "x": 4, "y": 5, "properties": [{{"name": "gold", "type": "int", "value": 12}}]}}
+   ]}}
+  ]}}
+ ]
+}}"#,
+            GIDS.iter().map(u32::to_string).collect::<Vec<_>>().join(","),
+            gids_base64(&GIDS)
+        );
+        let map = parse_tiled_json(&text, Path::new("")).unwrap();
+        assert_eq!(map.properties["title"], "Caf\u{e9} \u{1f600} \u{fffd}!");
+        assert_eq!(map.layer("array").unwrap().tiles(), &GIDS[..]);
+        let base64 = map.layer("base64").unwrap();
+        assert_eq!(base64.tiles(), &GIDS[..]);
+        assert_eq!(base64.offset, Vector2f::new(8.0, 2.0));
+        let chest = map.objects().find(|object| object.name == "chest").unwrap();
+        assert_eq!((chest.id, chest.kind.as_str(), chest.x, chest.y), (7, "loot", 12.0, 5.0));
+        assert_eq!(chest.property("gold"), Some("12"));
+    }
+
+    #[test]
+    fn json_rejects_layers_that_dont_fit_the_map() {
+        let map = |layer: &str| {
+            format!(
+                r#"{{"width": 3, "height": 2, "tilewidth": 16, "tileheight": 16, "tilesets": [], "layers": [{}]}}"#,
+                layer
+            )
+        };
+        let smaller = map(r#"{"type": "tilelayer", "width": 2, "height": 2, "data": [1, 1, 1, 1]}"#);
+        assert!(matches!(parse_tiled_json(&smaller, Path::new("")), Err(MapError::Parse(_))));
+        let overflow = map(r#"{"type": "tilelayer", "width": 65536, "height": 65536, "data": [1]}"#);
+        assert!(matches!(parse_tiled_json(&overflow, Path::new("")), Err(MapError::Parse(_))));
+    }
+}
@@ -0,0 +1,691 @@
+use std::collections::{HashMap, HashSet};
+
+use sfml::{
+    graphics::{BlendMode, Color, FloatRect, IntRect, PrimitiveType, RcSprite, RcTexture, RenderStates, RenderTarget, Transform, Vertex},
+    system::Vector2f,
+};
+
+use super::prelude::*;
+
+// Tiled keeps flips in the top bits of a gid
+pub const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+pub const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+pub const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+pub const GID_MASK: u32 = 0x1FFF_FFFF;
+
+#[derive(Clone)]
+pub struct Tileset {
+    pub name: String,
+    // Id in the scene textures, the image path is used when it isn't loaded yet
+    pub texture_id: String,
+    pub image_path: String,
+    // Gid of the first tile, 0 means an empty cell so it starts at 1
+    pub first_gid: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub spacing: u32,
+    pub margin: u32,
+    // Local tile ids that collide wherever they are placed
+    pub solid_tiles: HashSet<u32>,
+    pub tile_properties: HashMap<u32, HashMap<String, String>>,
+    // Only holds the texture, RenderStates can't take an RcTexture directly
+    sprite: Option<RcSprite>,
+}
+
+impl Tileset {
+    pub fn new(name: &str, texture_id: &str, first_gid: u32, tile_width: u32, tile_height: u32, columns: u32, tile_count: u32) -> Self {
+        Tileset {
+            name: name.to_string(),
+            texture_id: texture_id.to_string(),
+            image_path: String::new(),
+            first_gid,
+            tile_width,
+            tile_height,
+            columns: columns.max(1),
+            tile_count,
+            spacing: 0,
+            margin: 0,
+            solid_tiles: HashSet::new(),
+            tile_properties: HashMap::new(),
+            sprite: None,
+        }
+    }
+
+    pub fn contains(&self, gid: u32) -> bool {
+        let gid = gid & GID_MASK;
+        gid >= self.first_gid && gid < self.first_gid + self.tile_count
+    }
+
+    pub fn tile_rect(&self, local_id: u32) -> IntRect {
+        let column = local_id % self.columns;
+        let row = local_id / self.columns;
+        IntRect::new(
+            (self.margin + column * (self.tile_width + self.spacing)) as i32,
+            (self.margin + row * (self.tile_height + self.spacing)) as i32,
+            self.tile_width as i32,
+            self.tile_height as i32,
+        )
+    }
+
+    pub fn set_texture(&mut self, texture: &RcTexture) {
+        self.sprite = Some(RcSprite::with_texture(texture));
+    }
+
+    pub fn has_texture(&self) -> bool {
+        self.sprite.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    // Row major gids, 0 is empty
+    tiles: Vec<u32>,
+    pub visible: bool,
+    pub opacity: f32,
+    // Entity layer it is drawn with, tiles go before entities of the same layer
+    pub render_layer: i32,
+    // Every tile of a solid layer collides
+    pub solid: bool,
+    pub offset: Vector2f,
+    pub properties: HashMap<String, String>,
+}
+
+impl TileLayer {
+    // Drawn under entities (layer 0) by default
+    pub fn new(name: &str, width: u32, height: u32) -> Self {
+        TileLayer {
+            name: name.to_string(),
+            width,
+            height,
+            tiles: vec![0; width as usize * height as usize],
+            visible: true,
+            opacity: 1.0,
+            render_layer: -1,
+            solid: false,
+            offset: Vector2f::new(0.0, 0.0),
+            properties: HashMap::new(),
+        }
+    }
+
+    pub fn with_tiles(name: &str, width: u32, height: u32, tiles: Vec<u32>) -> Self {
+        let mut layer = TileLayer::new(name, width, height);
+        for (index, gid) in tiles.into_iter().take(layer.tiles.len()).enumerate() {
+            layer.tiles[index] = gid;
+        }
+        layer
+    }
+
+    // 0 outside the layer
+    pub fn tile(&self, x: i32, y: i32) -> u32 {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return 0;
+        }
+        self.tiles[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    pub fn tiles(&self) -> &[u32] {
+        &self.tiles
+    }
+}
+
+// Objects keep Tiled coordinates, tile objects (gid set) are anchored at their bottom left
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileObject {
+    pub id: u32,
+    pub name: String,
+    // "type" in older Tiled versions, "class" in newer ones
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub rotation: f32,
+    pub gid: Option<u32>,
+    pub visible: bool,
+    pub properties: HashMap<String, String>,
+}
+
+impl TileObject {
+    pub fn new(id: u32, name: &str, kind: &str, x: f32, y: f32) -> Self {
+        TileObject {
+            id,
+            name: name.to_string(),
+            kind: kind.to_string(),
+            x,
+            y,
+            width: 0.0,
+            height: 0.0,
+            rotation: 0.0,
+            gid: None,
+            visible: true,
+            properties: HashMap::new(),
+        }
+    }
+
+    // Top left corner, tile objects are moved up by their height
+    pub fn top_left(&self) -> Vector2f {
+        match self.gid {
+            Some(_) => Vector2f::new(self.x, self.y - self.height),
+            None => Vector2f::new(self.x, self.y),
+        }
+    }
+
+    pub fn bounds(&self) -> FloatRect {
+        let top_left = self.top_left();
+        FloatRect::new(top_left.x, top_left.y, self.width, self.height)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub objects: Vec<TileObject>,
+    pub properties: HashMap<String, String>,
+}
+
+// Vertices of a square block of tiles, one mesh per tileset used in it
+#[derive(Clone)]
+struct TileChunk {
+    bounds: FloatRect,
+    meshes: Vec<(usize, Vec<Vertex>)>,
+    dirty: bool,
+}
+
+#[derive(Clone)]
+pub struct Tilemap {
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // Map origin in world coordinates, changing it needs rebuild_all
+    pub position: Vector2f,
+    pub tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    pub object_layers: Vec<ObjectLayer>,
+    pub properties: HashMap<String, String>,
+    // Tiles per chunk side, only chunks inside the camera are drawn
+    chunk_size: u32,
+    chunks: Vec<Vec<TileChunk>>,
+    collision_rects: Vec<FloatRect>,
+    collision_dirty: bool,
+    // Layer index and rule, applied when added and around tiles set with set_tile_autotiled
+    autotile_rules: Vec<(usize, AutotileRule)>,
+}
+
+impl Tilemap {
+    pub fn new(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Self {
+        Tilemap {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            position: Vector2f::new(0.0, 0.0),
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            object_layers: Vec::new(),
+            properties: HashMap::new(),
+            chunk_size: 16,
+            chunks: Vec::new(),
+            collision_rects: Vec::new(),
+            collision_dirty: true,
+            autotile_rules: Vec::new(),
+        }
+    }
+
+    pub fn set_chunk_size(&mut self, chunk_size: u32) {
+        self.chunk_size = chunk_size.max(1);
+        self.rebuild_all();
+    }
+
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    pub fn add_tileset(&mut self, tileset: Tileset) {
+        self.tilesets.push(tileset);
+        self.tilesets.sort_by_key(|tileset| tileset.first_gid);
+        self.rebuild_all();
+    }
+
+    // Tileset with the highest first gid not above the gid
+    pub fn tileset_index(&self, gid: u32) -> Option<usize> {
+        let gid = gid & GID_MASK;
+        if gid == 0 {
+            return None;
+        }
+        self.tilesets.iter().rposition(|tileset| tileset.first_gid <= gid)
+    }
+
+    pub fn tileset_for(&self, gid: u32) -> Option<&Tileset> {
+        self.tileset_index(gid).map(|index| &self.tilesets[index])
+    }
+
+    pub fn tileset(&self, name: &str) -> Option<&Tileset> {
+        self.tilesets.iter().find(|tileset| tileset.name == name)
+    }
+
+    // Layers cover the whole map, a layer of another size is skipped
+    pub fn add_layer(&mut self, layer: TileLayer) -> Option<usize> {
+        if layer.width != self.width || layer.height != self.height {
+            println!(
+                "Layer {} is {}x{} tiles but the map is {}x{}, skipping it",
+                layer.name, layer.width, layer.height, self.width, self.height
+            );
+            return None;
+        }
+        self.layers.push(layer);
+        self.chunks.push(self.empty_chunks());
+        self.collision_dirty = true;
+        Some(self.layers.len() - 1)
+    }
+
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn set_layer_visible(&mut self, layer: usize, visible: bool) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.visible = visible;
+        }
+    }
+
+    pub fn set_layer_solid(&mut self, layer: usize, solid: bool) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.solid = solid;
+            self.collision_dirty = true;
+        }
+    }
+
+    pub fn set_render_layer(&mut self, layer: usize, render_layer: i32) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.render_layer = render_layer;
+        }
+    }
+
+    pub fn object_layer(&self, name: &str) -> Option<&ObjectLayer> {
+        self.object_layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &TileObject> {
+        self.object_layers.iter().flat_map(|layer| layer.objects.iter())
+    }
+
+    pub fn tile(&self, layer: usize, x: i32, y: i32) -> u32 {
+        self.layers.get(layer).map(|layer| layer.tile(x, y)).unwrap_or(0)
+    }
+
+    // Only the chunk holding the tile is built again
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, gid: u32) {
+        let Some(tile_layer) = self.layers.get_mut(layer) else {
+            return;
+        };
+        if x >= tile_layer.width || y >= tile_layer.height {
+            return;
+        }
+        tile_layer.tiles[(y * tile_layer.width + x) as usize] = gid;
+
+        let chunks_x = self.chunks_x();
+        let chunk = (y / self.chunk_size * chunks_x + x / self.chunk_size) as usize;
+        if let Some(chunk) = self.chunks.get_mut(layer).and_then(|chunks| chunks.get_mut(chunk)) {
+            chunk.dirty = true;
+        }
+        self.collision_dirty = true;
+    }
+
+    pub fn fill(&mut self, layer: usize, area: IntRect, gid: u32) {
+        for y in area.top.max(0)..(area.top + area.height).max(0) {
+            for x in area.left.max(0)..(area.left + area.width).max(0) {
+                self.set_tile(layer, x as u32, y as u32, gid);
+            }
+        }
+    }
+
+    // Retiles the whole layer with the rule right away, for maps painted with plain terrain tiles
+    pub fn add_autotile_rule(&mut self, layer: usize, rule: AutotileRule) {
+        self.autotile_rules.push((layer, rule));
+        self.autotile_layer(layer);
+    }
+
+    pub fn autotile_rule(&self, name: &str) -> Option<&AutotileRule> {
+        self.autotile_rules.iter().find(|(_, rule)| rule.name == name).map(|(_, rule)| rule)
+    }
+
+    pub fn remove_autotile_rule(&mut self, name: &str) -> Option<AutotileRule> {
+        let index = self.autotile_rules.iter().position(|(_, rule)| rule.name == name)?;
+        Some(self.autotile_rules.remove(index).1)
+    }
+
+    pub fn autotile_layer(&mut self, layer: usize) {
+        let Some(tile_layer) = self.layers.get(layer) else {
+            return;
+        };
+        let (width, height) = (tile_layer.width, tile_layer.height);
+        self.autotile_area(layer, 0, 0, width as i32 - 1, height as i32 - 1);
+    }
+
+    // Sets the tile and retiles it and its 8 neighbours
+    pub fn set_tile_autotiled(&mut self, layer: usize, x: u32, y: u32, gid: u32) {
+        self.set_tile(layer, x, y, gid);
+        self.autotile_area(layer, x as i32 - 1, y as i32 - 1, x as i32 + 1, y as i32 + 1);
+    }
+
+    // Places the terrain of a rule, false if there is no such rule on the layer
+    pub fn paint_terrain(&mut self, layer: usize, x: u32, y: u32, rule_name: &str) -> bool {
+        let gid = self
+            .autotile_rules
+            .iter()
+            .find(|(rule_layer, rule)| *rule_layer == layer && rule.name == rule_name)
+            .and_then(|(_, rule)| rule.paint_gid());
+        match gid {
+            Some(gid) => {
+                self.set_tile_autotiled(layer, x, y, gid);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn erase_terrain(&mut self, layer: usize, x: u32, y: u32) {
+        self.set_tile_autotiled(layer, x, y, 0);
+    }
+
+    // Results are computed from the layer before any change, so the order of cells doesn't matter
+    fn autotile_area(&mut self, layer: usize, left: i32, top: i32, right: i32, bottom: i32) {
+        let Some(tile_layer) = self.layers.get(layer) else {
+            return;
+        };
+        let mut changes: Vec<(u32, u32, u32)> = Vec::new();
+        for y in top.max(0)..=bottom.min(tile_layer.height as i32 - 1) {
+            for x in left.max(0)..=right.min(tile_layer.width as i32 - 1) {
+                let resolved = self
+                    .autotile_rules
+                    .iter()
+                    .filter(|(rule_layer, _)| *rule_layer == layer)
+                    .find_map(|(_, rule)| rule.resolve(tile_layer, x, y));
+                if let Some(gid) = resolved {
+                    if gid != tile_layer.tile(x, y) {
+                        changes.push((x as u32, y as u32, gid));
+                    }
+                }
+            }
+        }
+        for (x, y, gid) in changes {
+            self.set_tile(layer, x, y, gid);
+        }
+    }
+
+    pub fn world_to_tile(&self, point: Vector2f) -> Option<(u32, u32)> {
+        let x = ((point.x - self.position.x) / self.tile_width as f32).floor();
+        let y = ((point.y - self.position.y) / self.tile_height as f32).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some((x as u32, y as u32))
+    }
+
+    pub fn tile_to_world(&self, x: u32, y: u32) -> Vector2f {
+        self.position + Vector2f::new((x * self.tile_width) as f32, (y * self.tile_height) as f32)
+    }
+
+    pub fn world_size(&self) -> Vector2f {
+        Vector2f::new((self.width * self.tile_width) as f32, (self.height * self.tile_height) as f32)
+    }
+
+    pub fn is_solid(&self, layer: usize, x: i32, y: i32) -> bool {
+        let Some(tile_layer) = self.layers.get(layer) else {
+            return false;
+        };
+        let gid = tile_layer.tile(x, y) & GID_MASK;
+        if gid == 0 {
+            return false;
+        }
+        tile_layer.solid
+            || self
+                .tileset_for(gid)
+                .map(|tileset| tileset.solid_tiles.contains(&(gid - tileset.first_gid)))
+                .unwrap_or(false)
+    }
+
+    // Solid tiles of every layer merged into as few rects as possible
+    pub fn collision_rects(&self) -> &[FloatRect] {
+        &self.collision_rects
+    }
+
+    pub fn rebuild_all(&mut self) {
+        self.chunks = (0..self.layers.len()).map(|_| self.empty_chunks()).collect();
+        self.collision_dirty = true;
+    }
+
+    // Builds the chunks and collision rects that changed since the last call, the scene calls it before drawing
+    pub fn rebuild(&mut self) {
+        for layer in 0..self.layers.len() {
+            for chunk in 0..self.chunks[layer].len() {
+                if self.chunks[layer][chunk].dirty {
+                    let meshes = self.build_chunk(layer, chunk);
+                    self.chunks[layer][chunk].meshes = meshes;
+                    self.chunks[layer][chunk].dirty = false;
+                }
+            }
+        }
+        if self.collision_dirty {
+            self.collision_rects = self.build_collision_rects();
+            self.collision_dirty = false;
+        }
+    }
+
+    fn chunks_x(&self) -> u32 {
+        self.width.div_ceil(self.chunk_size)
+    }
+
+    fn empty_chunks(&self) -> Vec<TileChunk> {
+        let chunks_y = self.height.div_ceil(self.chunk_size);
+        let side = Vector2f::new((self.chunk_size * self.tile_width) as f32, (self.chunk_size * self.tile_height) as f32);
+        let mut chunks = Vec::new();
+        for cy in 0..chunks_y {
+            for cx in 0..self.chunks_x() {
+                // Taller tiles reach up into the chunk above, the bounds grow a tile to keep them visible
+                let top_left = self.position + Vector2f::new(cx as f32 * side.x, cy as f32 * side.y - self.tile_height as f32);
+                chunks.push(TileChunk {
+                    bounds: FloatRect::new(top_left.x, top_left.y, side.x, side.y + self.tile_height as f32),
+                    meshes: Vec::new(),
+                    dirty: true,
+                });
+            }
+        }
+        chunks
+    }
+
+    fn build_chunk(&self, layer: usize, chunk: usize) -> Vec<(usize, Vec<Vertex>)> {
+        let tile_layer = &self.layers[layer];
+        let chunks_x = self.chunks_x();
+        let start_x = chunk as u32 % chunks_x * self.chunk_size;
+        let start_y = chunk as u32 / chunks_x * self.chunk_size;
+        let alpha = (tile_layer.opacity.clamp(0.0, 1.0) * 255.0) as u8;
+        let color = Color::rgba(255, 255, 255, alpha);
+
+        let mut meshes: Vec<(usize, Vec<Vertex>)> = Vec::new();
+        for y in start_y..(start_y + self.chunk_size).min(tile_layer.height) {
+            for x in start_x..(start_x + self.chunk_size).min(tile_layer.width) {
+                let raw = tile_layer.tile(x as i32, y as i32);
+                let Some(tileset_index) = self.tileset_index(raw) else {
+                    continue;
+                };
+                let tileset = &self.tilesets[tileset_index];
+                let local = (raw & GID_MASK) - tileset.first_gid;
+                let rect = tileset.tile_rect(local);
+
+                // Tiles bigger than the map grid are aligned to the bottom of their cell
+                let left = self.position.x + tile_layer.offset.x + (x * self.tile_width) as f32;
+                let top = self.position.y + tile_layer.offset.y + ((y + 1) * self.tile_height) as f32 - tileset.tile_height as f32;
+                let right = left + tileset.tile_width as f32;
+                let bottom = top + tileset.tile_height as f32;
+
+                let mut coords = [
+                    Vector2f::new(rect.left as f32, rect.top as f32),
+                    Vector2f::new((rect.left + rect.width) as f32, rect.top as f32),
+                    Vector2f::new((rect.left + rect.width) as f32, (rect.top + rect.height) as f32),
+                    Vector2f::new(rect.left as f32, (rect.top + rect.height) as f32),
+                ];
+                if raw & FLIPPED_DIAGONALLY != 0 {
+                    coords.swap(1, 3);
+                }
+                if raw & FLIPPED_HORIZONTALLY != 0 {
+                    coords.swap(0, 1);
+                    coords.swap(2, 3);
+                }
+                if raw & FLIPPED_VERTICALLY != 0 {
+                    coords.swap(0, 3);
+                    coords.swap(1, 2);
+                }
+
+                let corners = [
+                    Vertex::new(Vector2f::new(left, top), color, coords[0]),
+                    Vertex::new(Vector2f::new(right, top), color, coords[1]),
+                    Vertex::new(Vector2f::new(right, bottom), color, coords[2]),
+                    Vertex::new(Vector2f::new(left, bottom), color, coords[3]),
+                ];
+                let quad = [corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]];
+                match meshes.iter_mut().find(|(index, _)| *index == tileset_index) {
+                    Some((_, vertices)) => vertices.extend_from_slice(&quad),
+                    None => meshes.push((tileset_index, quad.to_vec())),
+                }
+            }
+        }
+        meshes
+    }
+
+    // Runs of solid tiles per row, runs with the same columns in the next rows are merged downwards
+    fn build_collision_rects(&self) -> Vec<FloatRect> {
+        let mut rects: Vec<FloatRect> = Vec::new();
+        // (start column, end column) -> index of the rect that ended on the previous row
+        let mut open: HashMap<(u32, u32), usize> = HashMap::new();
+        let tile_width = self.tile_width as f32;
+        let tile_height = self.tile_height as f32;
+
+        for y in 0..self.height {
+            let mut runs: Vec<(u32, u32)> = Vec::new();
+            let mut x = 0;
+            while x < self.width {
+                if !self.is_solid_anywhere(x, y) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < self.width && self.is_solid_anywhere(x, y) {
+                    x += 1;
+                }
+                runs.push((start, x));
+            }
+
+            let mut next_open: HashMap<(u32, u32), usize> = HashMap::new();
+            for run in runs {
+                match open.get(&run) {
+                    Some(&index) => {
+                        rects[index].height += tile_height;
+                        next_open.insert(run, index);
+                    }
+                    None => {
+                        rects.push(FloatRect::new(
+                            self.position.x + run.0 as f32 * tile_width,
+                            self.position.y + y as f32 * tile_height,
+                            (run.1 - run.0) as f32 * tile_width,
+                            tile_height,
+                        ));
+                        next_open.insert(run, rects.len() - 1);
+                    }
+                }
+            }
+            open = next_open;
+        }
+        rects
+    }
+
+    fn is_solid_anywhere(&self, x: u32, y: u32) -> bool {
+        (0..self.layers.len()).any(|layer| self.is_solid(layer, x as i32, y as i32))
+    }
+
+    // Draws the chunks of one layer that touch the visible area
+    pub fn draw_layer(&self, target: &mut dyn RenderTarget, layer: usize, visible_area: FloatRect, stats: &mut RenderStats) {
+        let Some(tile_layer) = self.layers.get(layer) else {
+            return;
+        };
+        if !tile_layer.visible {
+            return;
+        }
+        for chunk in self.chunks[layer].iter() {
+            if chunk.bounds.intersection(&visible_area).is_none() {
+                continue;
+            }
+            for (tileset, vertices) in chunk.meshes.iter() {
+                let texture = self.tilesets[*tileset].sprite.as_ref().and_then(|sprite| sprite.texture());
+                if texture.is_none() || vertices.is_empty() {
+                    continue;
+                }
+                let states = RenderStates::new(BlendMode::ALPHA, Transform::IDENTITY, texture, None);
+                target.draw_primitives(vertices, PrimitiveType::TRIANGLES, &states);
+                stats.draw_calls += 1;
+                stats.batches += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_layer_skips_layers_of_another_size() {
+        let mut map = Tilemap::new(4, 3, 16, 16);
+        assert_eq!(map.add_layer(TileLayer::new("ground", 4, 3)), Some(0));
+        assert_eq!(map.add_layer(TileLayer::new("small", 3, 3)), None);
+        assert_eq!(map.add_layer(TileLayer::new("tall", 4, 4)), None);
+        assert_eq!(map.layers().len(), 1);
+        assert_eq!(map.add_layer(TileLayer::with_tiles("overlay", 4, 3, vec![1; 12])), Some(1));
+    }
+
+    #[test]
+    fn set_tile_autotiled_updates_the_neighbours() {
+        let mut map = Tilemap::new(3, 3, 16, 16);
+        let layer = map.add_layer(TileLayer::new("ground", 3, 3)).unwrap();
+        let mut rule = AutotileRule::blob("ground", 1);
+        rule.connect_to_edges = false;
+        let full = rule.paint_gid().unwrap();
+        map.add_autotile_rule(layer, rule.clone());
+        let gid_for = |mask: u8| rule.tile_for(mask).unwrap();
+
+        map.set_tile_autotiled(layer, 1, 1, full);
+        assert_eq!(map.tile(layer, 1, 1), gid_for(0));
+
+        map.set_tile_autotiled(layer, 2, 1, full);
+        assert_eq!(map.tile(layer, 1, 1), gid_for(EAST));
+        assert_eq!(map.tile(layer, 2, 1), gid_for(WEST));
+
+        map.set_tile_autotiled(layer, 1, 2, full | 0x4000_0000);
+        map.set_tile_autotiled(layer, 2, 2, full);
+        assert_eq!(map.tile(layer, 1, 1), gid_for(EAST | SOUTH | SOUTH_EAST));
+        assert_eq!(map.tile(layer, 1, 2), gid_for(NORTH | EAST | NORTH_EAST) | 0x4000_0000);
+
+        map.erase_terrain(layer, 2, 2);
+        assert_eq!(map.tile(layer, 2, 2), 0);
+        assert_eq!(map.tile(layer, 1, 1), gid_for(EAST | SOUTH));
+        assert_eq!(map.tile(layer, 2, 1), gid_for(WEST));
+    }
+}
@@ -0,0 +1,566 @@
+use std::{cell::RefCell, f32::consts::PI};
+
+use indexmap::IndexMap;
+use sfml::{
+    graphics::{Color, Drawable, Transformable},
+    system::Vector2f,
+};
+
+use super::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+}
+
+impl Easing {
+    // Maps linear progress (0..1) to eased progress, elastic and back may overshoot
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::ElasticIn => {
+                if t == 0.0 || t == 1.0 {
+                    return t;
+                }
+                let c4 = (2.0 * PI) / 3.0;
+                -(2.0f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * c4).sin()
+            }
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    return t;
+                }
+                let c4 = (2.0 * PI) / 3.0;
+                2.0f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0
+            }
+            Easing::ElasticInOut => {
+                if t == 0.0 || t == 1.0 {
+                    return t;
+                }
+                let c5 = (2.0 * PI) / 4.5;
+                if t < 0.5 {
+                    -(2.0f32.powf(20.0 * t - 10.0) * ((20.0 * t - 11.125) * c5).sin()) / 2.0
+                } else {
+                    (2.0f32.powf(-20.0 * t + 10.0) * ((20.0 * t - 11.125) * c5).sin()) / 2.0 + 1.0
+                }
+            }
+            Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+            Easing::BounceOut => bounce_out(t),
+            Easing::BounceInOut => {
+                if t < 0.5 {
+                    (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0
+                } else {
+                    (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0
+                }
+            }
+            Easing::BackIn => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                c3 * t * t * t - c1 * t * t
+            }
+            Easing::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+            Easing::BackInOut => {
+                let c2 = 1.70158 * 1.525;
+                if t < 0.5 {
+                    ((2.0 * t).powi(2) * ((c2 + 1.0) * 2.0 * t - c2)) / 2.0
+                } else {
+                    ((2.0 * t - 2.0).powi(2) * ((c2 + 1.0) * (t * 2.0 - 2.0) + c2) + 2.0) / 2.0
+                }
+            }
+        }
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    let n1 = 7.5625;
+    let d1 = 2.75;
+    if t < 1.0 / d1 {
+        n1 * t * t
+    } else if t < 2.0 / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+// Property of an entity to tween and the value to reach
+#[derive(Debug, Clone, Copy)]
+pub enum TweenTarget {
+    Position(Vector2f),
+    Scale(Vector2f),
+    Rotation(f32),
+    Color(Color),
+}
+
+impl TweenTarget {
+    // Reads the same property from the entity, used as start value
+    fn capture<T: Transformable + EntityTrait>(&self, entity: &T) -> TweenTarget {
+        match self {
+            TweenTarget::Position(_) => TweenTarget::Position(entity.position()),
+            TweenTarget::Scale(_) => TweenTarget::Scale(entity.get_scale()),
+            TweenTarget::Rotation(_) => TweenTarget::Rotation(entity.rotation()),
+            TweenTarget::Color(_) => TweenTarget::Color(entity.color()),
+        }
+    }
+
+    fn apply<T: Transformable + EntityTrait>(&self, from: &TweenTarget, t: f32, entity: &mut T) {
+        match (from, self) {
+            (TweenTarget::Position(a), TweenTarget::Position(b)) => entity.set_position(lerp_vec(*a, *b, t)),
+            (TweenTarget::Scale(a), TweenTarget::Scale(b)) => entity.set_scale(lerp_vec(*a, *b, t)),
+            (TweenTarget::Rotation(a), TweenTarget::Rotation(b)) => entity.set_rotation(lerp(*a, *b, t)),
+            (TweenTarget::Color(a), TweenTarget::Color(b)) => entity.set_color(lerp_color(*a, *b, t)),
+            _ => {}
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_vec(a: Vector2f, b: Vector2f, t: f32) -> Vector2f {
+    Vector2f::new(lerp(a.x, b.x, t), lerp(a.y, b.y, t))
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let channel = |a: u8, b: u8| lerp(a as f32, b as f32, t).round().clamp(0.0, 255.0) as u8;
+    Color::rgba(channel(a.r, b.r), channel(a.g, b.g), channel(a.b, b.b), channel(a.a, b.a))
+}
+
+#[derive(Debug, Clone)]
+pub enum TweenKind {
+    Property {
+        entity_id: String,
+        to: TweenTarget,
+        from: Option<TweenTarget>,
+        easing: Easing,
+        last_progress: Option<f32>,
+    },
+    Delay,
+    Sequence(Vec<Tween>),
+    Parallel(Vec<Tween>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Tween {
+    pub kind: TweenKind,
+    pub duration_ms: f32,
+}
+
+impl Tween {
+    pub fn to(entity_id: &str, to: TweenTarget, duration_ms: f32, easing: Easing) -> Self {
+        Tween {
+            kind: TweenKind::Property {
+                entity_id: entity_id.to_string(),
+                to,
+                from: None,
+                easing,
+                last_progress: None,
+            },
+            duration_ms: duration_ms.max(0.0),
+        }
+    }
+
+    pub fn from_to(entity_id: &str, from: TweenTarget, to: TweenTarget, duration_ms: f32, easing: Easing) -> Self {
+        let mut tween = Tween::to(entity_id, to, duration_ms, easing);
+        if let TweenKind::Property { from: start, .. } = &mut tween.kind {
+            *start = Some(from);
+        }
+        tween
+    }
+
+    pub fn delay(duration_ms: f32) -> Self {
+        Tween {
+            kind: TweenKind::Delay,
+            duration_ms: duration_ms.max(0.0),
+        }
+    }
+
+    // Children run one after another
+    pub fn sequence(tweens: Vec<Tween>) -> Self {
+        let duration_ms = tweens.iter().map(|t| t.duration_ms).sum();
+        Tween {
+            kind: TweenKind::Sequence(tweens),
+            duration_ms,
+        }
+    }
+
+    // Children run at the same time, group lasts as long as the longest child
+    pub fn parallel(tweens: Vec<Tween>) -> Self {
+        let duration_ms = tweens.iter().map(|t| t.duration_ms).fold(0.0, f32::max);
+        Tween {
+            kind: TweenKind::Parallel(tweens),
+            duration_ms,
+        }
+    }
+
+    // Samples the tween at a local time, time before 0 means not started yet
+    fn sample<T: Transformable + EntityTrait>(&mut self, time_ms: f32, entities: &IndexMap<String, RefCell<T>>) {
+        let duration_ms = self.duration_ms;
+        match &mut self.kind {
+            TweenKind::Property { entity_id, to, from, easing, last_progress } => {
+                if time_ms < 0.0 && last_progress.is_none() {
+                    return;
+                }
+
+                let progress = if duration_ms <= 0.0 {
+                    if time_ms < 0.0 { 0.0 } else { 1.0 }
+                } else {
+                    (time_ms / duration_ms).clamp(0.0, 1.0)
+                };
+
+                // Only touch the entity when something changed, so finished tweens don't fight gameplay code
+                if *last_progress == Some(progress) {
+                    return;
+                }
+
+                let entity = match entities.get(entity_id.as_str()) {
+                    Some(entity) => entity,
+                    None => return,
+                };
+                let mut entity = entity.borrow_mut();
+
+                if from.is_none() {
+                    *from = Some(to.capture(&*entity));
+                }
+
+                to.apply(from.as_ref().unwrap(), easing.apply(progress), &mut *entity);
+                *last_progress = Some(progress);
+            }
+            TweenKind::Delay => {}
+            TweenKind::Sequence(tweens) => {
+                let mut offsets = Vec::with_capacity(tweens.len());
+                let mut offset = 0.0;
+                for tween in tweens.iter() {
+                    offsets.push(offset);
+                    offset += tween.duration_ms;
+                }
+
+                // Rewind children that haven't started yet first (last to first),
+                // so the active child is always the last one to write a property
+                for (tween, offset) in tweens.iter_mut().zip(offsets.iter()).rev() {
+                    if time_ms < *offset {
+                        tween.sample(time_ms - offset, entities);
+                    }
+                }
+                for (tween, offset) in tweens.iter_mut().zip(offsets.iter()) {
+                    if time_ms >= *offset {
+                        tween.sample(time_ms - offset, entities);
+                    }
+                }
+            }
+            TweenKind::Parallel(tweens) => {
+                for tween in tweens.iter_mut() {
+                    tween.sample(time_ms, entities);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TweenRepeat {
+    Once,
+    Times(u32),
+    Forever,
+}
+
+pub type TweenCallback<T> = fn(&mut Scene<T>, String);
+
+pub struct TweenPlayer<T>
+where
+    T: Drawable + Transformable,
+{
+    pub tween: Tween,
+    pub elapsed_ms: f32,
+    pub repeat: TweenRepeat,
+    pub yoyo: bool,
+    pub paused: bool,
+    pub on_complete: Option<TweenCallback<T>>,
+}
+
+impl<T> TweenPlayer<T>
+where
+    T: Drawable + Transformable + EntityTrait,
+{
+    pub fn new(tween: Tween) -> Self {
+        TweenPlayer {
+            tween,
+            elapsed_ms: 0.0,
+            repeat: TweenRepeat::Once,
+            yoyo: false,
+            paused: false,
+            on_complete: None,
+        }
+    }
+
+    pub fn repeat(&mut self, repeat: TweenRepeat) -> &mut Self {
+        self.repeat = repeat;
+        self
+    }
+
+    // Every other cycle plays backwards
+    pub fn yoyo(&mut self, yoyo: bool) -> &mut Self {
+        self.yoyo = yoyo;
+        self
+    }
+
+    pub fn on_complete(&mut self, callback: TweenCallback<T>) -> &mut Self {
+        self.on_complete = Some(callback);
+        self
+    }
+
+    fn cycles(&self) -> Option<u32> {
+        match self.repeat {
+            TweenRepeat::Once => Some(1),
+            TweenRepeat::Times(times) => Some(times.max(1)),
+            TweenRepeat::Forever => None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.cycles() {
+            Some(cycles) => self.elapsed_ms >= self.tween.duration_ms * cycles as f32,
+            None => false,
+        }
+    }
+
+    // Returns true when the player just finished
+    fn update(&mut self, duration: f32, entities: &IndexMap<String, RefCell<T>>) -> bool {
+        if self.paused {
+            return false;
+        }
+
+        self.elapsed_ms += duration;
+        let total = self.tween.duration_ms;
+        let finished = self.is_finished();
+
+        let (cycle, local) = if total <= 0.0 {
+            (0, 0.0)
+        } else if finished {
+            // Land exactly on the end of the last cycle
+            let cycles = self.cycles().unwrap();
+            (cycles - 1, total)
+        } else {
+            ((self.elapsed_ms / total) as u32, self.elapsed_ms % total)
+        };
+
+        let local = if self.yoyo && cycle % 2 == 1 { total - local } else { local };
+        self.tween.sample(local, entities);
+        finished
+    }
+}
+
+pub struct TweenManager<T>
+where
+    T: Drawable + Transformable,
+{
+    players: IndexMap<String, TweenPlayer<T>>,
+}
+
+impl<T> TweenManager<T>
+where
+    T: Drawable + Transformable + EntityTrait,
+{
+    pub fn new() -> Self {
+        TweenManager {
+            players: IndexMap::new(),
+        }
+    }
+
+    // Starts a tween, replacing any tween playing under the same id
+    pub fn play(&mut self, id: &str, tween: Tween) -> &mut TweenPlayer<T> {
+        self.players.insert(id.to_string(), TweenPlayer::new(tween));
+        self.players.get_mut(id).unwrap()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TweenPlayer<T>> {
+        self.players.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut TweenPlayer<T>> {
+        self.players.get_mut(id)
+    }
+
+    pub fn is_playing(&self, id: &str) -> bool {
+        self.players.contains_key(id)
+    }
+
+    pub fn set_paused(&mut self, id: &str, paused: bool) {
+        if let Some(player) = self.players.get_mut(id) {
+            player.paused = paused;
+        }
+    }
+
+    // Stops the tween where it is, completion callback is not fired
+    pub fn stop(&mut self, id: &str) {
+        self.players.shift_remove(id);
+    }
+
+    pub fn clear(&mut self) {
+        self.players.clear();
+    }
+
+    // Advances every tween and returns the completion callbacks to fire
+    pub fn update(&mut self, duration: f32, entities: &IndexMap<String, RefCell<T>>) -> Vec<(String, TweenCallback<T>)> {
+        let mut finished: Vec<String> = Vec::new();
+        for (id, player) in self.players.iter_mut() {
+            if player.update(duration, entities) {
+                finished.push(id.clone());
+            }
+        }
+
+        let mut callbacks = Vec::new();
+        for id in finished {
+            if let Some(player) = self.players.shift_remove(&id) {
+                if let Some(callback) = player.on_complete {
+                    callbacks.push((id, callback));
+                }
+            }
+        }
+        callbacks
+    }
+}
+
+impl<T> Default for TweenManager<T>
+where
+    T: Drawable + Transformable + EntityTrait,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_motor::entity::Entity;
+
+    const EASINGS: [Easing; 16] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::ElasticIn,
+        Easing::ElasticOut,
+        Easing::ElasticInOut,
+        Easing::BounceIn,
+        Easing::BounceOut,
+        Easing::BounceInOut,
+        Easing::BackIn,
+        Easing::BackOut,
+        Easing::BackInOut,
+    ];
+
+    fn no_entities() -> IndexMap<String, RefCell<Entity>> {
+        IndexMap::new()
+    }
+
+    fn done(_scene: &mut Scene<Entity>, _id: String) {}
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for easing in EASINGS {
+            assert!(easing.apply(0.0).abs() < 1e-5, "{:?} at 0 is {}", easing, easing.apply(0.0));
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-5, "{:?} at 1 is {}", easing, easing.apply(1.0));
+            // Progress outside 0..1 is clamped
+            assert_eq!(easing.apply(-1.0), easing.apply(0.0));
+            assert_eq!(easing.apply(2.0), easing.apply(1.0));
+        }
+        assert_eq!(Easing::Linear.apply(0.25), 0.25);
+        assert!(Easing::QuadIn.apply(0.5) < 0.5 && Easing::QuadOut.apply(0.5) > 0.5);
+    }
+
+    #[test]
+    fn groups_take_their_duration_from_the_children() {
+        let sequence = Tween::sequence(vec![Tween::delay(100.0), Tween::delay(250.0), Tween::delay(-5.0)]);
+        assert_eq!(sequence.duration_ms, 350.0);
+        let parallel = Tween::parallel(vec![Tween::delay(100.0), sequence, Tween::delay(200.0)]);
+        assert_eq!(parallel.duration_ms, 350.0);
+        assert_eq!(Tween::parallel(Vec::new()).duration_ms, 0.0);
+    }
+
+    #[test]
+    fn sequence_completes_after_every_child() {
+        let entities = no_entities();
+        let mut tweens: TweenManager<Entity> = TweenManager::new();
+        tweens.play("intro", Tween::sequence(vec![Tween::delay(100.0), Tween::delay(200.0)])).on_complete(done);
+
+        assert!(tweens.update(150.0, &entities).is_empty());
+        assert!(tweens.update(149.0, &entities).is_empty());
+        assert!(tweens.is_playing("intro"));
+        let callbacks = tweens.update(1.0, &entities);
+        assert_eq!(callbacks.len(), 1);
+        assert_eq!(callbacks[0].0, "intro");
+        assert!(!tweens.is_playing("intro"));
+    }
+
+    #[test]
+    fn repeats_pauses_and_stops() {
+        let entities = no_entities();
+        let mut tweens: TweenManager<Entity> = TweenManager::new();
+        tweens.play("twice", Tween::delay(100.0)).repeat(TweenRepeat::Times(2)).on_complete(done);
+        tweens.play("forever", Tween::delay(100.0)).repeat(TweenRepeat::Forever);
+        tweens.play("stopped", Tween::delay(10.0)).on_complete(done);
+        tweens.stop("stopped");
+
+        tweens.set_paused("twice", true);
+        assert!(tweens.update(500.0, &entities).is_empty());
+        assert_eq!(tweens.get("twice").unwrap().elapsed_ms, 0.0);
+        tweens.set_paused("twice", false);
+
+        assert!(tweens.update(150.0, &entities).is_empty());
+        assert_eq!(tweens.update(50.0, &entities).len(), 1);
+        assert!(!tweens.is_playing("twice"));
+        assert!(!tweens.is_playing("stopped"));
+
+        tweens.update(10_000.0, &entities);
+        assert!(tweens.is_playing("forever"));
+        assert!(!tweens.get("forever").unwrap().is_finished());
+    }
+}
@@ -0,0 +1,2 @@
+// Engine shared by the game and the tools in src/bin
+pub mod game_motor;
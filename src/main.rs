@@ -5,56 +5,73 @@ use std::{env, mem::{self, ManuallyDrop}};
 
 use sfml::{
     graphics::{glsl::Vec2, Color, FloatRect, IntRect, RenderWindow, Transformable},
-    system::Vector2,
-    window::{Event, Key, Style, VideoMode},
+    system::{Vector2, Vector2f},
+    window::{joystick::Axis, mouse, Event, Key, Style, VideoMode},
 };
-mod game_motor;
-use game_motor::{entity, prelude::*, scene};
+use motor_game::game_motor::{entity, prelude::*, scene};
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "full");
     // Define Entities
     let mut player = Entity::new("main");
     let mut goblin = Entity::new("goblin1");
-    let mut ground = Entity::new("ground");
 
 
 
     // Define Scene
     let mut scn = Scene::new("main");
-    // Load Textures
-    scn.load_texture("player", "assets/Warrior_Yellow.png");
-    scn.load_texture(
-        "trch_goblin",
-        "assets/Torch_Blue.png",
-    );
+    // Load Textures. The player sheet stays a plain texture so edits to it are hot reloaded,
+    // atlas pages have no file to watch.
+    scn.load_texture("player", "assets/Warrior_Yellow.png").ok();
+    // The other sheets share atlas pages
+    let mut units = AtlasBuilder::new("units", 2048, 2048);
+    for (id, path) in [
+        ("trch_goblin", "assets/Torch_Blue.png"),
+        ("expl", "assets/Explosions.png"),
+    ] {
+        if let Err(error) = units.add(id, path, &scn.assets()) {
+            println!("Failed to add {} to atlas: {}", id, error);
+        }
+    }
+    if let Err(error) = units.build(&mut scn.assets_mut()) {
+        println!("Failed to build atlas: {}", error);
+    }
 
     scn.load_texture_custom("ground", "assets/Water.png", |mut tx| {
         tx.set_repeated(true);
         tx.set_smooth(true);
         tx
-    });
+    }).ok();
 
+    // Missing assets don't stop the game, animations using them show the empty texture
+    for (id, error) in scn.assets().failed_assets() {
+        println!("Failed to load {}: {}", id, error);
+    }
 
-    scn.load_texture("expl", "assets/Explosions.png");
 
+    scn.input = match InputMap::load("input.cfg") {
+        Ok(input) => input,
+        Err(InputConfigError::Io(_)) => default_bindings(),
+        Err(error) => {
+            println!("{}, using default bindings", error);
+            default_bindings()
+        }
+    };
 
     scn.set_gravity(Vec2::new(0.0, 0.3));
     // Create Animations
-    let p_ridle = Animation::new_loop("player", 100.0, (192, 192), Some((0, 5)));
-    let p_rwalk = Animation::new_loop("player", 100.0, (192, 192), Some((6, 11)));
-    let p_rattack1 = Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((12, 17)));
-    let p_rattack2 = Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((18, 23)));
-    let p_fattack1 = Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((24, 29)));
-    let p_fattack2 = Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((30, 35)));
-
-    let g_idle = Animation::new_loop("trch_goblin", 100.0, (192, 192), Some((0, 6)));
-    let g_walk = Animation::new_loop("trch_goblin", 100.0, (192, 192), Some((7, 12)));
-    let g_attack1 = Animation::new_once("g_idle", "trch_goblin", 100.0, (192, 192), Some((13, 18)));
+    let p_ridle = in_atlas(&scn, "player", Animation::new_loop("player", 100.0, (192, 192), Some((0, 5))));
+    let p_rwalk = in_atlas(&scn, "player", Animation::new_loop("player", 100.0, (192, 192), Some((6, 11))));
+    let p_rattack1 = in_atlas(&scn, "player", Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((12, 17))));
+    let p_rattack2 = in_atlas(&scn, "player", Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((18, 23))));
+    let p_fattack1 = in_atlas(&scn, "player", Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((24, 29))));
+    let p_fattack2 = in_atlas(&scn, "player", Animation::new_once("p_ridle", "player", 100.0, (192, 192), Some((30, 35))));
 
-    let e_explode = Animation::new_once("deleted", "expl", 100.0, (192, 192), Some((0, 8)));
+    let g_idle = in_atlas(&scn, "trch_goblin", Animation::new_loop("trch_goblin", 100.0, (192, 192), Some((0, 6))));
+    let g_walk = in_atlas(&scn, "trch_goblin", Animation::new_loop("trch_goblin", 100.0, (192, 192), Some((7, 12))));
+    let g_attack1 = in_atlas(&scn, "trch_goblin", Animation::new_once("g_idle", "trch_goblin", 100.0, (192, 192), Some((13, 18))));
 
-    let g_water = Animation::new_static("ground");
+    let e_explode = in_atlas(&scn, "expl", Animation::new_once("deleted", "expl", 100.0, (192, 192), Some((0, 8))));
 
     // Set physics properties
     let def = PhysicalProperties::default();
@@ -80,11 +97,6 @@ fn main() {
         ..def.clone()
     });
 
-    ground.set_physics(PhysicalProperties {
-        static_object: true,
-        collision_type: CollisionType::Rectangular  ,
-        ..def.clone()
-    });
 
 
 
@@ -101,9 +113,25 @@ fn main() {
     goblin.add_animation("g_attack1", g_attack1);
     goblin.add_animation("explode", e_explode);
 
-    ground.add_animation("water", g_water);
+    // Ground is a solid tile layer, drawn under the entities
+    let mut ground = Tilemap::new(24, 3, 64, 64);
+    ground.position = Vector2::new(150.0, 450.0);
+    ground.add_tileset(Tileset::new("water", "ground", 1, 64, 64, 1, 1));
+    if let Some(water) = ground.add_layer(TileLayer::new("water", 24, 3)) {
+        ground.fill(water, IntRect::new(0, 0, 24, 3), 1);
+        ground.set_layer_solid(water, true);
+    }
+    scn.add_tilemap("ground", ground);
+
+    // Darker water far behind the level, drifting by itself
+    scn.add_parallax_layer(
+        ParallaxLayer::new("far_water", "ground", 0.3)
+            .with_repeat(true, true)
+            .with_scroll_speed(-20.0, 0.0)
+            .with_color(Color::rgb(90, 90, 140)),
+    );
+
     // Add Entities to Scene
-    scn.add_entity(ground);
     scn.add_entity(player);
     scn.add_entity(goblin);
     // Event Handlers
@@ -113,19 +141,53 @@ fn main() {
     scn.on_start = |scn| {
         scn.entity("goblin1").borrow_mut().set_position(Vector2::new(400.0, 50.0));
         scn.entity("main").borrow_mut().set_position(Vec2::new(400.0, 300.0));
-        scn.entity("ground").borrow_mut().set_texture_rect(IntRect::new(0, 0, 1500, 180));
-        scn.entity("ground").borrow_mut().set_position(Vector2::new(150.0, 450.0));
-        
+
+        // Ground is 1536 wide, the camera keeps the player in view inside the level
+        scn.camera.follow("main");
+        scn.camera.set_dead_zone(200.0, 120.0);
+        scn.camera.set_smoothing(150.0);
+        scn.camera.set_bounds(FloatRect::new(0.0, 0.0, 1800.0, 700.0));
+        scn.camera.fade_in(Color::BLACK, 600.0);
+
+        // Toggled with F5
+        for pass in [PostPass::color_grading(0.0, 1.1, 1.2, Color::WHITE), PostPass::vignette(0.6, 0.75)] {
+            if let Err(error) = scn.add_post_pass(pass) {
+                println!("Failed to add post pass: {}", error);
+            }
+        }
+
     };
 
     // Define Game
     let mut game = Game::new("Game", Style::CLOSE, scn);
     // let mut game = Game::new("Game", (800, 600), Style::CLOSE, scn);
 
+    // --record <file> saves the input on exit, --replay <file> plays it back with the same fixed delta
+    let args: Vec<String> = env::args().collect();
+    let arg_value = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    if let Some(path) = arg_value("--record") {
+        game.set_fixed_delta(Some(16.0));
+        game.record_input(path);
+    }
+    if let Some(path) = arg_value("--replay") {
+        match InputRecording::load(path) {
+            Ok(recording) => game.replay_input(&recording),
+            Err(error) => println!("{}", error),
+        }
+    }
+
     // Start Game
     game.run((900, 600));
 }
 
+// Animations keep the plain texture id if the image didn't make it into the atlas
+fn in_atlas(scene: &Scene<Entity>, region: &str, animation: Animation) -> Animation {
+    match scene.assets().atlas_region(region) {
+        Some(region) => animation.with_region(&region),
+        None => animation,
+    }
+}
+
 fn on_collision(scene: &mut Scene<Entity>, e1: String, e2: String, inter: FloatRect) {
     let touch_area = if  inter.width < inter.height  {
         inter.width
@@ -142,10 +204,17 @@ fn on_collision(scene: &mut Scene<Entity>, e1: String, e2: String, inter: FloatR
             .current_animation()
             .contains("attack")
         {
+            let exploding = scene.entity(e2.as_str()).borrow().current_animation() == "explode";
             scene
                 .entity(e2.as_str())
                 .borrow_mut()
                 .set_current_animation("explode");
+            // Only once, collisions keep coming while the explosion plays
+            if !exploding {
+                scene.camera.add_trauma(0.6);
+                scene.camera.flash(Color::WHITE, 120.0);
+                explosion_debris(scene, e2.as_str());
+            }
         }
     }
 
@@ -154,40 +223,99 @@ fn on_collision(scene: &mut Scene<Entity>, e1: String, e2: String, inter: FloatR
     // }
 }
 
+// Small pieces of the first explosion frame thrown around the entity
+fn explosion_debris(scene: &mut Scene<Entity>, id: &str) {
+    let bounds = scene.entity(id).borrow().get_sprite().global_bounds();
+    let center = Vector2f::new(bounds.left + bounds.width / 2.0, bounds.top + bounds.height / 2.0);
+    let mut debris = ParticleEmitter::one_shot(&format!("{}_debris", id), EmitterAnchor::World(center))
+        .with_lifetime(400.0, 900.0)
+        .with_velocity(150.0, 350.0, 180.0, 360.0)
+        .with_gravity(0.0, 600.0)
+        .with_spin(-360.0, 360.0)
+        .with_colors(Color::rgb(255, 200, 120), Color::rgba(120, 40, 0, 0))
+        .with_sizes(24.0, 4.0);
+    if let Some(region) = scene.assets().atlas_region("expl") {
+        let frame = IntRect::new(region.rect.left + 64, region.rect.top + 64, 64, 64);
+        debris = debris.with_texture(&region.texture_id, vec![frame]);
+    }
+    scene.add_emitter(debris);
+    scene.burst(&format!("{}_debris", id), 60);
+}
+
 fn on_update(scene: &mut Scene<Entity>) {
     if let Some(goblin) = scene.try_entity("goblin1") {
-        if scene.check_key(Key::Numpad8) {
+        if scene.is_action_pressed("goblin_up") {
             goblin.borrow_mut().move_up();
         }
-        if scene.check_key(Key::Numpad5) {
+        if scene.is_action_pressed("goblin_down") {
             goblin.borrow_mut().move_down();
         }
-        if scene.check_key(Key::Numpad4) {
+        if scene.is_action_pressed("goblin_left") {
             goblin.borrow_mut().move_left();
         }
-        if scene.check_key(Key::Numpad6) {
+        if scene.is_action_pressed("goblin_right") {
             goblin.borrow_mut().move_right();
         }
     }
 
-    if scene.check_key(Key::W) {
+    if scene.is_action_pressed("move_up") {
         scene.entity("main").borrow_mut().move_up();
     }
 
-    if scene.check_key(Key::S) {
+    if scene.is_action_pressed("move_down") {
         scene.entity("main").borrow_mut().move_down();
     }
-    if scene.check_key(Key::A) {
+    if scene.is_action_pressed("move_left") {
         scene.entity("main").borrow_mut().move_left();
     }
-    if scene.check_key(Key::D) {
+    if scene.is_action_pressed("move_right") {
         scene.entity("main").borrow_mut().move_right();
     }
 
+    let wheel = scene.mouse_wheel_delta();
+    if wheel != 0.0 {
+        scene.camera.zoom_by(1.0 + wheel * 0.1);
+    }
+
+    // Combo first, its last press is an attack press as well
+    if scene.check_combo(&["move_down", "move_right", "attack"]) {
+        scene.entity("main").borrow_mut().set_current_animation("p_fattack1");
+    } else if scene.is_action_just_pressed("attack") {
+        scene.entity("main").borrow_mut().set_current_animation("p_rattack1");
+    } else if scene.is_action_just_pressed("attack2") {
+        scene.entity("main").borrow_mut().set_current_animation("p_rattack2");
+    }
+
     
 
 }
 
+// Used when there is no input.cfg next to the game
+fn default_bindings() -> InputMap {
+    let mut input = InputMap::new();
+    input.bind("move_up", InputBinding::Key(Key::W));
+    input.bind("move_down", InputBinding::Key(Key::S));
+    input.bind("move_left", InputBinding::Key(Key::A));
+    input.bind("move_right", InputBinding::Key(Key::D));
+    input.bind("move_up", InputBinding::JoystickAxis { joystick: 0, axis: Axis::Y, direction: -1.0 });
+    input.bind("move_down", InputBinding::JoystickAxis { joystick: 0, axis: Axis::Y, direction: 1.0 });
+    input.bind("move_left", InputBinding::JoystickAxis { joystick: 0, axis: Axis::X, direction: -1.0 });
+    input.bind("move_right", InputBinding::JoystickAxis { joystick: 0, axis: Axis::X, direction: 1.0 });
+    input.bind("attack", InputBinding::Key(Key::Space));
+    input.bind("attack", InputBinding::Mouse(mouse::Button::Left));
+    input.bind("attack", InputBinding::JoystickButton { joystick: 0, button: 0 });
+    input.bind("attack2", InputBinding::Key(Key::LShift));
+    input.bind("attack2", InputBinding::Mouse(mouse::Button::Right));
+    input.bind("attack2", InputBinding::JoystickButton { joystick: 0, button: 1 });
+
+    input.bind("goblin_up", InputBinding::Key(Key::Numpad8));
+    input.bind("goblin_down", InputBinding::Key(Key::Numpad5));
+    input.bind("goblin_left", InputBinding::Key(Key::Numpad4));
+    input.bind("goblin_right", InputBinding::Key(Key::Numpad6));
+    input.bind("goblin_attack", InputBinding::Key(Key::Numpad0));
+    input
+}
+
 fn on_keystate_changed(scene: &mut Scene<Entity>, key_state: KeyState) {
     if let Some(goblin) = scene.try_entity("goblin1") {
         match key_state {
@@ -200,12 +328,14 @@ fn on_keystate_changed(scene: &mut Scene<Entity>, key_state: KeyState) {
                     .set_current_animation("g_walk");
             }
             KeyState::Pressed(Key::Numpad4) => {
-                goblin
-                    .borrow_mut()
-                    .set_current_animation_flipped("g_walk", true, false);
+                let mut goblin = goblin.borrow_mut();
+                goblin.set_flip(true, false);
+                goblin.set_current_animation("g_walk");
             }
             KeyState::Pressed(Key::Numpad6) => {
-                goblin.borrow_mut().set_current_animation("g_walk");
+                let mut goblin = goblin.borrow_mut();
+                goblin.set_flip(false, false);
+                goblin.set_current_animation("g_walk");
             }
             KeyState::Released(Key::Numpad8) => {
                 goblin.borrow_mut().set_current_animation("g_idle");
@@ -242,16 +372,14 @@ fn on_keystate_changed(scene: &mut Scene<Entity>, key_state: KeyState) {
                     .set_current_animation("p_rwalk");
             }
             KeyState::Pressed(Key::A) => {
-                scene
-                    .entity("main")
-                    .borrow_mut()
-                    .set_current_animation_flipped("p_rwalk", true, false);
+                let mut player = scene.entity("main").borrow_mut();
+                player.set_flip(true, false);
+                player.set_current_animation("p_rwalk");
             }
             KeyState::Pressed(Key::D) => {
-                scene
-                    .entity("main")
-                    .borrow_mut()
-                    .set_current_animation("p_rwalk");
+                let mut player = scene.entity("main").borrow_mut();
+                player.set_flip(false, false);
+                player.set_current_animation("p_rwalk");
             }
 
             // ------------------------------------------------
@@ -280,18 +408,25 @@ fn on_keystate_changed(scene: &mut Scene<Entity>, key_state: KeyState) {
                     .set_current_animation("p_ridle");
             }
 
-            // ------------------------------------------------
-            KeyState::Pressed(Key::Space) => {
-                scene
-                    .entity("main")
-                    .borrow_mut()
-                    .set_current_animation("p_rattack1");
+            KeyState::Pressed(Key::F3) => {
+                let stats = scene.render_stats();
+                println!(
+                    "draw calls: {}, batches: {}, batched sprites: {}, particles: {}",
+                    stats.draw_calls,
+                    stats.batches,
+                    stats.batched_sprites,
+                    scene.particle_count()
+                );
             }
-            KeyState::Pressed(Key::LShift) => {
-                scene
-                    .entity("main")
-                    .borrow_mut()
-                    .set_current_animation("p_rattack2");
+
+            KeyState::Pressed(Key::F4) => {
+                scene.batching = !scene.batching;
+                println!("batching: {}", scene.batching);
+            }
+
+            KeyState::Pressed(Key::F5) => {
+                scene.post_process.enabled = !scene.post_process.enabled;
+                println!("post processing: {}", scene.post_process.enabled);
             }
 
             _ => {}